@@ -321,6 +321,14 @@ impl<D: dbc::Proof> ClientBundle<D> {
             .expect("same size as input map");
         Ok(true)
     }
+
+    /// Applies `f` to each of the known transitions. The function may modify
+    /// transitions as long as it doesn't change their ids.
+    pub fn update_transitions(&mut self, mut f: impl FnMut(OpId, &mut Transition)) {
+        for (opid, transition) in self.bundle.known_transitions.keyed_values_mut() {
+            f(*opid, transition);
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -19,7 +19,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::ops::Deref;
@@ -33,19 +33,22 @@ use amplify::{ByteArray, Bytes32};
 use armor::{ArmorHeader, AsciiArmor, StrictArmor, StrictArmorError};
 use baid64::{Baid64ParseError, DisplayBaid64, FromBaid64Str};
 use commit_verify::{CommitEncode, CommitEngine, CommitId, CommitmentId, DigestExt, Sha256};
-use rgb::validation::{ResolveWitness, Validator, Validity, Warning, CONSIGNMENT_MAX_LIBS};
+use rgb::validation::{
+    Failure, ResolveWitness, Validator, Validity, Warning, CONSIGNMENT_MAX_LIBS,
+};
 use rgb::{
-    impl_serde_baid64, validation, AttachId, BundleId, ContractId, Extension, Genesis, GraphSeal,
-    Operation, Schema, SchemaId, XChain,
+    impl_serde_baid64, validation, Assignments, AttachId, BundleId, ContractId, ExposedSeal,
+    Extension, Genesis, GraphSeal, OpId, Operation, Schema, SchemaId, TypedAssigns, XChain,
 };
 use rgbcore::validation::ConsignmentApi;
 use strict_encoding::{StrictDeserialize, StrictDumb, StrictSerialize};
 use strict_types::TypeSystem;
 
+use super::util::validate_sigs;
 use super::{
-    ContainerVer, ContentId, ContentSigs, IndexedConsignment, Supplement, WitnessBundle,
-    ASCII_ARMOR_CONSIGNMENT_TYPE, ASCII_ARMOR_CONTRACT, ASCII_ARMOR_IFACE, ASCII_ARMOR_SCHEMA,
-    ASCII_ARMOR_TERMINAL, ASCII_ARMOR_VERSION,
+    ContainerVer, ContentId, ContentSigs, IndexedConsignment, SigValidator, Supplement,
+    WitnessBundle, ASCII_ARMOR_CONSIGNMENT_TYPE, ASCII_ARMOR_CONTRACT, ASCII_ARMOR_IFACE,
+    ASCII_ARMOR_SCHEMA, ASCII_ARMOR_TERMINAL, ASCII_ARMOR_VERSION,
 };
 use crate::interface::{Iface, IfaceImpl};
 use crate::persistence::{MemContract, MemContractState};
@@ -283,6 +286,50 @@ impl<const TRANSFER: bool> Consignment<TRANSFER> {
     #[inline]
     pub fn consignment_id(&self) -> ConsignmentId { self.commit_id() }
 
+    /// Collects ids of the operations assigning any revealed state, together
+    /// with all their ancestors present in the consignment.
+    fn revealed_history(&self) -> BTreeSet<OpId> {
+        let mut prev_ids = BTreeMap::<OpId, Vec<OpId>>::new();
+        let mut history = BTreeSet::new();
+        if has_revealed_state(&self.genesis.assignments) {
+            history.insert(self.genesis.id());
+        }
+        for extension in &self.extensions {
+            let opid = extension.id();
+            prev_ids.insert(opid, extension.redeemed.values().copied().collect());
+            if has_revealed_state(&extension.assignments) {
+                history.insert(opid);
+            }
+        }
+        for transition in self
+            .bundled_witnesses()
+            .flat_map(WitnessBundle::known_transitions)
+        {
+            let opid = transition.id();
+            prev_ids.insert(
+                opid,
+                transition
+                    .inputs
+                    .iter()
+                    .map(|input| input.prev_out.op)
+                    .collect(),
+            );
+            if has_revealed_state(&transition.assignments) {
+                history.insert(opid);
+            }
+        }
+
+        let mut queue = history.iter().copied().collect::<Vec<_>>();
+        while let Some(opid) = queue.pop() {
+            for prev_id in prev_ids.get(&opid).into_iter().flatten() {
+                if history.insert(*prev_id) {
+                    queue.push(*prev_id);
+                }
+            }
+        }
+        history
+    }
+
     #[inline]
     pub fn schema_id(&self) -> SchemaId { self.schema.schema_id() }
 
@@ -323,10 +370,9 @@ impl<const TRANSFER: bool> Consignment<TRANSFER> {
     }
 
     pub fn validate(
-        self,
+        mut self,
         resolver: &impl ResolveWitness,
-        // TODO: Add sig validator
-        //_: &impl SigValidator,
+        sig_validator: &impl SigValidator,
         testnet: bool,
     ) -> Result<ValidConsignment<TRANSFER>, (validation::Status, Consignment<TRANSFER>)> {
         let index = IndexedConsignment::new(&self);
@@ -337,8 +383,24 @@ impl<const TRANSFER: bool> Consignment<TRANSFER> {
             (&self.schema, self.contract_id()),
         );
 
+        // Concealed transfers conceal amounts assigned by the operations which are not the part
+        // of the history of any revealed state. Until range proofs are supported by the
+        // consensus library these amounts can't be checked; since no revealed state depends on
+        // them, they are reported as uncheckable instead of failing the validation.
+        let history = self.revealed_history();
+        let (uncheckable, failures) = status.failures.into_iter().partition::<Vec<_>, _>(
+            |failure| matches!(failure, Failure::BulletproofsInvalid(opid, ..) if !history.contains(opid)),
+        );
+        status.failures = failures;
+
         let validity = status.validity();
 
+        for failure in uncheckable {
+            if let Failure::BulletproofsInvalid(opid, ty, _) = failure {
+                status.add_warning(Warning::UncheckableConfidentialState(opid, ty));
+            }
+        }
+
         if self.transfer != TRANSFER {
             status.add_warning(Warning::Custom(s!("invalid consignment type")));
         }
@@ -363,10 +425,11 @@ impl<const TRANSFER: bool> Consignment<TRANSFER> {
             }
         }
         // TODO: check attach ids from data containers are present in operations
-        // TODO: validate sigs and remove untrusted
         // TODO: Check that all extensions present in the consignment are used by state
         // transitions
 
+        self.signatures = validate_sigs(self.signatures, sig_validator, &mut status);
+
         if validity != Validity::Valid {
             Err((status, self))
         } else {
@@ -378,6 +441,15 @@ impl<const TRANSFER: bool> Consignment<TRANSFER> {
     }
 }
 
+fn has_revealed_state<Seal: ExposedSeal>(assignments: &Assignments<Seal>) -> bool {
+    assignments.values().any(|assigns| match assigns {
+        TypedAssigns::Declarative(vec) => vec.iter().any(|a| a.as_revealed_state().is_some()),
+        TypedAssigns::Fungible(vec) => vec.iter().any(|a| a.as_revealed_state().is_some()),
+        TypedAssigns::Structured(vec) => vec.iter().any(|a| a.as_revealed_state().is_some()),
+        TypedAssigns::Attachment(vec) => vec.iter().any(|a| a.as_revealed_state().is_some()),
+    })
+}
+
 impl<const TRANSFER: bool> StrictArmor for Consignment<TRANSFER> {
     type Id = ConsignmentId;
     const PLATE_TITLE: &'static str = "RGB CONSIGNMENT";
@@ -19,5 +19,174 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-// TODO: Implement disclosures
-pub struct Disclosure;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use amplify::confinement::{LargeOrdMap, LargeOrdSet, TinyOrdMap};
+use amplify::{ByteArray, Bytes32};
+use armor::{ArmorHeader, AsciiArmor, StrictArmor};
+use baid64::{Baid64ParseError, DisplayBaid64, FromBaid64Str};
+use commit_verify::{CommitEncode, CommitEngine, CommitId, CommitmentId, DigestExt, Sha256};
+use rgb::{validation, BundleId, ContractId, Extension, Operation, TransitionBundle};
+use strict_encoding::{StrictDeserialize, StrictSerialize};
+
+use super::util::validate_sigs;
+use super::{
+    ContainerVer, ContentId, ContentSigs, SigValidator, ASCII_ARMOR_BUNDLE, ASCII_ARMOR_CONTRACT,
+    ASCII_ARMOR_VERSION,
+};
+use crate::LIB_NAME_RGB_STD;
+
+/// Disclosure identifier.
+///
+/// Disclosure identifier commits to all data provided within the disclosure,
+/// including the revealed parts of the bundles and state extensions.
+#[derive(Wrapper, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, From)]
+#[wrapper(Deref, BorrowSlice, Hex, Index, RangeOps)]
+#[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
+#[strict_type(lib = LIB_NAME_RGB_STD)]
+pub struct DisclosureId(
+    #[from]
+    #[from([u8; 32])]
+    Bytes32,
+);
+
+impl From<Sha256> for DisclosureId {
+    fn from(hasher: Sha256) -> Self { hasher.finish().into() }
+}
+
+impl CommitmentId for DisclosureId {
+    const TAG: &'static str = "urn:lnp-bp:rgb:disclosure#2024-08-05";
+}
+
+impl DisplayBaid64 for DisclosureId {
+    const HRI: &'static str = "rgb:dsc";
+    const CHUNKING: bool = true;
+    const PREFIX: bool = true;
+    const EMBED_CHECKSUM: bool = false;
+    const MNEMONIC: bool = true;
+    fn to_baid64_payload(&self) -> [u8; 32] { self.to_byte_array() }
+}
+impl FromBaid64Str for DisclosureId {}
+impl FromStr for DisclosureId {
+    type Err = Baid64ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::from_baid64_str(s) }
+}
+impl Display for DisclosureId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { self.fmt_baid64(f) }
+}
+
+impl_serde_baid64!(DisclosureId);
+
+impl DisclosureId {
+    pub const fn from_array(id: [u8; 32]) -> Self { Self(Bytes32::from_array(id)) }
+}
+
+/// Disclosure reveals concealed state, seals and state transitions for the
+/// operations which are already known to the receiving party.
+///
+/// Unlike consignments, disclosures do not contain contract genesis, anchors or
+/// witness transactions, and may include operations from multiple (possibly
+/// unrelated) contracts. They can't be used to extend the contract history and
+/// are applicable only to the bundles and state extensions which were already
+/// consumed by the stock.
+#[derive(Clone, Default, Debug, Display, PartialEq)]
+#[display(AsciiArmor::to_ascii_armored_string)]
+#[derive(StrictType, StrictEncode, StrictDecode)]
+#[strict_type(lib = LIB_NAME_RGB_STD)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct Disclosure {
+    /// Version.
+    pub version: ContainerVer,
+
+    /// State transition bundles with revealed state, seals or transitions.
+    pub bundles: LargeOrdMap<BundleId, TransitionBundle>,
+
+    /// State extensions with revealed state or seals.
+    pub extensions: LargeOrdSet<Extension>,
+
+    /// Signatures on the pieces of content which are the part of the
+    /// disclosure.
+    pub signatures: TinyOrdMap<ContentId, ContentSigs>,
+}
+
+impl StrictSerialize for Disclosure {}
+impl StrictDeserialize for Disclosure {}
+
+impl CommitEncode for Disclosure {
+    type CommitmentId = DisclosureId;
+
+    fn commit_encode(&self, e: &mut CommitEngine) {
+        e.commit_to_serialized(&self.version);
+
+        e.commit_to_set(&LargeOrdSet::from_iter_checked(
+            self.bundles.values().map(TransitionBundle::disclose_hash),
+        ));
+        e.commit_to_set(&LargeOrdSet::from_iter_checked(
+            self.extensions.iter().map(Extension::disclose_hash),
+        ));
+
+        e.commit_to_map(&self.signatures);
+    }
+}
+
+impl Disclosure {
+    #[inline]
+    pub fn disclosure_id(&self) -> DisclosureId { self.commit_id() }
+
+    /// Lists contracts which are referenced by the state extensions from the
+    /// disclosure.
+    ///
+    /// NB: Transition bundles do not commit to the contract id, thus contracts
+    /// which are affected only by the disclosed bundles are not listed.
+    pub fn extension_contracts(&self) -> impl Iterator<Item = ContractId> + '_ {
+        self.extensions.iter().map(|ext| ext.contract_id)
+    }
+
+    /// Validates signatures provided within the disclosure, removing the ones
+    /// which are invalid.
+    ///
+    /// Returns validation status reporting all the removed signatures.
+    pub fn validate_sigs(&mut self, sig_validator: &impl SigValidator) -> validation::Status {
+        let mut status = validation::Status::new();
+        let signatures = std::mem::take(&mut self.signatures);
+        self.signatures = validate_sigs(signatures, sig_validator, &mut status);
+        status
+    }
+}
+
+impl StrictArmor for Disclosure {
+    type Id = DisclosureId;
+    const PLATE_TITLE: &'static str = "RGB DISCLOSURE";
+
+    fn armor_id(&self) -> Self::Id { self.disclosure_id() }
+    fn armor_headers(&self) -> Vec<ArmorHeader> {
+        let mut headers =
+            vec![ArmorHeader::new(ASCII_ARMOR_VERSION, format!("{:#}", self.version))];
+        if !self.bundles.is_empty() {
+            headers.push(ArmorHeader::with(
+                ASCII_ARMOR_BUNDLE,
+                self.bundles.keys().map(BundleId::to_string),
+            ));
+        }
+        let contracts = self.extension_contracts().collect::<BTreeSet<_>>();
+        if !contracts.is_empty() {
+            headers.push(ArmorHeader::with(
+                ASCII_ARMOR_CONTRACT,
+                contracts.iter().map(ContractId::to_string),
+            ));
+        }
+        headers
+    }
+}
+
+impl FromStr for Disclosure {
+    type Err = armor::StrictArmorError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::from_ascii_armored_str(s) }
+}
@@ -26,7 +26,7 @@ use amplify::confinement::U32 as FILE_MAX_LEN;
 use armor::{AsciiArmor, StrictArmor};
 use strict_encoding::{StreamReader, StreamWriter, StrictDecode, StrictEncode};
 
-use crate::containers::{Contract, Kit, Transfer};
+use crate::containers::{Contract, Disclosure, Kit, Transfer};
 
 const RGB_PREFIX: [u8; 4] = *b"RGB\x00";
 const MAGIC_LEN: usize = 3;
@@ -113,7 +113,10 @@ impl FileContent for Transfer {
     const MAGIC: [u8; MAGIC_LEN] = *b"TFR";
 }
 
-// TODO: Add disclosure
+impl FileContent for Disclosure {
+    const MAGIC: [u8; MAGIC_LEN] = *b"DIS";
+}
+
 // TODO: Add batch and fascia
 
 #[derive(Clone, Debug, From)]
@@ -131,7 +134,9 @@ pub enum UniversalFile {
 
     #[from]
     Transfer(Transfer),
-    // TODO: Add disclosure
+
+    #[from]
+    Disclosure(Disclosure),
     // TODO: Add batch and fascia
 }
 
@@ -149,6 +154,7 @@ impl UniversalFile {
             x if x == Kit::MAGIC => Kit::strict_read(&mut reader)?.into(),
             x if x == Contract::MAGIC => Contract::strict_read(&mut reader)?.into(),
             x if x == Transfer::MAGIC => Transfer::strict_read(&mut reader)?.into(),
+            x if x == Disclosure::MAGIC => Disclosure::strict_read(&mut reader)?.into(),
             _ => return Err(LoadError::InvalidMagic),
         })
     }
@@ -159,6 +165,7 @@ impl UniversalFile {
             UniversalFile::Kit(_) => Kit::MAGIC,
             UniversalFile::Contract(_) => Contract::MAGIC,
             UniversalFile::Transfer(_) => Transfer::MAGIC,
+            UniversalFile::Disclosure(_) => Disclosure::MAGIC,
         };
         writer.write_all(&magic)?;
 
@@ -168,6 +175,7 @@ impl UniversalFile {
             UniversalFile::Kit(content) => content.strict_write(writer),
             UniversalFile::Contract(content) => content.strict_write(writer),
             UniversalFile::Transfer(content) => content.strict_write(writer),
+            UniversalFile::Disclosure(content) => content.strict_write(writer),
         }
     }
 
@@ -190,6 +198,7 @@ impl Display for UniversalFile {
             UniversalFile::Kit(content) => Display::fmt(&content.display_ascii_armored(), f),
             UniversalFile::Contract(content) => Display::fmt(&content.display_ascii_armored(), f),
             UniversalFile::Transfer(content) => Display::fmt(&content.display_ascii_armored(), f),
+            UniversalFile::Disclosure(content) => Display::fmt(&content.display_ascii_armored(), f),
         }
     }
 }
@@ -439,4 +448,26 @@ mod test {
             Transfer::load_armored(ARMORED_TRANSFER_PATH).expect("fail to export armored transfer");
         assert_eq!(transfer, default_transfer, "armored transfer roudtrip does not work");
     }
+
+    #[test]
+    fn disclosure_save_load_round_trip() {
+        let default_disclosure = Disclosure::default();
+        let mut data = vec![];
+        default_disclosure
+            .save(&mut data)
+            .expect("fail to export disclosure");
+
+        let disclosure = Disclosure::load(data.as_slice()).expect("fail to load disclosure");
+        assert_eq!(disclosure, default_disclosure, "disclosure roudtrip does not work");
+
+        let file = UniversalFile::load(data.as_slice()).expect("fail to load universal file");
+        assert!(
+            matches!(&file, UniversalFile::Disclosure(d) if d == &default_disclosure),
+            "universal file is not a disclosure"
+        );
+
+        let disclosure = Disclosure::from_str(&default_disclosure.to_string())
+            .expect("fail to parse armored disclosure");
+        assert_eq!(disclosure, default_disclosure, "armored disclosure roudtrip does not work");
+    }
 }
@@ -19,27 +19,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::ops::Deref;
 use std::str::FromStr;
 
-use aluvm::library::Lib;
+use aluvm::library::{Lib, LibId};
 use amplify::confinement::{SmallOrdSet, TinyOrdMap, TinyOrdSet};
 use amplify::{ByteArray, Bytes32};
 use armor::{ArmorHeader, AsciiArmor, StrictArmor};
 use baid64::{Baid64ParseError, DisplayBaid64, FromBaid64Str};
 use commit_verify::{CommitEncode, CommitEngine, CommitId, CommitmentId, DigestExt, Sha256};
-use rgb::{validation, Schema};
+use rgb::{validation, Schema, SchemaId};
 use strict_encoding::{StrictDeserialize, StrictSerialize};
 use strict_types::TypeSystem;
 
+use super::util::validate_sigs;
 use super::{
     ContentRef, Supplement, ASCII_ARMOR_IFACE, ASCII_ARMOR_IIMPL, ASCII_ARMOR_SCHEMA,
     ASCII_ARMOR_SCRIPT, ASCII_ARMOR_TYPE_SYSTEM, ASCII_ARMOR_VERSION,
 };
-use crate::containers::{ContainerVer, ContentId, ContentSigs};
-use crate::interface::{Iface, IfaceImpl};
+use crate::containers::{ContainerVer, ContentId, ContentSigs, SigValidator};
+use crate::interface::{
+    Iface, IfaceId, IfaceImpl, IfaceInconsistency, IfaceTypeError, ImplId, ImplInconsistency,
+    InheritanceError,
+};
+use crate::persistence::SchemaIfaces;
 use crate::LIB_NAME_RGB_STD;
 
 /// Kit identifier.
@@ -175,17 +181,148 @@ impl Kit {
     #[inline]
     pub fn kit_id(&self) -> KitId { self.commit_id() }
 
+    /// Validates the kit integrity and signatures.
+    ///
+    /// Checks interfaces (including inheritance from the parent interfaces
+    /// present in the kit), interface implementations and schemata against
+    /// each other and the kit type system, and that all script libraries used
+    /// by the schemata are present. Schemata are checked against the types
+    /// required by the interfaces they implement, including the interfaces
+    /// inherited by them. Any failure makes the kit invalid; all
+    /// detected failures are returned together with the kit.
+    ///
+    /// Invalid signatures are removed from the kit and reported as warnings in
+    /// the validation status of the returned [`ValidKit`].
     pub fn validate(
-        self,
-        // TODO: Add sig validator
-        //_: &impl SigValidator,
-    ) -> Result<ValidKit, (validation::Status, Kit)> {
-        let status = validation::Status::new();
-        // TODO:
-        //  - Verify integrity for each interface
-        //  - Verify implementations against interfaces
-        //  - Check schema integrity
-        //  - Validate content sigs and remove untrusted ones
+        mut self,
+        sig_validator: &impl SigValidator,
+    ) -> Result<ValidKit, (Vec<KitFailure>, Kit)> {
+        let mut failures = vec![];
+        let mut status = validation::Status::new();
+
+        let ifaces = self
+            .ifaces
+            .iter()
+            .map(|iface| (iface.iface_id(), iface))
+            .collect::<BTreeMap<_, _>>();
+        for (iface_id, iface) in &ifaces {
+            let iface_id = *iface_id;
+            if let Err(errors) = iface.check() {
+                failures.extend(
+                    errors
+                        .into_iter()
+                        .map(|err| KitFailure::Iface(iface_id, err)),
+                );
+            }
+            // Parent interfaces are not required to be the part of the kit
+            if let Err(errors) =
+                iface.check_inheritance(ifaces.iter().map(|(id, iface)| (id, *iface)))
+            {
+                failures.extend(
+                    errors
+                        .into_iter()
+                        .filter(|err| {
+                            !matches!(
+                                err,
+                                InheritanceError::UnknownParent(_)
+                                    | InheritanceError::UnknownAncestor(..)
+                            )
+                        })
+                        .map(|err| KitFailure::Inheritance(iface_id, err)),
+                );
+            }
+            if let Err(errors) = iface.check_types(&self.types) {
+                failures.extend(
+                    errors
+                        .into_iter()
+                        .map(|err| KitFailure::IfaceTypes(iface_id, err)),
+                );
+            }
+        }
+
+        for schema in &self.schemata {
+            let schema_id = schema.schema_id();
+            let schema_status = schema.verify(&self.types);
+            failures.extend(
+                schema_status
+                    .failures
+                    .into_iter()
+                    .map(|failure| KitFailure::Schema(schema_id, failure)),
+            );
+            status.warnings.extend(schema_status.warnings);
+            let validators = schema
+                .transitions
+                .values()
+                .map(|t| t.validator)
+                .chain(schema.extensions.values().map(|e| e.validator))
+                .chain([schema.genesis.validator]);
+            for lib_site in validators.flatten() {
+                if !self.scripts.iter().any(|lib| lib.id() == lib_site.lib) {
+                    failures.push(KitFailure::SchemaLibAbsent(schema_id, lib_site.lib));
+                }
+            }
+        }
+
+        for iimpl in &self.iimpls {
+            let impl_id = iimpl.impl_id();
+            let Some(iface) = ifaces.get(&iimpl.iface_id) else {
+                failures.push(KitFailure::ImplIfaceAbsent(impl_id, iimpl.iface_id));
+                continue;
+            };
+            let Some(schema) = self
+                .schemata
+                .iter()
+                .find(|schema| schema.schema_id() == iimpl.schema_id)
+            else {
+                failures.push(KitFailure::ImplSchemaAbsent(impl_id, iimpl.schema_id));
+                continue;
+            };
+            if let Err(errors) = iimpl.check(iface, schema) {
+                failures.extend(errors.into_iter().map(|err| KitFailure::Impl(impl_id, err)));
+            }
+        }
+
+        for schema in &self.schemata {
+            let schema_id = schema.schema_id();
+            let mut schema_ifaces = SchemaIfaces::new(schema.clone());
+            for iimpl in self
+                .iimpls
+                .iter()
+                .filter(|iimpl| iimpl.schema_id == schema_id)
+            {
+                let Some(iface) = ifaces.get(&iimpl.iface_id) else {
+                    continue;
+                };
+                // Both collections are tiny, so this never fails
+                schema_ifaces
+                    .iimpls
+                    .insert(iface.name.clone(), iimpl.clone())
+                    .ok();
+            }
+            if let Err(errors) =
+                schema_ifaces.check_types(ifaces.iter().map(|(id, iface)| (id, *iface)))
+            {
+                failures.extend(
+                    errors
+                        .into_iter()
+                        .map(|(iface_id, err)| KitFailure::SchemaIface(schema_id, iface_id, err)),
+                );
+            }
+            if let Err(errors) =
+                schema_ifaces.check_inheritance(ifaces.iter().map(|(id, iface)| (id, *iface)))
+            {
+                failures.extend(errors.into_iter().map(|(iface_id, err)| {
+                    KitFailure::SchemaInheritance(schema_id, iface_id, err)
+                }));
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err((failures, self));
+        }
+
+        self.signatures = validate_sigs(self.signatures, sig_validator, &mut status);
+
         Ok(ValidKit {
             validation_status: status,
             kit: self,
@@ -193,6 +330,42 @@ impl Kit {
     }
 }
 
+/// Kit validation failures.
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum KitFailure {
+    /// interface {0} is invalid: {1}
+    Iface(IfaceId, IfaceInconsistency),
+
+    /// interface {0} doesn't conform to its parent interface: {1}
+    Inheritance(IfaceId, InheritanceError),
+
+    /// interface {0} is invalid: {1}
+    IfaceTypes(IfaceId, IfaceTypeError),
+
+    /// schema {0} is invalid: {1}
+    Schema(SchemaId, validation::Failure),
+
+    /// schema {0} uses script library {1} absent in the kit.
+    SchemaLibAbsent(SchemaId, LibId),
+
+    /// implementation {0} references interface {1} absent in the kit.
+    ImplIfaceAbsent(ImplId, IfaceId),
+
+    /// implementation {0} references schema {1} absent in the kit.
+    ImplSchemaAbsent(ImplId, SchemaId),
+
+    /// implementation {0} is invalid: {1}
+    Impl(ImplId, ImplInconsistency),
+
+    /// schema {0} doesn't match interface {1} it implements: {2}
+    SchemaIface(SchemaId, IfaceId, ImplInconsistency),
+
+    /// schema {0} doesn't conform to interface {1} inherited by the interfaces
+    /// it implements: {2}
+    SchemaInheritance(SchemaId, IfaceId, ImplInconsistency),
+}
+
 impl StrictArmor for Kit {
     type Id = KitId;
     const PLATE_TITLE: &'static str = "RGB KIT";
@@ -271,7 +444,10 @@ impl FromStr for Kit {
 
 #[cfg(test)]
 mod test {
+    use amplify::confinement::{Confined, NonEmptyOrdMap};
+
     use super::*;
+    use crate::containers::{DumbValidator, SigBlob};
     #[test]
     fn kit_str_round_trip() {
         let kit = Kit::from_str(include_str!("../../asset/armored_kit.default"))
@@ -281,12 +457,174 @@ mod test {
         assert_eq!(kit.to_string(), hardcoded, "kit string round trip fails");
 
         assert_eq!(
-            kit.validate().unwrap().to_string(),
+            kit.validate(&DumbValidator).unwrap().to_string(),
             hardcoded,
             "validated kit string round trip fails"
         );
     }
 
+    #[test]
+    fn kit_sig_validation() {
+        use bp::secp256k1::{Keypair, SECP256K1};
+        use rgb::SchemaId;
+
+        use crate::containers::Bip340Validator;
+        use crate::interface::IfaceId;
+
+        let keypair = Keypair::from_seckey_slice(SECP256K1, &[0x42; 32]).unwrap();
+        let identity = Bip340Validator::identity(keypair.x_only_public_key().0);
+        let schema_id = ContentId::Schema(SchemaId::from_byte_array([0x01; 32]));
+        let iface_id = ContentId::Iface(IfaceId::from_byte_array([0x02; 32]));
+
+        let sig = keypair.sign_schnorr(&schema_id.sig_msg());
+        let valid = SigBlob::from(Confined::try_from(sig.as_ref().to_vec()).unwrap());
+        // Signature over a different content id must be rejected
+        let invalid = valid.clone();
+
+        let mut kit = Kit::default();
+        kit.signatures
+            .insert(
+                schema_id,
+                ContentSigs::from(NonEmptyOrdMap::with_key_value(identity.clone(), valid)),
+            )
+            .unwrap();
+        kit.signatures
+            .insert(iface_id, ContentSigs::from(NonEmptyOrdMap::with_key_value(identity, invalid)))
+            .unwrap();
+
+        let (kit, status) = kit.validate(&Bip340Validator).unwrap().split();
+        assert_eq!(status.warnings.len(), 1);
+        assert!(kit.signatures.contains_key(&schema_id));
+        assert!(!kit.signatures.contains_key(&iface_id));
+
+        let (kit, status) = kit.validate(&DumbValidator).unwrap().split();
+        assert_eq!(status.warnings.len(), 1);
+        assert!(kit.signatures.is_empty());
+    }
+
+    #[test]
+    fn kit_integrity_validation() {
+        use aluvm::library::LibSite;
+        use rgb::validation::Failure;
+        use rgb::{GlobalStateSchema, GlobalStateType};
+        use strict_encoding::StrictDumb;
+        use strict_types::SemId;
+
+        use crate::interface::{Modifier, OpName, TransitionIface};
+
+        let sem_id = SemId::from_byte_array([0x01; 32]);
+        let lib_id = LibId::from_byte_array([0x02; 32]);
+        let mut schema = Schema::strict_dumb();
+        schema
+            .global_types
+            .insert(GlobalStateType::with(1), GlobalStateSchema::once(sem_id))
+            .unwrap();
+        schema.genesis.validator = Some(LibSite::with(0, lib_id));
+        let schema_id = schema.schema_id();
+
+        let mut parent = Iface::strict_dumb();
+        let mut transfer = TransitionIface::strict_dumb();
+        transfer.modifier = Modifier::Final;
+        parent
+            .transitions
+            .insert(fname!("transfer"), transfer.clone())
+            .unwrap();
+        let parent_id = parent.iface_id();
+        let mut child = parent.clone();
+        child.name = tn!("Child");
+        child.inherits.push(parent_id).unwrap();
+        transfer.optional = true;
+        child
+            .transitions
+            .insert(fname!("transfer"), transfer)
+            .unwrap();
+        let child_id = child.iface_id();
+
+        let mut kit = Kit::default();
+        kit.schemata.push(schema).unwrap();
+        kit.ifaces.push(parent).unwrap();
+        kit.ifaces.push(child).unwrap();
+
+        let (failures, _) = kit.validate(&DumbValidator).unwrap_err();
+        assert_eq!(failures.len(), 3);
+        assert!(failures.contains(&KitFailure::Schema(
+            schema_id,
+            Failure::SchemaGlobalSemIdUnknown(GlobalStateType::with(1), sem_id)
+        )));
+        assert!(failures.contains(&KitFailure::SchemaLibAbsent(schema_id, lib_id)));
+        assert!(failures.contains(&KitFailure::Inheritance(
+            child_id,
+            InheritanceError::OpFinal(parent_id, OpName::Transition(fname!("transfer")))
+        )));
+    }
+
+    #[test]
+    fn kit_schema_iface_validation() {
+        use rgb::{GlobalStateSchema, GlobalStateType, MetaType};
+        use strict_encoding::StrictDumb;
+        use strict_types::SemId;
+
+        use crate::interface::{GlobalIface, NamedField};
+
+        let (schema_sem_id, iface_sem_id) =
+            (SemId::from_byte_array([0x01; 32]), SemId::from_byte_array([0x02; 32]));
+        let mut schema = Schema::strict_dumb();
+        schema
+            .global_types
+            .insert(GlobalStateType::with(1), GlobalStateSchema::once(schema_sem_id))
+            .unwrap();
+        schema
+            .meta_types
+            .insert(MetaType::with(1), schema_sem_id)
+            .unwrap();
+        let schema_id = schema.schema_id();
+
+        // Metadata of the parent is not required to be present in the child
+        let mut parent = Iface::strict_dumb();
+        parent
+            .metadata
+            .insert(fname!("ticker"), schema_sem_id)
+            .unwrap();
+        let parent_id = parent.iface_id();
+        let mut child = Iface::strict_dumb();
+        child.name = tn!("Child");
+        child.inherits.push(parent_id).unwrap();
+        child
+            .global_state
+            .insert(fname!("name"), GlobalIface::optional(iface_sem_id))
+            .unwrap();
+        let child_id = child.iface_id();
+
+        let mut iimpl = IfaceImpl::strict_dumb();
+        iimpl.schema_id = schema_id;
+        iimpl.iface_id = child_id;
+        iimpl
+            .global_state
+            .push(NamedField::with(GlobalStateType::with(1), fname!("name")))
+            .unwrap();
+
+        let mut kit = Kit::default();
+        kit.schemata.push(schema).unwrap();
+        kit.ifaces.push(parent).unwrap();
+        kit.ifaces.push(child).unwrap();
+        kit.iimpls.push(iimpl).unwrap();
+
+        let (failures, _) = kit.validate(&DumbValidator).unwrap_err();
+        assert!(failures.contains(&KitFailure::SchemaIface(
+            schema_id,
+            child_id,
+            ImplInconsistency::GlobalTypeMismatch(fname!("name"))
+        )));
+        assert!(failures.contains(&KitFailure::SchemaInheritance(
+            schema_id,
+            parent_id,
+            ImplInconsistency::IfaceMetaAbsent(fname!("ticker"))
+        )));
+        assert!(!failures
+            .iter()
+            .any(|failure| matches!(failure, KitFailure::Impl(..))));
+    }
+
     #[test]
     fn error_kit_strs() {
         assert!(Kit::from_str(
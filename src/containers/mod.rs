@@ -24,7 +24,7 @@
 //! 1. [`Consignment`]s, containing information about partial state of a *single contract*,
 //!    extending from its genesis up to certain contract endpoints.
 //! 2. [`Disclosure`]s, containing extracts from (possibly) independent state transitions and
-//!    extensions under multiple contracts. Useful fro disclosing the concealed state for some other
+//!    extensions under multiple contracts. Useful for disclosing the concealed state for some other
 //!    parties, and also for performing "change" operations on inventory during state transfers.
 
 mod seal;
@@ -46,10 +46,10 @@ pub use consignment::{
     Consignment, ConsignmentExt, ConsignmentId, ConsignmentParseError, Contract, Transfer,
     ValidConsignment, ValidContract, ValidTransfer,
 };
-pub use disclosure::Disclosure;
+pub use disclosure::{Disclosure, DisclosureId};
 pub use file::{FileContent, LoadError, UniversalFile};
 pub use indexed::IndexedConsignment;
-pub use kit::{Kit, KitId, ValidKit};
+pub use kit::{Kit, KitFailure, KitId, ValidKit};
 pub use partials::{
     Batch, BundleDichotomy, CloseMethodSet, Dichotomy, Fascia, TransitionDichotomy, TransitionInfo,
     TransitionInfoError,
@@ -61,7 +61,8 @@ pub use suppl::{
     SUPPL_ANNOT_VELOCITY,
 };
 pub use util::{
-    ContainerVer, ContentId, ContentSigs, DumbValidator, SigBlob, SigValidator, TrustLevel,
+    Bip340Validator, ContainerVer, ContentId, ContentSigs, DumbValidator, SigBlob, SigValidator,
+    TrustLevel,
 };
 
 pub const ASCII_ARMOR_NAME: &str = "Name";
@@ -71,6 +72,7 @@ pub const ASCII_ARMOR_SCHEMA: &str = "Schema";
 pub const ASCII_ARMOR_CONTRACT: &str = "Contract";
 pub const ASCII_ARMOR_VERSION: &str = "Version";
 pub const ASCII_ARMOR_TERMINAL: &str = "Terminal";
+pub const ASCII_ARMOR_BUNDLE: &str = "Bundle";
 pub const ASCII_ARMOR_SUPPL: &str = "Supplement";
 pub const ASCII_ARMOR_SCRIPT: &str = "Alu-Lib";
 pub const ASCII_ARMOR_TYPE_SYSTEM: &str = "Type-System";
@@ -20,9 +20,13 @@
 // limitations under the License.
 
 use std::collections::btree_map;
+use std::str::FromStr;
 
-use amplify::confinement::{NonEmptyBlob, NonEmptyOrdMap};
-use commit_verify::StrictHash;
+use amplify::confinement::{NonEmptyBlob, NonEmptyOrdMap, TinyOrdMap};
+use amplify::ByteArray;
+use bp::secp256k1::{schnorr, XOnlyPublicKey};
+use commit_verify::{CommitId, StrictHash};
+use rgb::validation::{self, Warning};
 use rgb::{ContractId, Identity, SchemaId};
 use strict_encoding::StrictDumb;
 
@@ -48,12 +52,50 @@ pub enum ContainerVer {
 }
 
 pub trait SigValidator {
-    fn validate_sig(&self, identity: &Identity, sig: SigBlob) -> bool;
+    /// Checks that the signature `sig` made by `identity` commits to the
+    /// content with the id `content_id`.
+    fn validate_sig(&self, identity: &Identity, content_id: ContentId, sig: &SigBlob) -> bool;
 }
 
 pub struct DumbValidator;
 impl SigValidator for DumbValidator {
-    fn validate_sig(&self, _: &Identity, _: SigBlob) -> bool { false }
+    fn validate_sig(&self, _: &Identity, _: ContentId, _: &SigBlob) -> bool { false }
+}
+
+/// Validator for BIP340 Schnorr signatures over the content id commitment.
+///
+/// Supports only identities in the form of `ssi:bip340:<x-only-pubkey-hex>`;
+/// signatures from all other identities are reported as invalid.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Bip340Validator;
+
+impl Bip340Validator {
+    pub const IDENTITY_PREFIX: &'static str = "ssi:bip340:";
+
+    /// Constructs identity for a given BIP340 public key.
+    pub fn identity(pubkey: XOnlyPublicKey) -> Identity {
+        Identity::from_str(&format!("{}{pubkey}", Self::IDENTITY_PREFIX))
+            .expect("identity is always a valid ASCII string")
+    }
+
+    /// Extracts BIP340 public key from the identity, if the identity uses the
+    /// supported format.
+    pub fn pubkey(identity: &Identity) -> Option<XOnlyPublicKey> {
+        let hex = identity.as_str().strip_prefix(Self::IDENTITY_PREFIX)?;
+        XOnlyPublicKey::from_str(hex).ok()
+    }
+}
+
+impl SigValidator for Bip340Validator {
+    fn validate_sig(&self, identity: &Identity, content_id: ContentId, sig: &SigBlob) -> bool {
+        let Some(pubkey) = Self::pubkey(identity) else {
+            return false;
+        };
+        let Ok(sig) = schnorr::Signature::from_slice(sig.as_slice()) else {
+            return false;
+        };
+        sig.verify(&content_id.sig_msg(), &pubkey).is_ok()
+    }
 }
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Default)]
@@ -81,22 +123,34 @@ impl TrustLevel {
     pub fn must_use(self) -> bool { self >= Self::Ultimate }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
 #[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
 #[strict_type(lib = LIB_NAME_RGB_STD, tags = order, dumb = ContentId::Schema(strict_dumb!()))]
+#[derive(CommitEncode)]
+#[commit_encode(strategy = strict, id = StrictHash)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
     serde(crate = "serde_crate", rename_all = "camelCase")
 )]
 pub enum ContentId {
+    #[display("schema {0}")]
     Schema(SchemaId),
+    #[display("contract {0}")]
     Genesis(ContractId),
+    #[display("interface {0}")]
     Iface(IfaceId),
+    #[display("implementation {0}")]
     IfaceImpl(ImplId),
+    #[display("supplement {0}")]
     Suppl(SupplId),
 }
 
+impl ContentId {
+    /// Message which has to be signed by the content signers.
+    pub fn sig_msg(&self) -> [u8; 32] { self.commit_id().to_byte_array() }
+}
+
 #[derive(Wrapper, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From, Display)]
 #[wrapper(Deref, AsSlice, BorrowSlice, Hex)]
 #[display(LowerHex)]
@@ -129,6 +183,42 @@ impl StrictDumb for ContentSigs {
     }
 }
 
+impl ContentSigs {
+    /// Validates signatures using the provided validator, returning only the
+    /// valid ones. Each invalid signature is reported as a warning to the
+    /// validation `status`.
+    pub fn validate(
+        self,
+        content_id: ContentId,
+        validator: &impl SigValidator,
+        status: &mut validation::Status,
+    ) -> Option<Self> {
+        let sigs = self.into_iter().filter(|(identity, sig)| {
+            let valid = validator.validate_sig(identity, content_id, sig);
+            if !valid {
+                status.add_warning(Warning::Custom(format!(
+                    "invalid signature of {identity} on {content_id} was removed"
+                )));
+            }
+            valid
+        });
+        NonEmptyOrdMap::try_from_iter(sigs).ok().map(Self)
+    }
+}
+
+/// Validates all signatures from the container, removing invalid ones and
+/// reporting them as warnings to the validation `status`.
+pub(crate) fn validate_sigs(
+    signatures: TinyOrdMap<ContentId, ContentSigs>,
+    validator: &impl SigValidator,
+    status: &mut validation::Status,
+) -> TinyOrdMap<ContentId, ContentSigs> {
+    TinyOrdMap::from_iter_checked(signatures.into_iter().filter_map(|(content_id, sigs)| {
+        sigs.validate(content_id, validator, status)
+            .map(|sigs| (content_id, sigs))
+    }))
+}
+
 impl IntoIterator for ContentSigs {
     type Item = (Identity, SigBlob);
     type IntoIter = btree_map::IntoIter<Identity, SigBlob>;
@@ -20,12 +20,13 @@
 // limitations under the License.
 
 use std::borrow::Borrow;
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use invoice::{Allocation, Amount};
 use rgb::{
-    AssignmentType, AttachState, ContractId, DataState, OpId, RevealedAttach, RevealedData,
-    RevealedValue, Schema, VoidState, XOutpoint, XOutputSeal, XWitnessId,
+    AssignmentType, AttachState, ContractId, DataState, GlobalStateType, OpId, OwnedStateSchema,
+    RevealedAttach, RevealedData, RevealedValue, Schema, VoidState, XOutpoint, XOutputSeal,
+    XWitnessId,
 };
 use strict_encoding::{FieldName, StrictDecode, StrictDumb, StrictEncode};
 use strict_types::{StrictVal, TypeSystem};
@@ -41,6 +42,15 @@ use crate::LIB_NAME_RGB_STD;
 pub enum ContractError {
     /// field name {0} is unknown to the contract interface
     FieldNameUnknown(FieldName),
+
+    /// state {0} is not fungible.
+    NotFungible(FieldName),
+
+    /// balance of {0} exceeds the maximal amount value.
+    BalanceOverflow(FieldName),
+
+    /// movement of the fungible state {0} exceeds the maximal amount value.
+    MovementOverflow(AssignmentType),
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, From)]
@@ -228,6 +238,198 @@ impl ContractOp {
     }
 }
 
+/// Fungible state of a single assignment type owned by a wallet, broken down
+/// by the status of the witness transactions.
+///
+/// Each allocation is accounted in exactly one of the fields:
+/// - `settled`: unspent allocations created by genesis, state extensions or witnesses mined with
+///   the required number of confirmations;
+/// - `pending_incoming`: unspent allocations created by tentative witnesses or witnesses which are
+///   not yet confirmed deep enough;
+/// - `pending_outgoing`: allocations spent only by tentative or not yet confirmed deep enough
+///   witnesses;
+/// - `invalidated`: allocations created by archived witnesses.
+///
+/// Allocations spent by a settled witness are not accounted.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct Balance {
+    pub settled: Amount,
+    pub pending_incoming: Amount,
+    pub pending_outgoing: Amount,
+    pub invalidated: Amount,
+}
+
+impl Balance {
+    /// Amount which will be owned once all pending witnesses get settled,
+    /// i.e. the settled amount together with the pending incoming one.
+    ///
+    /// Returns `None` on an overflow.
+    pub fn expected(&self) -> Option<Amount> { self.settled.checked_add(self.pending_incoming) }
+}
+
+/// Change of the fungible state of a single assignment type between two
+/// contract states.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct FungibleMovement {
+    pub created: Amount,
+    pub spent: Amount,
+}
+
+impl FungibleMovement {
+    /// Net movement, which is negative if more state was spent than created.
+    pub fn net(&self) -> i128 { self.created.value() as i128 - self.spent.value() as i128 }
+}
+
+/// Difference between two states of the same contract, as seen by the outputs
+/// matching some filter.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct ContractDiff {
+    /// Allocations unspent in the new state which were not present or spent
+    /// in the old state.
+    pub created: Vec<OwnedAllocation>,
+    /// Allocations unspent in the old state which are spent or not present in
+    /// the new state.
+    pub spent: Vec<OwnedAllocation>,
+    /// Global state entries which are present only in the new state.
+    pub global: BTreeMap<GlobalStateType, Vec<DataState>>,
+    /// Movement of the fungible state per assignment type.
+    pub fungible: BTreeMap<AssignmentType, FungibleMovement>,
+}
+
+impl ContractDiff {
+    /// Computes difference from the `old` to the `new` state of a contract for
+    /// the outputs matching the filter.
+    ///
+    /// Global state is compared only for the provided global state types, and
+    /// only within the number of the most recent items retained by the state.
+    ///
+    /// Errors if the amount created or spent for some assignment type exceeds
+    /// the maximal amount value.
+    pub fn compute(
+        old: &impl ContractStateRead,
+        new: &impl ContractStateRead,
+        global_types: impl IntoIterator<Item = GlobalStateType>,
+        filter: impl AssignmentsFilter + Copy,
+    ) -> Result<Self, ContractError> {
+        let old_unspent = unspent_allocations(old, filter).collect::<Vec<_>>();
+        let new_unspent = unspent_allocations(new, filter).collect::<Vec<_>>();
+        let old_opouts = old_unspent.iter().map(|a| a.opout).collect::<HashSet<_>>();
+        let new_opouts = new_unspent.iter().map(|a| a.opout).collect::<HashSet<_>>();
+
+        let mut diff = ContractDiff {
+            created: new_unspent
+                .into_iter()
+                .filter(|a| !old_opouts.contains(&a.opout))
+                .collect(),
+            spent: old_unspent
+                .into_iter()
+                .filter(|a| !new_opouts.contains(&a.opout))
+                .collect(),
+            ..default!()
+        };
+
+        for a in &diff.created {
+            if let AllocatedState::Amount(amount) = a.state {
+                let movement = diff.fungible.entry(a.opout.ty).or_default();
+                movement.created = movement
+                    .created
+                    .checked_add(amount)
+                    .ok_or(ContractError::MovementOverflow(a.opout.ty))?;
+            }
+        }
+        for a in &diff.spent {
+            if let AllocatedState::Amount(amount) = a.state {
+                let movement = diff.fungible.entry(a.opout.ty).or_default();
+                movement.spent = movement
+                    .spent
+                    .checked_add(amount)
+                    .ok_or(ContractError::MovementOverflow(a.opout.ty))?;
+            }
+        }
+
+        for ty in global_types {
+            let mut known = BTreeMap::<DataState, usize>::new();
+            for data in old.global(ty).into_iter().flatten() {
+                *known.entry(data.borrow().clone()).or_default() += 1;
+            }
+            let added = new
+                .global(ty)
+                .into_iter()
+                .flatten()
+                .map(|data| data.borrow().clone())
+                .filter(|data| match known.get_mut(data) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        false
+                    }
+                    _ => true,
+                })
+                .collect::<Vec<_>>();
+            if !added.is_empty() {
+                diff.global.insert(ty, added);
+            }
+        }
+
+        Ok(diff)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.spent.is_empty() && self.global.is_empty()
+    }
+}
+
+/// Iterates over all owned state of a contract assigned to the outputs matching
+/// the filter, independently from any interface.
+pub(crate) fn owned_allocations<'c, S: ContractStateRead>(
+    state: &'c S,
+    filter: impl AssignmentsFilter + Copy + 'c,
+) -> impl Iterator<Item = OwnedAllocation> + 'c {
+    fn f<'a, S, U>(
+        filter: impl AssignmentsFilter + 'a,
+        state: impl IntoIterator<Item = &'a OutputAssignment<S>> + 'a,
+    ) -> impl Iterator<Item = OutputAssignment<U>> + 'a
+    where
+        S: Clone + KnownState + 'a,
+        U: From<S> + KnownState + 'a,
+    {
+        state
+            .into_iter()
+            .filter(move |outp| filter.should_include(outp.seal, outp.witness))
+            .cloned()
+            .map(OutputAssignment::<S>::transmute)
+    }
+
+    f(filter, state.rights_all())
+        .map(OwnedAllocation::from)
+        .chain(f(filter, state.fungible_all()).map(OwnedAllocation::from))
+        .chain(f(filter, state.data_all()).map(OwnedAllocation::from))
+        .chain(f(filter, state.attach_all()).map(OwnedAllocation::from))
+}
+
+/// Iterates over unspent owned state of a contract assigned to the outputs
+/// matching the filter, independently from any interface.
+pub(crate) fn unspent_allocations<'c, S: ContractStateRead>(
+    state: &'c S,
+    filter: impl AssignmentsFilter + Copy + 'c,
+) -> impl Iterator<Item = OwnedAllocation> + 'c {
+    owned_allocations(state, filter).filter(|a| !state.is_spent(a.opout))
+}
+
 /// Contract state is an in-memory structure providing API to read structured
 /// data from the [`rgb::ContractHistory`].
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -313,7 +515,7 @@ impl<S: ContractStateRead> ContractIface<S> {
         name: impl Into<FieldName>,
         filter: impl AssignmentsFilter + 'c,
     ) -> Result<impl Iterator<Item = RightsAllocation> + 'c, ContractError> {
-        self.extract_state(self.state.rights_all(), name, filter)
+        self.extract_state(self.state.rights_unspent(), name, filter)
     }
 
     pub fn fungible<'c>(
@@ -321,7 +523,61 @@ impl<S: ContractStateRead> ContractIface<S> {
         name: impl Into<FieldName>,
         filter: impl AssignmentsFilter + 'c,
     ) -> Result<impl Iterator<Item = FungibleAllocation> + 'c, ContractError> {
-        self.extract_state(self.state.fungible_all(), name, filter)
+        self.extract_state(self.state.fungible_unspent(), name, filter)
+    }
+
+    /// Computes balance of the fungible state with the given name, assigned to
+    /// the outputs matching the filter.
+    ///
+    /// Witnesses mined at `tip_height` have a single confirmation; witnesses
+    /// with less than `min_depth` confirmations are considered pending.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`ContractError::NotFungible`] if the schema doesn't define
+    /// the state as fungible.
+    pub fn balance(
+        &self,
+        name: impl Into<FieldName>,
+        filter: impl AssignmentsFilter,
+        tip_height: u32,
+        min_depth: u32,
+    ) -> Result<Balance, ContractError> {
+        let name = name.into();
+        let type_id = self
+            .iface
+            .assignments_type(&name)
+            .ok_or_else(|| ContractError::FieldNameUnknown(name.clone()))?;
+        if !matches!(self.schema.owned_types.get(&type_id), Some(OwnedStateSchema::Fungible(_))) {
+            return Err(ContractError::NotFungible(name));
+        }
+        self.state
+            .fungible_balance(type_id, filter, tip_height, min_depth)
+            .ok_or(ContractError::BalanceOverflow(name))
+    }
+
+    /// Computes balances of all fungible state known to the interface, see
+    /// [`Self::balance`] for the details.
+    pub fn balances(
+        &self,
+        filter: impl AssignmentsFilter + Copy,
+        tip_height: u32,
+        min_depth: u32,
+    ) -> Result<BTreeMap<FieldName, Balance>, ContractError> {
+        self.iface
+            .assignments
+            .iter()
+            .filter(|field| {
+                matches!(
+                    self.schema.owned_types.get(&field.id),
+                    Some(OwnedStateSchema::Fungible(_))
+                )
+            })
+            .map(|field| {
+                let balance = self.balance(field.name.clone(), filter, tip_height, min_depth)?;
+                Ok((field.name.clone(), balance))
+            })
+            .collect()
     }
 
     pub fn data<'c>(
@@ -329,7 +585,7 @@ impl<S: ContractStateRead> ContractIface<S> {
         name: impl Into<FieldName>,
         filter: impl AssignmentsFilter + 'c,
     ) -> Result<impl Iterator<Item = DataAllocation> + 'c, ContractError> {
-        self.extract_state(self.state.data_all(), name, filter)
+        self.extract_state(self.state.data_unspent(), name, filter)
     }
 
     pub fn attachments<'c>(
@@ -337,33 +593,24 @@ impl<S: ContractStateRead> ContractIface<S> {
         name: impl Into<FieldName>,
         filter: impl AssignmentsFilter + 'c,
     ) -> Result<impl Iterator<Item = AttachAllocation> + 'c, ContractError> {
-        self.extract_state(self.state.attach_all(), name, filter)
+        self.extract_state(self.state.attach_unspent(), name, filter)
     }
 
     pub fn allocations<'c>(
         &'c self,
         filter: impl AssignmentsFilter + Copy + 'c,
     ) -> impl Iterator<Item = OwnedAllocation> + 'c {
-        fn f<'a, S, U>(
-            filter: impl AssignmentsFilter + 'a,
-            state: impl IntoIterator<Item = &'a OutputAssignment<S>> + 'a,
-        ) -> impl Iterator<Item = OutputAssignment<U>> + 'a
-        where
-            S: Clone + KnownState + 'a,
-            U: From<S> + KnownState + 'a,
-        {
-            state
-                .into_iter()
-                .filter(move |outp| filter.should_include(outp.seal, outp.witness))
-                .cloned()
-                .map(OutputAssignment::<S>::transmute)
-        }
+        unspent_allocations(&self.state, filter)
+    }
 
-        f(filter, self.state.rights_all())
-            .map(OwnedAllocation::from)
-            .chain(f(filter, self.state.fungible_all()).map(OwnedAllocation::from))
-            .chain(f(filter, self.state.data_all()).map(OwnedAllocation::from))
-            .chain(f(filter, self.state.attach_all()).map(OwnedAllocation::from))
+    /// Computes changes in the contract state owned by the outputs matching
+    /// the filter, which happened from the `base` state to the current one.
+    pub fn diff_since(
+        &self,
+        base: &impl ContractStateRead,
+        filter: impl AssignmentsFilter + Copy,
+    ) -> Result<ContractDiff, ContractError> {
+        ContractDiff::compute(base, &self.state, self.schema.global_types.keys().copied(), filter)
     }
 
     pub fn outpoint_allocations(
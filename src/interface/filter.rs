@@ -22,111 +22,353 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ops::Deref;
 
-use rgb::{XOutpoint, XWitnessId};
+use bp::seals::txout::{CloseMethod, TxoSeal};
+use rgb::vm::WitnessOrd;
+use rgb::{XOutpoint, XOutputSeal, XWitnessId};
+
+use crate::persistence::ContractStateRead;
 
 pub trait AssignmentsFilter {
-    fn should_include(
-        &self,
-        outpoint: impl Into<XOutpoint>,
-        witness_id: Option<XWitnessId>,
-    ) -> bool;
+    /// Checks whether an assignment to the output seal, created by the given
+    /// witness, should be included.
+    fn should_include(&self, seal: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool;
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
 pub struct FilterIncludeAll;
+
+/// Negation of a filter.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
 pub struct FilterExclude<T>(pub T);
 
+/// Includes assignments matching both of the filters.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct FilterAnd<A, B>(pub A, pub B);
+
+/// Includes assignments matching any of the filters.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct FilterOr<A, B>(pub A, pub B);
+
 impl AssignmentsFilter for FilterIncludeAll {
-    fn should_include(&self, _: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool { true }
+    fn should_include(&self, _: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool { true }
 }
 
 impl<T: AssignmentsFilter> AssignmentsFilter for FilterExclude<T> {
-    fn should_include(
-        &self,
-        outpoint: impl Into<XOutpoint>,
-        witness_id: Option<XWitnessId>,
-    ) -> bool {
-        !self.0.should_include(outpoint.into(), witness_id)
+    fn should_include(&self, seal: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        !self.0.should_include(seal, witness_id)
+    }
+}
+
+impl<A: AssignmentsFilter, B: AssignmentsFilter> AssignmentsFilter for FilterAnd<A, B> {
+    fn should_include(&self, seal: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        let seal = seal.into();
+        self.0.should_include(seal, witness_id) && self.1.should_include(seal, witness_id)
+    }
+}
+
+impl<A: AssignmentsFilter, B: AssignmentsFilter> AssignmentsFilter for FilterOr<A, B> {
+    fn should_include(&self, seal: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        let seal = seal.into();
+        self.0.should_include(seal, witness_id) || self.1.should_include(seal, witness_id)
     }
 }
 
 impl<T: AssignmentsFilter> AssignmentsFilter for &T {
-    fn should_include(
-        &self,
-        outpoint: impl Into<XOutpoint>,
-        witness_id: Option<XWitnessId>,
-    ) -> bool {
-        (*self).should_include(outpoint, witness_id)
+    fn should_include(&self, seal: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        (*self).should_include(seal, witness_id)
     }
 }
 
 impl<T: AssignmentsFilter> AssignmentsFilter for &mut T {
-    fn should_include(
-        &self,
-        outpoint: impl Into<XOutpoint>,
-        witness_id: Option<XWitnessId>,
-    ) -> bool {
-        self.deref().should_include(outpoint, witness_id)
+    fn should_include(&self, seal: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        self.deref().should_include(seal, witness_id)
     }
 }
 
 impl<T: AssignmentsFilter> AssignmentsFilter for Option<T> {
-    fn should_include(
-        &self,
-        outpoint: impl Into<XOutpoint>,
-        witness_id: Option<XWitnessId>,
-    ) -> bool {
+    fn should_include(&self, seal: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
         self.as_ref()
-            .map(|filter| filter.should_include(outpoint, witness_id))
+            .map(|filter| filter.should_include(seal, witness_id))
             .unwrap_or(true)
     }
 }
 
 impl AssignmentsFilter for XOutpoint {
-    fn should_include(&self, outpoint: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool {
-        *self == outpoint.into()
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        *self == XOutpoint::from(seal.into())
     }
 }
 
 impl<const LEN: usize> AssignmentsFilter for [XOutpoint; LEN] {
-    fn should_include(&self, outpoint: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool {
-        self.contains(&outpoint.into())
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        self.contains(&XOutpoint::from(seal.into()))
     }
 }
 
 impl AssignmentsFilter for &[XOutpoint] {
-    fn should_include(&self, outpoint: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool {
-        self.contains(&outpoint.into())
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        self.contains(&XOutpoint::from(seal.into()))
     }
 }
 
 impl AssignmentsFilter for Vec<XOutpoint> {
-    fn should_include(&self, outpoint: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool {
-        self.contains(&outpoint.into())
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        self.contains(&XOutpoint::from(seal.into()))
     }
 }
 
 impl AssignmentsFilter for HashSet<XOutpoint> {
-    fn should_include(&self, outpoint: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool {
-        self.contains(&outpoint.into())
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        self.contains(&XOutpoint::from(seal.into()))
     }
 }
 
 impl AssignmentsFilter for BTreeSet<XOutpoint> {
-    fn should_include(&self, outpoint: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool {
-        self.contains(&outpoint.into())
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        self.contains(&XOutpoint::from(seal.into()))
     }
 }
 
 impl<V> AssignmentsFilter for HashMap<XOutpoint, V> {
-    fn should_include(&self, outpoint: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool {
-        let outpoint = outpoint.into();
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        let outpoint = XOutpoint::from(seal.into());
         self.keys().any(|o| *o == outpoint)
     }
 }
 
 impl<V> AssignmentsFilter for BTreeMap<XOutpoint, V> {
-    fn should_include(&self, outpoint: impl Into<XOutpoint>, _: Option<XWitnessId>) -> bool {
-        let outpoint = outpoint.into();
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        let outpoint = XOutpoint::from(seal.into());
         self.keys().any(|o| *o == outpoint)
     }
 }
+
+/// Includes assignments to the seals with the given closing method.
+impl AssignmentsFilter for CloseMethod {
+    fn should_include(&self, seal: impl Into<XOutputSeal>, _: Option<XWitnessId>) -> bool {
+        seal.into().method() == *self
+    }
+}
+
+impl AssignmentsFilter for XWitnessId {
+    fn should_include(&self, _: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        witness_id == Some(*self)
+    }
+}
+
+impl<const LEN: usize> AssignmentsFilter for [XWitnessId; LEN] {
+    fn should_include(&self, _: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        witness_id.is_some_and(|id| self.contains(&id))
+    }
+}
+
+impl AssignmentsFilter for &[XWitnessId] {
+    fn should_include(&self, _: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        witness_id.is_some_and(|id| self.contains(&id))
+    }
+}
+
+impl AssignmentsFilter for Vec<XWitnessId> {
+    fn should_include(&self, _: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        witness_id.is_some_and(|id| self.contains(&id))
+    }
+}
+
+impl AssignmentsFilter for HashSet<XWitnessId> {
+    fn should_include(&self, _: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        witness_id.is_some_and(|id| self.contains(&id))
+    }
+}
+
+impl AssignmentsFilter for BTreeSet<XWitnessId> {
+    fn should_include(&self, _: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        witness_id.is_some_and(|id| self.contains(&id))
+    }
+}
+
+/// Source of witness ordering information for [`FilterWitnessStatus`].
+pub trait WitnessOrdProvider {
+    fn witness_ord(&self, witness_id: XWitnessId) -> Option<WitnessOrd>;
+}
+
+impl<S: ContractStateRead> WitnessOrdProvider for &S {
+    fn witness_ord(&self, witness_id: XWitnessId) -> Option<WitnessOrd> {
+        ContractStateRead::witness_ord(*self, witness_id)
+    }
+}
+
+impl WitnessOrdProvider for &HashMap<XWitnessId, WitnessOrd> {
+    fn witness_ord(&self, witness_id: XWitnessId) -> Option<WitnessOrd> {
+        self.get(&witness_id).copied()
+    }
+}
+
+impl WitnessOrdProvider for &BTreeMap<XWitnessId, WitnessOrd> {
+    fn witness_ord(&self, witness_id: XWitnessId) -> Option<WitnessOrd> {
+        self.get(&witness_id).copied()
+    }
+}
+
+/// Status of a witness transaction required by [`FilterWitnessStatus`].
+///
+/// Assignments without witness (made by genesis or state extensions) are
+/// treated as mined below any height.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum WitnessStatus {
+    /// Witness is mined.
+    Mined,
+    /// Witness is tentative, i.e. not yet mined.
+    Tentative,
+    /// Witness is archived.
+    Archived,
+    /// Witness is mined at a height lower than the given one.
+    MinedBelow(u32),
+    /// Witness is mined at a height higher than the given one.
+    MinedAbove(u32),
+    /// Witness has at least `depth` confirmations when the blockchain tip is
+    /// at `tip` height. Witness mined in the tip block has a single
+    /// confirmation.
+    Confirmed { tip: u32, depth: u32 },
+}
+
+impl WitnessStatus {
+    /// Checks whether a witness with the given ordering has the status.
+    pub fn matches(self, ord: WitnessOrd) -> bool {
+        match (self, ord) {
+            (WitnessStatus::Mined, WitnessOrd::Mined(_)) => true,
+            (WitnessStatus::Tentative, WitnessOrd::Tentative) => true,
+            (WitnessStatus::Archived, WitnessOrd::Archived) => true,
+            (WitnessStatus::MinedBelow(height), WitnessOrd::Mined(pos)) => {
+                pos.height().get() < height
+            }
+            (WitnessStatus::MinedAbove(height), WitnessOrd::Mined(pos)) => {
+                pos.height().get() > height
+            }
+            (WitnessStatus::Confirmed { tip, depth }, WitnessOrd::Mined(pos)) => {
+                tip.saturating_sub(pos.height().get()).saturating_add(1) >= depth
+            }
+            _ => false,
+        }
+    }
+
+    fn matches_no_witness(self) -> bool {
+        matches!(
+            self,
+            WitnessStatus::Mined | WitnessStatus::MinedBelow(_) | WitnessStatus::Confirmed { .. }
+        )
+    }
+}
+
+/// Includes assignments which witness transactions have the given status.
+/// Assignments with witnesses unknown to the provider are not included.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct FilterWitnessStatus<P: WitnessOrdProvider> {
+    pub provider: P,
+    pub status: WitnessStatus,
+}
+
+impl<P: WitnessOrdProvider> FilterWitnessStatus<P> {
+    pub fn new(provider: P, status: WitnessStatus) -> Self { Self { provider, status } }
+}
+
+impl<P: WitnessOrdProvider> AssignmentsFilter for FilterWitnessStatus<P> {
+    fn should_include(&self, _: impl Into<XOutputSeal>, witness_id: Option<XWitnessId>) -> bool {
+        match witness_id {
+            None => self.status.matches_no_witness(),
+            Some(witness_id) => self
+                .provider
+                .witness_ord(witness_id)
+                .is_some_and(|ord| self.status.matches(ord)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU32;
+
+    use bp::seals::txout::ExplicitSeal;
+    use bp::Txid;
+    use rgb::vm::WitnessPos;
+    use rgb::XChain;
+
+    use super::*;
+
+    fn seal(no: u8, method: CloseMethod) -> XOutputSeal {
+        XChain::Bitcoin(ExplicitSeal::with(method, Txid::from([no; 32]), 0))
+    }
+
+    fn witness(no: u8) -> XWitnessId { XChain::Bitcoin(Txid::from([no; 32])) }
+
+    fn mined(height: u32) -> WitnessOrd {
+        WitnessOrd::Mined(
+            WitnessPos::bitcoin(NonZeroU32::new(height).unwrap(), 1231006505).unwrap(),
+        )
+    }
+
+    #[test]
+    fn combinators() {
+        let tapret = seal(1, CloseMethod::TapretFirst);
+        let opret = seal(2, CloseMethod::OpretFirst);
+        let ours = [XOutpoint::from(tapret)];
+
+        assert!(CloseMethod::TapretFirst.should_include(tapret, None));
+        assert!(!CloseMethod::TapretFirst.should_include(opret, None));
+        assert!(!FilterExclude(CloseMethod::TapretFirst).should_include(tapret, None));
+        assert!(FilterExclude(CloseMethod::TapretFirst).should_include(opret, None));
+        assert!(FilterExclude(&CloseMethod::OpretFirst).should_include(tapret, Some(witness(1))));
+
+        let filter = FilterAnd(ours, CloseMethod::TapretFirst);
+        assert!(filter.should_include(tapret, None));
+        assert!(!filter.should_include(opret, None));
+
+        let filter = FilterOr(ours, CloseMethod::OpretFirst);
+        assert!(filter.should_include(tapret, None));
+        assert!(filter.should_include(opret, None));
+        assert!(!FilterExclude(filter).should_include(opret, None));
+
+        let filter = FilterAnd(FilterIncludeAll, [witness(1)]);
+        assert!(filter.should_include(opret, Some(witness(1))));
+        assert!(!filter.should_include(opret, Some(witness(2))));
+        assert!(!filter.should_include(opret, None));
+
+        let filter = FilterAnd(FilterExclude(CloseMethod::OpretFirst), Some([witness(1)]));
+        assert!(filter.should_include(tapret, Some(witness(1))));
+        assert!(!filter.should_include(opret, Some(witness(1))));
+        assert!(!filter.should_include(tapret, Some(witness(2))));
+    }
+
+    #[test]
+    fn witness_status() {
+        let ords = bmap! {
+            witness(1) => mined(100),
+            witness(2) => mined(110),
+            witness(3) => WitnessOrd::Tentative,
+            witness(4) => WitnessOrd::Archived,
+        };
+        let matching = |status: WitnessStatus| {
+            let filter = FilterWitnessStatus::new(&ords, status);
+            (1..=5)
+                .map(witness)
+                .map(Some)
+                .chain([None])
+                .filter(|id| filter.should_include(seal(0, CloseMethod::OpretFirst), *id))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(matching(WitnessStatus::Mined), vec![Some(witness(1)), Some(witness(2)), None]);
+        assert_eq!(matching(WitnessStatus::Tentative), vec![Some(witness(3))]);
+        assert_eq!(matching(WitnessStatus::Archived), vec![Some(witness(4))]);
+        assert_eq!(matching(WitnessStatus::MinedBelow(110)), vec![Some(witness(1)), None]);
+        assert_eq!(matching(WitnessStatus::MinedAbove(100)), vec![Some(witness(2))]);
+        assert_eq!(matching(WitnessStatus::Confirmed { tip: 110, depth: 6 }), vec![
+            Some(witness(1)),
+            None
+        ]);
+        assert_eq!(matching(WitnessStatus::Confirmed { tip: 110, depth: 1 }), vec![
+            Some(witness(1)),
+            Some(witness(2)),
+            None
+        ]);
+    }
+}
@@ -20,7 +20,7 @@
 // limitations under the License.
 
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{self, Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::str::FromStr;
@@ -35,7 +35,7 @@ use strict_encoding::{
     FieldName, StrictDecode, StrictDeserialize, StrictDumb, StrictEncode, StrictSerialize,
     StrictType, TypeName, VariantName,
 };
-use strict_types::{SemId, SymbolicSys, TypeLib};
+use strict_types::{SemId, SymbolicSys, TypeLib, TypeSystem};
 
 use crate::interface::{ContractIface, IfaceDisplay, IfaceImpl, VerNo};
 use crate::persistence::{ContractStateRead, SchemaIfaces};
@@ -620,23 +620,186 @@ impl Iface {
         }
     }
 
-    // TODO: Implement checking interface inheritance.
-    /*
-    pub fn check_inheritance<'a>(&self, ifaces: impl IntoIterator<Item = (&'a IfaceId, &'a Iface)>) -> Result<(), Vec<InheritanceError>> {
-        // check for the depth
+    /// Checks that the interface inheritance chain resolves against the
+    /// provided set of known interfaces, has no cycles, doesn't exceed
+    /// [`IFACE_INHERITANCE_DEPTH_MAX`] and that the interface respects
+    /// operation modifiers of all its parents.
+    pub fn check_inheritance<'a>(
+        &self,
+        ifaces: impl IntoIterator<Item = (&'a IfaceId, &'a Iface)>,
+    ) -> Result<(), Vec<InheritanceError>> {
+        let ifaces = ifaces.into_iter().collect::<BTreeMap<_, _>>();
+        let mut errors = vec![];
+
+        let iface_id = self.iface_id();
+        let mut path = vec![iface_id];
+        let depth = self.inheritance_depth(&ifaces, &mut path, &mut HashMap::new(), &mut errors);
+        if depth > IFACE_INHERITANCE_DEPTH_MAX {
+            errors.push(InheritanceError::TooDeep(depth));
+        }
+
+        for parent_id in &self.inherits {
+            let Some(parent) = ifaces.get(parent_id) else {
+                continue;
+            };
+            self.check_parent(*parent_id, parent, &mut errors);
+        }
+
+        // The same ancestor may be reached through multiple inheritance paths
+        let mut reported = HashSet::new();
+        errors.retain(|err| reported.insert(err.clone()));
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
-     */
 
-    // TODO: Implement checking types against presence in a type system.
-    /*
-    pub fn check_types(&self, sys: &TypeSystem) -> Result<(), Vec<IfaceTypeError>> {
-        for g in self.global_state.values() {
-            if let Some(id) = g.sem_id {
+    /// Computes the depth of the inheritance chain. Depths of the already
+    /// visited ancestors are memoized in `depths`, so each ancestor is
+    /// processed once even if reachable through multiple inheritance paths.
+    fn inheritance_depth(
+        &self,
+        ifaces: &BTreeMap<&IfaceId, &Iface>,
+        path: &mut Vec<IfaceId>,
+        depths: &mut HashMap<IfaceId, usize>,
+        errors: &mut Vec<InheritanceError>,
+    ) -> usize {
+        let mut depth = 0;
+        for parent_id in &self.inherits {
+            if path.contains(parent_id) {
+                errors.push(InheritanceError::Cycle(*parent_id));
+                continue;
+            }
+            let Some(parent) = ifaces.get(parent_id) else {
+                if path.len() == 1 {
+                    errors.push(InheritanceError::UnknownParent(*parent_id));
+                } else {
+                    errors.push(InheritanceError::UnknownAncestor(
+                        *path.last().expect("path is never empty"),
+                        *parent_id,
+                    ));
+                }
+                continue;
+            };
+            if path.len() > IFACE_INHERITANCE_DEPTH_MAX {
+                return path.len();
+            }
+            let parent_depth = match depths.get(parent_id) {
+                Some(parent_depth) => *parent_depth,
+                None => {
+                    path.push(*parent_id);
+                    let parent_depth = parent.inheritance_depth(ifaces, path, depths, errors);
+                    path.pop();
+                    depths.insert(*parent_id, parent_depth);
+                    parent_depth
+                }
+            };
+            depth = depth.max(parent_depth + 1);
+        }
+        depth
+    }
 
+    fn check_parent(&self, parent_id: IfaceId, parent: &Iface, errors: &mut Vec<InheritanceError>) {
+        for (name, g) in &parent.global_state {
+            match self.global_state.get(name) {
+                None => errors.push(InheritanceError::GlobalAbsent(parent_id, name.clone())),
+                Some(own) if g.sem_id.is_some() && own.sem_id != g.sem_id => {
+                    errors.push(InheritanceError::GlobalType(parent_id, name.clone()))
+                }
+                _ => {}
             }
         }
+        for (name, a) in &parent.assignments {
+            match self.assignments.get(name) {
+                None => errors.push(InheritanceError::AssignmentAbsent(parent_id, name.clone())),
+                Some(own) if !a.owned_state.is_superset(own.owned_state) => {
+                    errors.push(InheritanceError::AssignmentType(parent_id, name.clone()))
+                }
+                _ => {}
+            }
+        }
+
+        let mut check_op = |op: OpName, orig: Modifier, own: Option<Modifier>, same: bool| {
+            let Some(own) = own else {
+                errors.push(InheritanceError::OpAbsent(parent_id, op));
+                return;
+            };
+            if same {
+                return;
+            }
+            if orig.is_final() {
+                errors.push(InheritanceError::OpFinal(parent_id, op));
+            } else if !orig.can_be_overridden_by(own) {
+                errors.push(InheritanceError::OpNoOverride(parent_id, op));
+            }
+        };
+
+        check_op(
+            OpName::Genesis,
+            parent.genesis.modifier,
+            Some(self.genesis.modifier),
+            self.genesis == parent.genesis,
+        );
+        for (name, op) in &parent.transitions {
+            let own = self.transitions.get(name);
+            if own.is_none() && op.optional {
+                continue;
+            }
+            check_op(
+                OpName::Transition(name.clone()),
+                op.modifier,
+                own.map(|own| own.modifier),
+                own == Some(op),
+            );
+        }
+        for (name, op) in &parent.extensions {
+            let own = self.extensions.get(name);
+            if own.is_none() && op.optional {
+                continue;
+            }
+            check_op(
+                OpName::Extension(name.clone()),
+                op.modifier,
+                own.map(|own| own.modifier),
+                own == Some(op),
+            );
+        }
+    }
+
+    /// Checks that all semantic types used by the interface metadata, global
+    /// state and assignments are present in the type system.
+    ///
+    /// NB: Interface errors are defined as plain text messages, thus they do
+    /// not reference any types.
+    pub fn check_types(&self, sys: &TypeSystem) -> Result<(), Vec<IfaceTypeError>> {
+        let mut errors = vec![];
+        for (name, sem_id) in &self.metadata {
+            if !sys.contains_key(sem_id) {
+                errors.push(IfaceTypeError::MetadataTypeAbsent(name.clone(), *sem_id));
+            }
+        }
+        for (name, g) in &self.global_state {
+            if let Some(sem_id) = g.sem_id {
+                if !sys.contains_key(&sem_id) {
+                    errors.push(IfaceTypeError::GlobalTypeAbsent(name.clone(), sem_id));
+                }
+            }
+        }
+        for (name, a) in &self.assignments {
+            if let Some(sem_id) = a.owned_state.sem_id() {
+                if !sys.contains_key(&sem_id) {
+                    errors.push(IfaceTypeError::AssignmentTypeAbsent(name.clone(), sem_id));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
-     */
 }
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug, Display)]
@@ -692,3 +855,178 @@ pub enum IfaceInconsistency {
     /// valency '{0}' is required, but genesis doesn't define it.
     RequiredValencyAbsent(FieldName),
 }
+
+/// Maximum depth of the interface inheritance chain.
+pub const IFACE_INHERITANCE_DEPTH_MAX: usize = 0xFF;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum InheritanceError {
+    /// parent interface {0} is unknown.
+    UnknownParent(IfaceId),
+    /// interface {0} inherits from interface {1} which is unknown.
+    UnknownAncestor(IfaceId, IfaceId),
+    /// interface {0} is present in its own inheritance chain.
+    Cycle(IfaceId),
+    /// inheritance chain depth {0} exceeds the maximum allowed depth of 255.
+    TooDeep(usize),
+    /// global state '{1}' defined by the parent interface {0} is absent.
+    GlobalAbsent(IfaceId, FieldName),
+    /// global state '{1}' has different data type from the parent interface {0}.
+    GlobalType(IfaceId, FieldName),
+    /// assignment '{1}' defined by the parent interface {0} is absent.
+    AssignmentAbsent(IfaceId, FieldName),
+    /// assignment '{1}' has a state type incompatible with the parent interface
+    /// {0}.
+    AssignmentType(IfaceId, FieldName),
+    /// {1} defined by the parent interface {0} is absent.
+    OpAbsent(IfaceId, OpName),
+    /// {1} in the parent interface {0} is final and can't be overridden.
+    OpFinal(IfaceId, OpName),
+    /// {1} must use `override` keyword to modify the version from the parent
+    /// interface {0}.
+    OpNoOverride(IfaceId, OpName),
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum IfaceTypeError {
+    /// metadata '{0}' uses semantic type {1} which is absent in the type system.
+    MetadataTypeAbsent(FieldName, SemId),
+    /// global state '{0}' uses semantic type {1} which is absent in the type
+    /// system.
+    GlobalTypeAbsent(FieldName, SemId),
+    /// assignment '{0}' uses semantic type {1} which is absent in the type
+    /// system.
+    AssignmentTypeAbsent(FieldName, SemId),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn base() -> Iface {
+        let mut iface = Iface::strict_dumb();
+        iface.name = tn!("Base");
+        iface
+            .global_state
+            .insert(fname!("name"), GlobalIface::required(SemId::from_byte_array([0x01; 32])))
+            .unwrap();
+        let mut transfer = TransitionIface::strict_dumb();
+        transfer.modifier = Modifier::Final;
+        iface
+            .transitions
+            .insert(fname!("transfer"), transfer)
+            .unwrap();
+        iface
+    }
+
+    fn child(base: &Iface) -> Iface {
+        let mut iface = base.clone();
+        iface.name = tn!("Child");
+        iface.inherits.push(base.iface_id()).unwrap();
+        iface
+            .global_state
+            .insert(fname!("ticker"), GlobalIface::required(SemId::from_byte_array([0x02; 32])))
+            .unwrap();
+        iface
+    }
+
+    #[test]
+    fn iface_inheritance() {
+        let base = base();
+        let base_id = base.iface_id();
+        let child = child(&base);
+        assert_eq!(child.check_inheritance([(&base_id, &base)]), Ok(()));
+        assert_eq!(
+            child.check_inheritance([]),
+            Err(vec![InheritanceError::UnknownParent(base_id)])
+        );
+
+        let mut broken = child.clone();
+        broken.global_state.remove(&fname!("name")).unwrap();
+        broken
+            .transitions
+            .get_mut(&fname!("transfer"))
+            .unwrap()
+            .optional = true;
+        assert_eq!(
+            broken.check_inheritance([(&base_id, &base)]),
+            Err(vec![
+                InheritanceError::GlobalAbsent(base_id, fname!("name")),
+                InheritanceError::OpFinal(base_id, OpName::Transition(fname!("transfer"))),
+            ])
+        );
+
+        // Interface ids are commitments, so a cycle may appear only in a
+        // corrupted set of known interfaces
+        let child_id = child.iface_id();
+        let mut looping = base.clone();
+        looping.inherits.push(child_id).unwrap();
+        assert!(child
+            .check_inheritance([(&base_id, &looping), (&child_id, &child)])
+            .unwrap_err()
+            .contains(&InheritanceError::Cycle(child_id)));
+    }
+
+    #[test]
+    fn iface_inheritance_modifiers() {
+        let mut base = base();
+        let mut issue = TransitionIface::strict_dumb();
+        issue.modifier = Modifier::Abstract;
+        base.transitions.insert(fname!("issue"), issue).unwrap();
+        let base_id = base.iface_id();
+
+        // Abstract operation can't be redefined as abstract once again
+        let mut child = child(&base);
+        let issue = child.transitions.get_mut(&fname!("issue")).unwrap();
+        issue.optional = true;
+        assert_eq!(
+            child.check_inheritance([(&base_id, &base)]),
+            Err(vec![InheritanceError::OpNoOverride(base_id, OpName::Transition(fname!("issue")))])
+        );
+
+        let issue = child.transitions.get_mut(&fname!("issue")).unwrap();
+        issue.modifier = Modifier::Override;
+        assert_eq!(child.check_inheritance([(&base_id, &base)]), Ok(()));
+    }
+
+    #[test]
+    fn iface_inheritance_diamonds() {
+        // Each level inherits from both interfaces of the previous level,
+        // giving 2^64 inheritance paths to the base interface
+        let base = base();
+        let mut ifaces = vec![base.clone()];
+        let mut level = vec![base.iface_id()];
+        for no in 0..64 {
+            let mut next = vec![];
+            for side in ["Left", "Right"] {
+                let mut iface = base.clone();
+                iface.name = TypeName::from_str(&format!("{side}{no}")).unwrap();
+                for parent_id in &level {
+                    iface.inherits.push(*parent_id).unwrap();
+                }
+                next.push(iface.iface_id());
+                ifaces.push(iface);
+            }
+            level = next;
+        }
+        let ids = ifaces.iter().map(Iface::iface_id).collect::<Vec<_>>();
+        let known = ids.iter().zip(&ifaces);
+        let last = ifaces.last().unwrap();
+        assert_eq!(last.check_inheritance(known), Ok(()));
+    }
+
+    #[test]
+    fn iface_types() {
+        let base = base();
+        let sys = TypeSystem::new();
+        assert_eq!(
+            base.check_types(&sys),
+            Err(vec![IfaceTypeError::GlobalTypeAbsent(
+                fname!("name"),
+                SemId::from_byte_array([0x01; 32])
+            )])
+        );
+    }
+}
@@ -376,6 +376,17 @@ pub enum ImplInconsistency {
     /// implementation references unknown interface error '{0}'.
     IfaceErrorAbsent(VariantName),
 
+    /// implementation metadata field '{0}' maps to a schema metadata type with
+    /// a semantic type different from the one required by the interface.
+    MetaTypeMismatch(FieldName),
+    /// implementation global state field '{0}' maps to a schema global state
+    /// type with a semantic type different from the one required by the
+    /// interface.
+    GlobalTypeMismatch(FieldName),
+    /// implementation owned state field '{0}' maps to a schema owned state
+    /// type of a kind different from the one required by the interface.
+    AssignmentTypeMismatch(FieldName),
+
     /// metadata field '{0}' is repeated {1} times
     RepeatedMetaData(FieldName, i32),
 
@@ -560,4 +571,97 @@ impl IfaceImpl {
             Err(errors)
         }
     }
+
+    /// Checks that the schema types, to which the implementation maps the
+    /// interface metadata, global state and assignments, match the types
+    /// required by the interface. Fields not resolved by the implementation
+    /// or unknown to the schema are reported by [`Self::check`].
+    pub fn check_types(
+        &self,
+        iface: &Iface,
+        schema: &Schema,
+    ) -> Result<(), Vec<ImplInconsistency>> {
+        let mut errors = vec![];
+
+        for field in &self.metadata {
+            let (Some(sem_id), Some(schema_sem_id)) =
+                (iface.metadata.get(&field.name), schema.meta_types.get(&field.id))
+            else {
+                continue;
+            };
+            if sem_id != schema_sem_id {
+                errors.push(ImplInconsistency::MetaTypeMismatch(field.name.clone()));
+            }
+        }
+        for field in &self.global_state {
+            let (Some(global_iface), Some(global_schema)) =
+                (iface.global_state.get(&field.name), schema.global_types.get(&field.id))
+            else {
+                continue;
+            };
+            if global_iface
+                .sem_id
+                .is_some_and(|sem_id| sem_id != global_schema.sem_id)
+            {
+                errors.push(ImplInconsistency::GlobalTypeMismatch(field.name.clone()));
+            }
+        }
+        for field in &self.assignments {
+            let (Some(assign_iface), Some(state_schema)) =
+                (iface.assignments.get(&field.name), schema.owned_types.get(&field.id))
+            else {
+                continue;
+            };
+            if !assign_iface.owned_state.accepts(state_schema) {
+                errors.push(ImplInconsistency::AssignmentTypeMismatch(field.name.clone()));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks that the implementation resolves metadata, global state,
+    /// assignments and valencies of an ancestor of the implemented interface
+    /// to the schema types matching the ones required by the ancestor.
+    pub fn check_ancestor(
+        &self,
+        ancestor: &Iface,
+        schema: &Schema,
+    ) -> Result<(), Vec<ImplInconsistency>> {
+        let mut errors = vec![];
+
+        for name in ancestor.metadata.keys() {
+            if self.metadata.iter().all(|field| &field.name != name) {
+                errors.push(ImplInconsistency::IfaceMetaAbsent(name.clone()));
+            }
+        }
+        for name in ancestor.global_state.keys() {
+            if self.global_state.iter().all(|field| &field.name != name) {
+                errors.push(ImplInconsistency::IfaceGlobalAbsent(name.clone()));
+            }
+        }
+        for name in ancestor.assignments.keys() {
+            if self.assignments.iter().all(|field| &field.name != name) {
+                errors.push(ImplInconsistency::IfaceAssignmentAbsent(name.clone()));
+            }
+        }
+        for name in ancestor.valencies.keys() {
+            if self.valencies.iter().all(|field| &field.name != name) {
+                errors.push(ImplInconsistency::IfaceValencyAbsent(name.clone()));
+            }
+        }
+        if let Err(type_errors) = self.check_types(ancestor, schema) {
+            errors.extend(type_errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 }
@@ -21,8 +21,8 @@
 
 use amplify::confinement::{Confined, TinyOrdMap, TinyOrdSet};
 use rgb::{
-    AssignmentType, ExtensionType, GlobalStateType, Occurrences, OpFullType, OpSchema, Schema,
-    TransitionType, ValencyType,
+    AssignmentType, ExtensionType, GlobalStateType, Occurrences, OpFullType, OpSchema,
+    OwnedStateSchema, Schema, TransitionType, ValencyType,
 };
 use strict_encoding::{FieldName, TypeName};
 
@@ -283,6 +283,22 @@ impl OwnedIface {
         }
         self == other
     }
+
+    /// Checks whether the owned state defined by a schema is of the kind
+    /// required by the interface.
+    pub fn accepts(self, state_schema: &OwnedStateSchema) -> bool {
+        match (self, state_schema) {
+            (Self::Any, _) => true,
+            (Self::Rights, OwnedStateSchema::Declarative) => true,
+            (Self::Amount, OwnedStateSchema::Fungible(_)) => true,
+            (Self::AnyData, OwnedStateSchema::Structured(_)) => true,
+            (Self::AnyAttach, OwnedStateSchema::Attachment(_)) => true,
+            (Self::Data(sem_id), OwnedStateSchema::Structured(schema_sem_id)) => {
+                sem_id == *schema_sem_id
+            }
+            _ => false,
+        }
+    }
 }
 
 impl Modifier {
@@ -33,18 +33,25 @@ mod contractum;
 mod inheritance;
 
 pub use builder::{BuilderError, ContractBuilder, TransitionBuilder, TxOutpoint};
+pub(crate) use contract::unspent_allocations;
 pub use contract::{
-    AllocatedState, AttachAllocation, ContractError, ContractIface, ContractOp, DataAllocation,
-    FungibleAllocation, OpDirection, OwnedAllocation, RightsAllocation,
+    AllocatedState, AttachAllocation, Balance, ContractDiff, ContractError, ContractIface,
+    ContractOp, DataAllocation, FungibleAllocation, FungibleMovement, OpDirection, OwnedAllocation,
+    RightsAllocation,
 };
 pub use contractum::IfaceDisplay;
-pub use filter::{AssignmentsFilter, FilterExclude, FilterIncludeAll};
+pub use filter::{
+    AssignmentsFilter, FilterAnd, FilterExclude, FilterIncludeAll, FilterOr, FilterWitnessStatus,
+    WitnessOrdProvider, WitnessStatus,
+};
 pub use iface::{
     ArgMap, AssignIface, ExtensionIface, GenesisIface, GlobalIface, Iface, IfaceClass, IfaceId,
-    IfaceInconsistency, IfaceRef, IfaceWrapper, Modifier, OpName, OwnedIface, Req, TransitionIface,
-    ValencyIface,
+    IfaceInconsistency, IfaceRef, IfaceTypeError, IfaceWrapper, InheritanceError, Modifier, OpName,
+    OwnedIface, Req, TransitionIface, ValencyIface, IFACE_INHERITANCE_DEPTH_MAX,
+};
+pub use iimpl::{
+    IfaceImpl, ImplId, ImplInconsistency, NamedField, NamedType, NamedVariant, SchemaTypeIndex,
 };
-pub use iimpl::{IfaceImpl, ImplId, NamedField, NamedType, NamedVariant, SchemaTypeIndex};
 pub use inheritance::{CheckInheritance, ExtensionError, InheritanceFailure};
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Default)]
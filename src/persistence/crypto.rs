@@ -0,0 +1,288 @@
+// RGB standard library for working with smart contracts on Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2019-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2019-2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Authenticated encryption of persisted data under a passphrase-derived key.
+//!
+//! The data are encrypted and authenticated with XChaCha20-Poly1305 using a key
+//! derived from the passphrase with Argon2id.
+
+use argon2::{Algorithm, Argon2, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use zeroize::Zeroizing;
+
+const MAGIC: [u8; 8] = *b"RGBENC\x00\x02";
+const SALT_LEN: usize = 16;
+const PARAMS_LEN: usize = 12;
+const CHECK_LEN: usize = 8;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + PARAMS_LEN + CHECK_LEN + NONCE_LEN;
+
+/// Errors decrypting persisted data.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum EncryptionError {
+    /// data are not encrypted or use an unsupported encryption format.
+    UnknownFormat,
+
+    /// key derivation parameters {0} are not supported or exceed the limits
+    /// of this version of the software.
+    UnsupportedKdf(KdfParams),
+
+    /// the passphrase doesn't match the one used to encrypt the data.
+    InvalidPassphrase,
+
+    /// encrypted data are corrupted or were tampered with.
+    Tampered,
+}
+
+/// Parameters of the Argon2id function deriving the encryption key from a
+/// passphrase.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display("argon2id(m={memory_kib}, t={iterations}, p={parallelism})")]
+pub struct KdfParams {
+    /// Memory size, in KiB.
+    pub memory_kib: u32,
+    /// Number of passes over the memory.
+    pub iterations: u32,
+    /// Degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// Parameters recommended by OWASP for password storage.
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Upper limit of the parameters accepted from the encrypted data, which
+    /// protects from the denial of service with crafted files.
+    pub const MAX: KdfParams = KdfParams {
+        memory_kib: 4 * 1024 * 1024,
+        iterations: 64,
+        parallelism: 16,
+    };
+
+    fn argon2(self) -> Result<Argon2<'static>, EncryptionError> {
+        let max = Self::MAX;
+        if self.memory_kib > max.memory_kib
+            || self.iterations > max.iterations
+            || self.parallelism > max.parallelism
+        {
+            return Err(EncryptionError::UnsupportedKdf(self));
+        }
+        let params = argon2::Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN + CHECK_LEN),
+        )
+        .map_err(|_| EncryptionError::UnsupportedKdf(self))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// Keys derived from a passphrase.
+pub(super) struct Keys {
+    salt: [u8; SALT_LEN],
+    params: KdfParams,
+    check: [u8; CHECK_LEN],
+    key: Zeroizing<[u8; KEY_LEN]>,
+}
+
+impl Keys {
+    pub fn derive(
+        passphrase: &[u8],
+        salt: [u8; SALT_LEN],
+        params: KdfParams,
+    ) -> Result<Self, EncryptionError> {
+        let mut output = Zeroizing::new([0u8; KEY_LEN + CHECK_LEN]);
+        params
+            .argon2()?
+            .hash_password_into(passphrase, &salt, output.as_mut_slice())
+            .map_err(|_| EncryptionError::UnsupportedKdf(params))?;
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        key.copy_from_slice(&output[..KEY_LEN]);
+        let mut check = [0u8; CHECK_LEN];
+        check.copy_from_slice(&output[KEY_LEN..]);
+        Ok(Keys {
+            salt,
+            params,
+            check,
+            key,
+        })
+    }
+
+    pub fn with_random_salt(passphrase: &[u8], params: KdfParams) -> Result<Self, EncryptionError> {
+        Self::derive(passphrase, rand::random(), params)
+    }
+
+    pub fn params(&self) -> KdfParams { self.params }
+
+    /// Checks whether data were encrypted with keys derived with the same
+    /// salt and parameters.
+    pub fn matches(&self, header: &Header) -> bool {
+        self.salt == header.salt && self.params == header.params
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new_from_slice(self.key.as_slice()).expect("key has a valid length")
+    }
+
+    /// Encrypts data, authenticating them together with the header and the
+    /// `context`.
+    pub fn encrypt(&self, context: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let mut data = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&self.salt);
+        data.extend_from_slice(&self.params.memory_kib.to_le_bytes());
+        data.extend_from_slice(&self.params.iterations.to_le_bytes());
+        data.extend_from_slice(&self.params.parallelism.to_le_bytes());
+        data.extend_from_slice(&self.check);
+        data.extend_from_slice(&nonce);
+        let aad = [&data, context].concat();
+        let ciphertext = self
+            .cipher()
+            .encrypt(XNonce::from_slice(&nonce), Payload {
+                msg: plaintext,
+                aad: &aad,
+            })
+            .expect("data are within the cipher limits");
+        data.extend_from_slice(&ciphertext);
+        data
+    }
+
+    /// Authenticates and decrypts data produced by [`Keys::encrypt`] with the
+    /// same `context`.
+    pub fn decrypt(&self, context: &[u8], data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let header = Header::read(data)?;
+        if header.check != self.check {
+            return Err(EncryptionError::InvalidPassphrase);
+        }
+        let (header, ciphertext) = data.split_at(HEADER_LEN);
+        let aad = [header, context].concat();
+        self.cipher()
+            .decrypt(XNonce::from_slice(&header[HEADER_LEN - NONCE_LEN..]), Payload {
+                msg: ciphertext,
+                aad: &aad,
+            })
+            .map_err(|_| EncryptionError::Tampered)
+    }
+}
+
+/// Key derivation parameters of encrypted data.
+pub(super) struct Header {
+    pub salt: [u8; SALT_LEN],
+    pub params: KdfParams,
+    check: [u8; CHECK_LEN],
+}
+
+impl Header {
+    pub fn read(data: &[u8]) -> Result<Self, EncryptionError> {
+        if data.len() < HEADER_LEN + TAG_LEN || data[..MAGIC.len()] != MAGIC {
+            return Err(EncryptionError::UnknownFormat);
+        }
+        let data = &data[MAGIC.len()..];
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&data[..SALT_LEN]);
+        let data = &data[SALT_LEN..];
+        let param = |no: usize| {
+            let mut value = [0u8; 4];
+            value.copy_from_slice(&data[no * 4..no * 4 + 4]);
+            u32::from_le_bytes(value)
+        };
+        let params = KdfParams {
+            memory_kib: param(0),
+            iterations: param(1),
+            parallelism: param(2),
+        };
+        let mut check = [0u8; CHECK_LEN];
+        check.copy_from_slice(&data[PARAMS_LEN..PARAMS_LEN + CHECK_LEN]);
+        Ok(Header {
+            salt,
+            params,
+            check,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PARAMS: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn encrypt_decrypt() {
+        let keys = Keys::with_random_salt(b"passphrase", PARAMS).unwrap();
+        let data = keys.encrypt(b"stash", b"some secret data");
+        assert_eq!(keys.decrypt(b"stash", &data).unwrap(), b"some secret data");
+        assert_eq!(keys.decrypt(b"index", &data).unwrap_err(), EncryptionError::Tampered);
+
+        let mut tampered = data.clone();
+        tampered[HEADER_LEN] ^= 1;
+        assert_eq!(keys.decrypt(b"stash", &tampered).unwrap_err(), EncryptionError::Tampered);
+        let mut tampered = data.clone();
+        tampered[HEADER_LEN - 1] ^= 1;
+        assert_eq!(keys.decrypt(b"stash", &tampered).unwrap_err(), EncryptionError::Tampered);
+
+        let header = Header::read(&data).unwrap();
+        let other = Keys::derive(b"other", header.salt, header.params).unwrap();
+        assert_eq!(other.decrypt(b"stash", &data).unwrap_err(), EncryptionError::InvalidPassphrase);
+        assert_eq!(
+            keys.decrypt(b"stash", b"plaintext").unwrap_err(),
+            EncryptionError::UnknownFormat
+        );
+    }
+
+    #[test]
+    fn kdf_limits() {
+        let params = KdfParams {
+            iterations: u32::MAX,
+            ..PARAMS
+        };
+        assert_eq!(
+            Keys::with_random_salt(b"passphrase", params).err(),
+            Some(EncryptionError::UnsupportedKdf(params))
+        );
+        let params = KdfParams {
+            memory_kib: 0,
+            ..PARAMS
+        };
+        assert_eq!(
+            Keys::with_random_salt(b"passphrase", params).err(),
+            Some(EncryptionError::UnsupportedKdf(params))
+        );
+    }
+}
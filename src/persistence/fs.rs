@@ -19,23 +19,95 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::path::PathBuf;
-use std::{fs, io};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock, RwLockReadGuard};
+use std::{fs, io, mem, process};
 
-use amplify::confinement::U32 as U32MAX;
-use nonasync::persistence::{PersistenceError, PersistenceProvider};
-use strict_encoding::{StrictDeserialize, StrictSerialize};
+use amplify::hex::FromHex;
+use fs4::FileExt;
+use nonasync::persistence::{
+    CloneNoPersistence, Persistence, PersistenceError, PersistenceProvider, Persisting,
+};
+use rgb::validation::ResolveWitness;
+use rgb::{ContractId, Genesis, Operation, Schema, XWitnessId};
+use zeroize::Zeroizing;
 
-use crate::persistence::{MemIndex, MemStash, MemState};
+pub use super::crypto::{EncryptionError, KdfParams};
+use super::crypto::{Header, Keys};
+#[cfg(doc)]
+use crate::persistence::StorageError;
+use crate::persistence::{
+    HistoryPoint, MemContract, MemContractState, MemContractWriter, MemError, MemIndex, MemStash,
+    MemState, StateInconsistency, StateProvider, StateReadProvider, StateWriteProvider, Stock,
+    StorageData, StoreTransaction, UpdateRes,
+};
+#[cfg(doc)]
+use crate::stl::STORAGE_VERSION;
 
-#[derive(Clone, Eq, PartialEq, Debug)]
+const MANIFEST_FILE: &str = "manifest";
+const LOCK_FILE: &str = "lock";
+const TMP_EXT: &str = "tmp";
+const SEGMENT_PREFIX: &str = "contracts/";
+const WITNESSES_SEGMENT: &str = "witnesses";
+const SEGMENT_EXT: &str = "dat";
+
+/// File-system storage for a stock, keeping stash, state and index in binary
+/// files.
+///
+/// Each write of the stash, state or index creates a new generation of the
+/// file, which becomes effective only once it is committed into the manifest
+/// file. Generations of the state and index files are committed together with
+/// the next successful write of the stash (which is written last when a stock
+/// is saved) or on an explicit [`FsBinStore::commit`]. Generations which are
+/// not committed by the time the last copy of the store is dropped are
+/// discarded, as well as after a crash, such that the data are always loaded
+/// from the last consistent generation.
+///
+/// The stash and index are kept in single files, which are completely read on
+/// load and rewritten on each save, since their lookups by bundle and operation
+/// ids span all contracts. The contract state can be split into per-contract
+/// segments, loaded on demand, by persisting [`FsState`] instead of
+/// [`MemState`]; the segments are written as generations committed together
+/// with the other files.
+///
+/// The directory is locked with an advisory lock over a lock file for as long
+/// as the store (or any of its copies) exists, such that two processes can't
+/// use the same stock. The lock is released by the operating system once the
+/// process exits, so a crashed process doesn't leave the directory locked.
+#[derive(Clone, Debug)]
 pub struct FsBinStore {
     pub stash: PathBuf,
     pub state: PathBuf,
     pub index: PathBuf,
+    /// Directory keeping the state of each contract in a separate file, used
+    /// by [`FsState`].
+    pub contracts: PathBuf,
+    journal: Arc<Mutex<Journal>>,
 }
 
+impl PartialEq for FsBinStore {
+    fn eq(&self, other: &Self) -> bool {
+        self.stash == other.stash
+            && self.state == other.state
+            && self.index == other.index
+            && self.contracts == other.contracts
+    }
+}
+
+impl Eq for FsBinStore {}
+
 impl FsBinStore {
+    /// Opens a stock directory, creating it if necessary.
+    ///
+    /// # Errors
+    ///
+    /// If the directory is already used by another store, returns an error of
+    /// [`io::ErrorKind::WouldBlock`] kind.
     pub fn new(path: PathBuf) -> io::Result<Self> {
         fs::create_dir_all(&path)?;
 
@@ -45,49 +117,1377 @@ impl FsBinStore {
         state.push("state.dat");
         let mut index = path.clone();
         index.push("index.dat");
+        let mut contracts = path.clone();
+        contracts.push("contracts");
+
+        let journal = Journal::open(path, contracts.clone())?;
 
         Ok(Self {
             stash,
             state,
             index,
+            contracts,
+            journal: Arc::new(Mutex::new(journal)),
         })
     }
+
+    /// Commits generations of the state and index files which were written
+    /// after the last write of the stash.
+    ///
+    /// NB: Generations which are not committed are discarded once the store is
+    /// dropped.
+    pub fn commit(&self) -> Result<(), PersistenceError> { self.journal().commit() }
+
+    fn journal(&self) -> MutexGuard<'_, Journal> {
+        // A panic while holding the lock can't leave the journal in an invalid state, since the
+        // manifest is updated in-memory only after it is written.
+        self.journal
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn base_path(&self, component: Component) -> &Path {
+        match component {
+            Component::Stash => &self.stash,
+            Component::State => &self.state,
+            Component::Index => &self.index,
+        }
+    }
+
+    /// Returns path to the latest generation of a component file, including
+    /// generations which were written by this process and not committed yet.
+    fn current_path(&self, component: Component) -> PathBuf { self.current_file(component).0 }
+
+    /// Returns path and generation of the latest generation of a component
+    /// file.
+    fn current_file(&self, component: Component) -> (PathBuf, Option<u64>) {
+        self.journal()
+            .current_file(component.name(), self.base_path(component))
+    }
+
+    fn load_component<T: StorageData>(&self, component: Component) -> Result<T, PersistenceError> {
+        read_file(&self.current_path(component))
+    }
+
+    fn store_component(
+        &self,
+        component: Component,
+        object: &impl StorageData,
+    ) -> Result<(), PersistenceError> {
+        let data = object.to_envelope().map_err(PersistenceError::with)?;
+        self.write_component(component, |path, _| {
+            fs::write(path, data).map_err(PersistenceError::with)
+        })
+    }
+
+    /// Writes a new generation of a component file, committing it if the
+    /// component is the stash.
+    fn write_component(
+        &self,
+        component: Component,
+        write: impl FnOnce(&Path, u64) -> Result<(), PersistenceError>,
+    ) -> Result<(), PersistenceError> {
+        let base = self.base_path(component).to_path_buf();
+        let mut journal = self.journal();
+        journal.stage(component.name(), &base, write)?;
+        if component == Component::Stash {
+            journal.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Returns path to the latest generation of a contract state segment, if
+    /// the segment exists.
+    fn segment_path(&self, name: &str) -> Option<PathBuf> {
+        self.segment_file(name).map(|(path, _)| path)
+    }
+
+    /// Returns path and generation of the latest generation of a contract
+    /// state segment, if the segment exists.
+    fn segment_file(&self, name: &str) -> Option<(PathBuf, Option<u64>)> {
+        let base = segment_base(&self.contracts, name);
+        let (path, generation) = self.journal().current_file(&segment_key(name), &base);
+        // Segments written before they were tracked by the manifest may be absent
+        (generation.is_some() || path.exists()).then_some((path, generation))
+    }
+
+    /// Lists contracts which state segments exist.
+    fn segment_contracts(&self) -> io::Result<BTreeSet<ContractId>> {
+        let parse = |name: &str| ContractId::from_hex(name).ok();
+        let mut contracts = self
+            .journal()
+            .staged
+            .files
+            .keys()
+            .filter_map(|key| key.strip_prefix(SEGMENT_PREFIX))
+            .filter_map(parse)
+            .collect::<BTreeSet<_>>();
+        let entries = match fs::read_dir(&self.contracts) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(contracts),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXT) {
+                continue;
+            }
+            contracts.extend(
+                path.file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(parse),
+            );
+        }
+        Ok(contracts)
+    }
+
+    /// Writes a new generation of a contract state segment, which is committed
+    /// together with the stash.
+    fn write_segment(&self, name: &str, object: &impl StorageData) -> Result<(), PersistenceError> {
+        let data = object.to_envelope().map_err(PersistenceError::with)?;
+        let base = segment_base(&self.contracts, name);
+        self.journal().stage(&segment_key(name), &base, |path, _| {
+            fs::write(path, data).map_err(PersistenceError::with)
+        })
+    }
+
+    /// Upgrades data persisted with the previous storage versions to the
+    /// current [`STORAGE_VERSION`].
+    ///
+    /// Fails with [`StorageError::Unsupported`] if the contract state or index
+    /// can't be migrated; in this case they have to be re-computed with
+    /// [`FsBinStore::migrate`].
+    pub fn upgrade(&self) -> Result<(), PersistenceError> {
+        let mut stock = Stock::<MemStash, MemState, MemIndex>::load(self.clone(), true)?;
+        stock.make_persistent(self.clone(), true)?;
+        Ok(())
+    }
+
+    /// Migrates data persisted with the previous storage versions, including
+    /// the legacy layout of the `RGBStorage` type library, which limited the
+    /// stash to 255 schemata and contracts.
+    ///
+    /// The stash is converted into the current layout, while contract state
+    /// and index are re-computed from the stash, resolving witness ordering
+    /// with the `resolver`. All files are overwritten.
+    pub fn migrate(&self, resolver: impl ResolveWitness) -> Result<(), PersistenceError> {
+        let stash = self.load_component::<MemStash>(Component::Stash)?;
+        let mut stock = Stock::with(stash, MemState::in_memory(), MemIndex::in_memory())
+            .rebuild_state(MemState::in_memory(), resolver)
+            .map_err(PersistenceError::with)?
+            .rebuild_index(MemIndex::in_memory())
+            .map_err(PersistenceError::with)?;
+        stock.make_persistent(self.clone(), true)?;
+        Ok(())
+    }
 }
+
 impl PersistenceProvider<MemStash> for FsBinStore {
-    fn load(&self) -> Result<MemStash, PersistenceError> {
-        MemStash::strict_deserialize_from_file::<U32MAX>(&self.stash)
-            .map_err(PersistenceError::with)
-    }
+    fn load(&self) -> Result<MemStash, PersistenceError> { self.load_component(Component::Stash) }
 
     fn store(&self, object: &MemStash) -> Result<(), PersistenceError> {
-        object
-            .strict_serialize_to_file::<U32MAX>(&self.stash)
-            .map_err(PersistenceError::with)
+        self.store_component(Component::Stash, object)
     }
 }
 
 impl PersistenceProvider<MemState> for FsBinStore {
-    fn load(&self) -> Result<MemState, PersistenceError> {
-        MemState::strict_deserialize_from_file::<U32MAX>(&self.state)
-            .map_err(PersistenceError::with)
-    }
+    fn load(&self) -> Result<MemState, PersistenceError> { self.load_component(Component::State) }
 
     fn store(&self, object: &MemState) -> Result<(), PersistenceError> {
-        object
-            .strict_serialize_to_file::<U32MAX>(&self.state)
-            .map_err(PersistenceError::with)
+        self.store_component(Component::State, object)
     }
 }
 
 impl PersistenceProvider<MemIndex> for FsBinStore {
-    fn load(&self) -> Result<MemIndex, PersistenceError> {
-        MemIndex::strict_deserialize_from_file::<U32MAX>(&self.index)
+    fn load(&self) -> Result<MemIndex, PersistenceError> { self.load_component(Component::Index) }
+
+    fn store(&self, object: &MemIndex) -> Result<(), PersistenceError> {
+        self.store_component(Component::Index, object)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Component {
+    Stash,
+    State,
+    Index,
+}
+
+impl Component {
+    const ALL: [Component; 3] = [Component::Stash, Component::State, Component::Index];
+
+    fn name(self) -> &'static str {
+        match self {
+            Component::Stash => "stash",
+            Component::State => "state",
+            Component::Index => "index",
+        }
+    }
+}
+
+/// Generations of the stock files, kept in the manifest file.
+///
+/// Files are identified by the name of the component or by the name of the
+/// contract state segment prefixed with [`SEGMENT_PREFIX`].
+#[derive(Clone, Eq, PartialEq, Default, Debug)]
+struct Manifest {
+    generation: u64,
+    files: BTreeMap<String, u64>,
+}
+
+impl Manifest {
+    fn get(&self, file: &str) -> Option<u64> { self.files.get(file).copied() }
+
+    fn set(&mut self, file: &str, generation: u64) {
+        self.files.insert(file.to_owned(), generation);
+    }
+}
+
+impl Display for Manifest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "generation {}", self.generation)?;
+        for (file, generation) in &self.files {
+            writeln!(f, "{file} {generation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid stock manifest");
+        let mut manifest = Manifest::default();
+        for line in s.lines() {
+            let (key, value) = line.split_once(' ').ok_or_else(invalid)?;
+            let value = value.parse().map_err(|_| invalid())?;
+            match key {
+                "generation" => manifest.generation = value,
+                _ if Component::ALL.iter().any(|c| c.name() == key)
+                    || key.starts_with(SEGMENT_PREFIX) =>
+                {
+                    manifest.set(key, value)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// Tracks generations of the stock files written by all copies of a
+/// [`FsBinStore`] and holds the lock over the stock directory.
+#[derive(Debug)]
+struct Journal {
+    /// Lock file, holding the advisory lock over the directory while it is
+    /// open.
+    lock: fs::File,
+    dir: PathBuf,
+    /// Directory with the contract state segments.
+    contracts: PathBuf,
+    /// Generations recorded in the manifest file.
+    committed: Manifest,
+    /// Generations written since the last commit.
+    staged: Manifest,
+}
+
+impl Journal {
+    fn open(dir: PathBuf, contracts: PathBuf) -> io::Result<Self> {
+        let mut path = dir.clone();
+        path.push(LOCK_FILE);
+        let mut lock = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        lock.try_lock_exclusive().map_err(|err| {
+            if err.kind() == io::ErrorKind::WouldBlock
+                || err.raw_os_error() == fs4::lock_contended_error().raw_os_error()
+            {
+                io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("stock directory is in use by the process {}", read_pid(&path)),
+                )
+            } else {
+                err
+            }
+        })?;
+        // The process id is informational only, while the lock itself is held by the open file
+        lock.set_len(0)?;
+        writeln!(lock, "{}", process::id())?;
+
+        let mut path = dir.clone();
+        path.push(MANIFEST_FILE);
+        let committed = match fs::read_to_string(&path) {
+            Ok(s) => s.parse()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Manifest::default(),
+            Err(err) => return Err(err),
+        };
+        let journal = Journal {
+            lock,
+            dir,
+            contracts,
+            staged: committed.clone(),
+            committed,
+        };
+        journal.cleanup()?;
+        Ok(journal)
+    }
+
+    /// Returns path and generation of the latest generation of a file.
+    fn current_file(&self, file: &str, base: &Path) -> (PathBuf, Option<u64>) {
+        match self.staged.get(file) {
+            Some(generation) => (generation_path(base, generation), Some(generation)),
+            // Data written before the introduction of the manifest
+            None => (base.to_path_buf(), None),
+        }
+    }
+
+    /// Writes a new generation of a file.
+    ///
+    /// Files of the replaced generations which were not committed are kept
+    /// until the next commit, such that the staged generations can be
+    /// restored.
+    fn stage(
+        &mut self,
+        file: &str,
+        base: &Path,
+        write: impl FnOnce(&Path, u64) -> Result<(), PersistenceError>,
+    ) -> Result<(), PersistenceError> {
+        let generation = self.staged.generation + 1;
+        write_atomic(&generation_path(base, generation), |path| write(path, generation))?;
+        self.staged.set(file, generation);
+        self.staged.generation = generation;
+        Ok(())
+    }
+
+    /// Records staged generations in the manifest and removes the files of
+    /// the replaced generations.
+    fn commit(&mut self) -> Result<(), PersistenceError> {
+        if self.staged == self.committed {
+            return Ok(());
+        }
+        let mut path = self.dir.clone();
+        path.push(MANIFEST_FILE);
+        let manifest = self.staged.to_string();
+        write_atomic(&path, |path| fs::write(path, &manifest).map_err(PersistenceError::with))?;
+        self.committed = self.staged.clone();
+        // The commit is complete once the manifest is written; files which can't be removed now
+        // are removed by the next commit or once the directory is opened again
+        self.cleanup().ok();
+        Ok(())
+    }
+
+    /// Reverts staged generations to the given ones, removing the files of the
+    /// generations staged since then.
+    fn restore(&mut self, staged: Manifest) -> io::Result<()> {
+        self.staged = staged;
+        self.cleanup()
+    }
+
+    /// Removes files of the staged generations which were not committed.
+    fn discard(&mut self) -> io::Result<()> { self.restore(self.committed.clone()) }
+
+    /// Removes files of generations not recorded in the manifest, which may
+    /// remain after a crash or a commit.
+    fn cleanup(&self) -> io::Result<()> {
+        self.cleanup_dir(&self.dir, |name| {
+            Component::ALL
+                .into_iter()
+                .find(|c| c.name() == name)
+                .map(|c| c.name().to_owned())
+        })?;
+        match self.cleanup_dir(&self.contracts, |name| Some(segment_key(name))) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+
+    fn cleanup_dir(&self, dir: &Path, file: impl Fn(&str) -> Option<String>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some(TMP_EXT) {
+                fs::remove_file(path)?;
+                continue;
+            }
+            let Some((file, generation)) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split_once(&format!(".{SEGMENT_EXT}.")))
+                .and_then(|(name, generation)| {
+                    Some((file(name)?, generation.parse::<u64>().ok()?))
+                })
+            else {
+                continue;
+            };
+            if self.committed.get(&file) != Some(generation)
+                && self.staged.get(&file) != Some(generation)
+            {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        // Staged generations may be written by a transaction which failed to persist the stash.
+        // Files which can't be removed now are removed once the directory is opened again.
+        self.discard().ok();
+        // The file is left in place, since removing it may allow another process to lock a new
+        // file while this one is still locked
+        self.lock.set_len(0).ok();
+    }
+}
+
+fn read_pid(path: &Path) -> String {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+        .map(|pid| pid.to_string())
+        .unwrap_or_else(|| s!("<unknown>"))
+}
+
+fn generation_path(base: &Path, generation: u64) -> PathBuf {
+    let mut path = OsString::from(base.as_os_str());
+    path.push(format!(".{generation}"));
+    PathBuf::from(path)
+}
+
+fn read_file<T: StorageData>(path: &Path) -> Result<T, PersistenceError> {
+    let data = fs::read(path).map_err(PersistenceError::with)?;
+    T::from_envelope(data).map_err(PersistenceError::with)
+}
+
+/// Writes a file by writing a temporary file and renaming it, such that a
+/// crash can't leave the file partially written.
+fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&Path) -> Result<(), PersistenceError>,
+) -> Result<(), PersistenceError> {
+    let mut tmp = OsString::from(path.as_os_str());
+    tmp.push(format!(".{TMP_EXT}"));
+    let tmp = PathBuf::from(tmp);
+    write(&tmp)?;
+    let sync = || -> io::Result<()> {
+        fs::File::open(&tmp)?.sync_all()?;
+        fs::rename(&tmp, path)?;
+        // Directory has to be synced for the rename to be durable, which is not supported on
+        // Windows
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            fs::File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    };
+    sync().map_err(PersistenceError::with)
+}
+
+/// File-system storage for a stock, keeping stash, state and index encrypted
+/// with a key derived from a passphrase. Contract state segments of
+/// [`FsState`] are encrypted in the same way.
+///
+/// The files are encrypted and authenticated, such that a wrong passphrase or
+/// modification of a file is detected on load. Each file is authenticated
+/// together with its record in the manifest, so a file can't be replaced with
+/// another component or with another generation of the same component.
+/// Generations of the files and the directory locking work in the same way as
+/// in [`FsBinStore`].
+///
+/// NB: The store keeps the passphrase in memory for as long as it (or any of
+/// its copies) exists; the memory is zeroed once the store is dropped.
+#[derive(Clone, Debug)]
+pub struct FsEncryptedStore {
+    store: FsBinStore,
+    keyring: Arc<RwLock<Keyring>>,
+}
+
+struct Keyring {
+    passphrase: Zeroizing<String>,
+    /// Keys used for writing.
+    current: Arc<Keys>,
+    /// Keys derived for reading data encrypted with other salt or key
+    /// derivation parameters.
+    other: Vec<Arc<Keys>>,
+}
+
+impl Debug for Keyring {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("Keyring { .. }") }
+}
+
+impl Keyring {
+    fn new(passphrase: &str, params: KdfParams) -> Result<Self, EncryptionError> {
+        Ok(Keyring {
+            passphrase: Zeroizing::new(passphrase.to_owned()),
+            current: Arc::new(Keys::with_random_salt(passphrase.as_bytes(), params)?),
+            other: vec![],
+        })
+    }
+
+    fn keys_for(&mut self, header: &Header) -> Result<Arc<Keys>, EncryptionError> {
+        if self.current.matches(header) {
+            return Ok(self.current.clone());
+        }
+        if let Some(keys) = self.other.iter().find(|keys| keys.matches(header)) {
+            return Ok(keys.clone());
+        }
+        // The parameters come from the file and are checked against the limits before the
+        // derivation
+        let keys = Arc::new(Keys::derive(self.passphrase.as_bytes(), header.salt, header.params)?);
+        self.other.push(keys.clone());
+        Ok(keys)
+    }
+}
+
+/// Data authenticated together with an encrypted file, which are the record of
+/// the file in the manifest.
+fn file_context(file: &str, generation: Option<u64>) -> Vec<u8> {
+    format!("{file} {}", generation.unwrap_or_default()).into_bytes()
+}
+
+impl FsEncryptedStore {
+    /// Opens an encrypted stock directory, creating it if necessary.
+    ///
+    /// # Errors
+    ///
+    /// If the directory is already used by another store, returns an error of
+    /// [`io::ErrorKind::WouldBlock`] kind.
+    pub fn new(path: PathBuf, passphrase: &str) -> io::Result<Self> {
+        Self::with_kdf_params(path, passphrase, KdfParams::default())
+    }
+
+    /// Opens an encrypted stock directory, using custom key derivation
+    /// parameters for the data written by the store.
+    ///
+    /// # Errors
+    ///
+    /// If the parameters exceed [`KdfParams::MAX`] or are not valid for
+    /// Argon2id, returns an error of [`io::ErrorKind::InvalidInput`] kind.
+    pub fn with_kdf_params(path: PathBuf, passphrase: &str, params: KdfParams) -> io::Result<Self> {
+        let keyring = Keyring::new(passphrase, params)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        Ok(Self {
+            store: FsBinStore::new(path)?,
+            keyring: Arc::new(RwLock::new(keyring)),
+        })
+    }
+
+    /// Commits generations of the state and index files which were written
+    /// after the last write of the stash.
+    pub fn commit(&self) -> Result<(), PersistenceError> { self.store.commit() }
+
+    /// Re-encrypts all the stock files, including contract state segments, with
+    /// a key derived from a new passphrase.
+    ///
+    /// The files are read from the disk, thus changes not yet stored by a
+    /// stock using this store are not affected. New generations of all the
+    /// files are committed at once, after all of them are written; if the
+    /// operation fails, the files remain encrypted with the old passphrase.
+    pub fn rotate(&self, passphrase: &str) -> Result<(), PersistenceError> {
+        let params = self.keyring().current.params();
+        let keyring = Keyring::new(passphrase, params).map_err(PersistenceError::with)?;
+
+        let mut files = Component::ALL
+            .map(|c| (c.name().to_owned(), self.store.base_path(c).to_path_buf()))
+            .to_vec();
+        let contracts = self
+            .store
+            .segment_contracts()
+            .map_err(PersistenceError::with)?;
+        let segments = contracts
+            .into_iter()
+            .map(|contract_id| format!("{contract_id:x}"))
+            .chain([WITNESSES_SEGMENT.to_owned()]);
+        files.extend(
+            segments.map(|name| (segment_key(&name), segment_base(&self.store.contracts, &name))),
+        );
+
+        let mut journal = self.store.journal();
+        let staged = journal.staged.clone();
+        let res = files
+            .into_iter()
+            .try_for_each(|(file, base)| {
+                let (path, generation) = journal.current_file(&file, &base);
+                // The state file is absent if the state is kept in segments, and vice versa
+                if generation.is_none() && !path.exists() {
+                    return Ok(());
+                }
+                let data = self.read_file(&file, &path, generation)?;
+                journal.stage(&file, &base, |path, generation| {
+                    let data = keyring
+                        .current
+                        .encrypt(&file_context(&file, Some(generation)), &data);
+                    fs::write(path, data).map_err(PersistenceError::with)
+                })
+            })
+            .and_then(|_| journal.commit());
+        if let Err(err) = res {
+            // The files written with the new key are removed, while the files which can't be
+            // removed now are ignored and removed once the directory is opened again
+            journal.restore(staged).ok();
+            return Err(err);
+        }
+        drop(journal);
+
+        *self
+            .keyring
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = keyring;
+        Ok(())
+    }
+
+    fn keyring(&self) -> RwLockReadGuard<'_, Keyring> {
+        self.keyring
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Reads and decrypts a generation of a file.
+    fn read_file(
+        &self,
+        file: &str,
+        path: &Path,
+        generation: Option<u64>,
+    ) -> Result<Zeroizing<Vec<u8>>, PersistenceError> {
+        let data = fs::read(path).map_err(PersistenceError::with)?;
+        let header = Header::read(&data).map_err(PersistenceError::with)?;
+        let keys = self
+            .keyring
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .keys_for(&header)
+            .map_err(PersistenceError::with)?;
+        keys.decrypt(&file_context(file, generation), &data)
+            .map(Zeroizing::new)
             .map_err(PersistenceError::with)
     }
 
+    fn load_file<T: StorageData>(
+        &self,
+        file: &str,
+        path: &Path,
+        generation: Option<u64>,
+    ) -> Result<T, PersistenceError> {
+        let mut data = self.read_file(file, path, generation)?;
+        T::from_envelope(mem::take(&mut *data)).map_err(PersistenceError::with)
+    }
+
+    fn load_component<T: StorageData>(&self, component: Component) -> Result<T, PersistenceError> {
+        let (path, generation) = self.store.current_file(component);
+        self.load_file(component.name(), &path, generation)
+    }
+
+    fn store_component(
+        &self,
+        component: Component,
+        object: &impl StorageData,
+        keys: &Keys,
+    ) -> Result<(), PersistenceError> {
+        let data = Zeroizing::new(object.to_envelope().map_err(PersistenceError::with)?);
+        self.store.write_component(component, |path, generation| {
+            let data = keys.encrypt(&file_context(component.name(), Some(generation)), &data);
+            fs::write(path, data).map_err(PersistenceError::with)
+        })
+    }
+
+    /// Writes a new encrypted generation of a contract state segment, which is
+    /// committed together with the stash.
+    fn write_segment(&self, name: &str, object: &impl StorageData) -> Result<(), PersistenceError> {
+        let data = Zeroizing::new(object.to_envelope().map_err(PersistenceError::with)?);
+        let keys = self.keyring().current.clone();
+        let file = segment_key(name);
+        let base = segment_base(&self.store.contracts, name);
+        self.store
+            .journal()
+            .stage(&file, &base, |path, generation| {
+                let data = keys.encrypt(&file_context(&file, Some(generation)), &data);
+                fs::write(path, data).map_err(PersistenceError::with)
+            })
+    }
+}
+
+impl PersistenceProvider<MemStash> for FsEncryptedStore {
+    fn load(&self) -> Result<MemStash, PersistenceError> { self.load_component(Component::Stash) }
+
+    fn store(&self, object: &MemStash) -> Result<(), PersistenceError> {
+        let keys = self.keyring().current.clone();
+        self.store_component(Component::Stash, object, &keys)
+    }
+}
+
+impl PersistenceProvider<MemState> for FsEncryptedStore {
+    fn load(&self) -> Result<MemState, PersistenceError> { self.load_component(Component::State) }
+
+    fn store(&self, object: &MemState) -> Result<(), PersistenceError> {
+        let keys = self.keyring().current.clone();
+        self.store_component(Component::State, object, &keys)
+    }
+}
+
+impl PersistenceProvider<MemIndex> for FsEncryptedStore {
+    fn load(&self) -> Result<MemIndex, PersistenceError> { self.load_component(Component::Index) }
+
     fn store(&self, object: &MemIndex) -> Result<(), PersistenceError> {
-        object
-            .strict_serialize_to_file::<U32MAX>(&self.index)
-            .map_err(PersistenceError::with)
+        let keys = self.keyring().current.clone();
+        self.store_component(Component::Index, object, &keys)
+    }
+}
+
+/// Store keeping the contract state segments of [`FsState`].
+#[derive(Clone, Debug)]
+enum SegmentStore {
+    Bin(FsBinStore),
+    Encrypted(FsEncryptedStore),
+}
+
+impl SegmentStore {
+    fn bin(&self) -> &FsBinStore {
+        match self {
+            SegmentStore::Bin(store) => store,
+            SegmentStore::Encrypted(store) => &store.store,
+        }
+    }
+
+    fn read_segment<T: StorageData>(
+        &self,
+        name: &str,
+        path: &Path,
+        generation: Option<u64>,
+    ) -> Result<T, PersistenceError> {
+        match self {
+            SegmentStore::Bin(_) => read_file(path),
+            SegmentStore::Encrypted(store) => store.load_file(&segment_key(name), path, generation),
+        }
+    }
+
+    fn write_segment(&self, name: &str, object: &impl StorageData) -> Result<(), PersistenceError> {
+        match self {
+            SegmentStore::Bin(store) => store.write_segment(name, object),
+            SegmentStore::Encrypted(store) => store.write_segment(name, object),
+        }
+    }
+
+    fn load_state(self) -> Result<FsState, PersistenceError> {
+        let (path, generation) = self
+            .bin()
+            .segment_file(WITNESSES_SEGMENT)
+            .ok_or_else(|| PersistenceError::with(io::Error::from(io::ErrorKind::NotFound)))?;
+        let loaded = self.read_segment::<MemState>(WITNESSES_SEGMENT, &path, generation)?;
+        let segments = self
+            .bin()
+            .segment_contracts()
+            .map_err(PersistenceError::with)?
+            .into_iter()
+            .map(|contract_id| (contract_id, OnceLock::new()))
+            .collect();
+        Ok(FsState {
+            persistence: None,
+            loaded,
+            store: Some(self),
+            segments,
+            touched: empty!(),
+        })
+    }
+
+    fn store_state(&self, object: &FsState) -> Result<(), PersistenceError> {
+        let store = self.bin();
+        fs::create_dir_all(&store.contracts).map_err(PersistenceError::with)?;
+        self.write_segment(WITNESSES_SEGMENT, &object.loaded.witnesses_only())?;
+
+        // Contracts which were not modified are written only if the segment is absent, which
+        // happens when the state is persisted to a new location.
+        for (contract_id, contract) in object.loaded.debug_contracts() {
+            let name = format!("{contract_id:x}");
+            if object.touched.contains(contract_id) || store.segment_path(&name).is_none() {
+                self.write_segment(&name, contract)?;
+            }
+        }
+        for contract_id in object.segments.keys() {
+            let name = format!("{contract_id:x}");
+            if object.loaded.debug_contracts().contains_key(contract_id)
+                || store.segment_path(&name).is_some()
+            {
+                continue;
+            }
+            let contract = object
+                .segment(*contract_id)
+                .map_err(PersistenceError::with)?;
+            self.write_segment(&name, contract)?;
+        }
+        Ok(())
+    }
+}
+
+impl PersistenceProvider<FsState> for FsBinStore {
+    fn load(&self) -> Result<FsState, PersistenceError> {
+        SegmentStore::Bin(self.clone()).load_state()
+    }
+
+    fn store(&self, object: &FsState) -> Result<(), PersistenceError> {
+        SegmentStore::Bin(self.clone()).store_state(object)
+    }
+}
+
+impl PersistenceProvider<FsState> for FsEncryptedStore {
+    fn load(&self) -> Result<FsState, PersistenceError> {
+        SegmentStore::Encrypted(self.clone()).load_state()
+    }
+
+    fn store(&self, object: &FsState) -> Result<(), PersistenceError> {
+        SegmentStore::Encrypted(self.clone()).store_state(object)
+    }
+}
+
+fn segment_key(name: &str) -> String { format!("{SEGMENT_PREFIX}{name}") }
+
+fn segment_base(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    path.push(format!("{name}.{SEGMENT_EXT}"));
+    path
+}
+
+/// Errors reading contract state persisted by [`FsState`].
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum FsStateError {
+    /// unable to load state of contract {0} from '{1}'. Details: {2}
+    Load(ContractId, String, String),
+
+    #[from]
+    #[display(inner)]
+    Inconsistency(StateInconsistency),
+}
+
+/// Contract state persisted to a file system, which keeps the state of each
+/// contract in a separate file (segment).
+///
+/// On load only the ordering of the witnesses is read; the state of a contract
+/// is read from its segment once the contract is accessed for the first time.
+/// On save only the segments of the contracts which were registered or
+/// updated are written.
+///
+/// NB: [`StateWriteProvider::prune_spent`] has to access the state of all
+/// contracts and thus loads all the segments.
+///
+/// Segments are written as new generations of the files, which become
+/// effective together with the other stock files (see [`FsBinStore`]).
+#[derive(Debug)]
+pub struct FsState {
+    persistence: Option<Persistence<Self>>,
+    /// Witness ordering and the state of contracts which were registered or
+    /// updated since the load.
+    loaded: MemState,
+    /// Store with the contract state segments.
+    store: Option<SegmentStore>,
+    /// Contract state segments which were not modified since the load; they
+    /// are read from the directory on the first access.
+    segments: BTreeMap<ContractId, OnceLock<MemContractState>>,
+    /// Contracts modified since the last commit to the persistence.
+    touched: BTreeSet<ContractId>,
+}
+
+impl FsState {
+    pub fn in_memory() -> Self {
+        Self {
+            persistence: None,
+            loaded: MemState::in_memory(),
+            store: None,
+            segments: empty!(),
+            touched: empty!(),
+        }
+    }
+
+    /// Returns state of a contract from a segment, reading it from the
+    /// file system if it was not accessed before.
+    fn segment(&self, contract_id: ContractId) -> Result<&MemContractState, FsStateError> {
+        let lock = self
+            .segments
+            .get(&contract_id)
+            .ok_or(StateInconsistency::UnknownContract(contract_id))?;
+        if lock.get().is_none() {
+            let contract = self.read_segment(contract_id)?;
+            // The value may be already set only if the segment was read concurrently, in which
+            // case both values are equal.
+            let _ = lock.set(contract);
+        }
+        Ok(lock.get().expect("segment state is just set"))
+    }
+
+    fn read_segment(&self, contract_id: ContractId) -> Result<MemContractState, FsStateError> {
+        let name = format!("{contract_id:x}");
+        let store = self
+            .store
+            .as_ref()
+            .ok_or(StateInconsistency::UnknownContract(contract_id))?;
+        let (path, generation) = store
+            .bin()
+            .segment_file(&name)
+            .ok_or(StateInconsistency::UnknownContract(contract_id))?;
+        let mut contract = store
+            .read_segment::<MemContractState>(&name, &path, generation)
+            .map_err(|e| {
+                FsStateError::Load(contract_id, path.display().to_string(), e.to_string())
+            })?;
+        self.loaded.restore_unspent(&mut contract);
+        Ok(contract)
+    }
+
+    /// Moves state of a contract from its segment to the loaded contracts,
+    /// such that it can be modified.
+    fn load_segment(&mut self, contract_id: ContractId) -> Result<(), MemError> {
+        if self.loaded.debug_contracts().contains_key(&contract_id) {
+            return Ok(());
+        }
+        // NB: The segment is left empty, so if the transaction is rolled back the contract state
+        // will be read from the file system again.
+        let Some(lock) = self.segments.get_mut(&contract_id) else {
+            return Ok(());
+        };
+        let contract = match lock.take() {
+            Some(contract) => contract,
+            None => self
+                .read_segment(contract_id)
+                .map_err(|e| MemError::Persistence(PersistenceError::with(e)))?,
+        };
+        self.loaded.insert_contract(contract)?;
+        Ok(())
+    }
+}
+
+impl From<MemState> for FsState {
+    fn from(mut state: MemState) -> Self {
+        state.as_mut_persistence().take();
+        let touched = state.debug_contracts().keys().copied().collect();
+        Self {
+            persistence: None,
+            loaded: state,
+            store: None,
+            segments: empty!(),
+            touched,
+        }
+    }
+}
+
+impl CloneNoPersistence for FsState {
+    fn clone_no_persistence(&self) -> Self {
+        Self {
+            persistence: None,
+            loaded: self.loaded.clone_no_persistence(),
+            store: self.store.clone(),
+            segments: self.segments.clone(),
+            touched: self.touched.clone(),
+        }
+    }
+}
+
+impl Persisting for FsState {
+    #[inline]
+    fn persistence(&self) -> Option<&Persistence<Self>> { self.persistence.as_ref() }
+    #[inline]
+    fn persistence_mut(&mut self) -> Option<&mut Persistence<Self>> { self.persistence.as_mut() }
+    #[inline]
+    fn as_mut_persistence(&mut self) -> &mut Option<Persistence<Self>> { &mut self.persistence }
+}
+
+impl StoreTransaction for FsState {
+    type TransactionErr = MemError;
+    #[inline]
+    fn begin_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.loaded.begin_transaction()?;
+        self.mark_dirty();
+        Ok(())
+    }
+    #[inline]
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.store()?;
+        self.loaded.prepare_transaction()
+    }
+    #[inline]
+    fn finish_transaction(&mut self) {
+        self.loaded.finish_transaction();
+        // `Persisting::store` writes the data only if the autosave is enabled
+        if self.is_persisted() && self.is_autosave() {
+            self.touched.clear();
+        }
+    }
+    #[inline]
+    fn rollback_transaction(&mut self) {
+        self.loaded.rollback_transaction();
+        // Segments touched within the transaction are rewritten with the restored data
+        self.mark_dirty();
+    }
+}
+
+impl StateProvider for FsState {}
+
+impl StateReadProvider for FsState {
+    type ContractRead<'a> = MemContract<&'a MemContractState>;
+    type Error = FsStateError;
+
+    fn contract_state(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Self::ContractRead<'_>, Self::Error> {
+        if self.loaded.debug_contracts().contains_key(&contract_id) {
+            return Ok(self.loaded.contract_state(contract_id)?);
+        }
+        Ok(self.loaded.filtered(self.segment(contract_id)?))
+    }
+
+    fn contract_state_at(
+        &self,
+        contract_id: ContractId,
+        point: HistoryPoint,
+    ) -> Result<Self::ContractRead<'_>, Self::Error> {
+        if self.loaded.debug_contracts().contains_key(&contract_id) {
+            return Ok(self.loaded.contract_state_at(contract_id, point)?);
+        }
+        Ok(self.loaded.filtered_at(self.segment(contract_id)?, point))
+    }
+
+    fn is_valid_witness(&self, witness_id: XWitnessId) -> Result<bool, Self::Error> {
+        Ok(self.loaded.is_valid_witness(witness_id)?)
+    }
+}
+
+impl StateWriteProvider for FsState {
+    type ContractWrite<'a> = MemContractWriter<'a>;
+    type Error = MemError;
+
+    fn register_contract(
+        &mut self,
+        schema: &Schema,
+        genesis: &Genesis,
+    ) -> Result<Self::ContractWrite<'_>, Self::Error> {
+        let contract_id = genesis.contract_id();
+        self.load_segment(contract_id)?;
+        self.touched.insert(contract_id);
+        self.loaded.register_contract(schema, genesis)
+    }
+
+    fn update_contract(
+        &mut self,
+        contract_id: ContractId,
+    ) -> Result<Option<Self::ContractWrite<'_>>, Self::Error> {
+        self.load_segment(contract_id)?;
+        if self.loaded.debug_contracts().contains_key(&contract_id) {
+            self.touched.insert(contract_id);
+        }
+        self.loaded.update_contract(contract_id)
+    }
+
+    fn update_witnesses(
+        &mut self,
+        resolver: impl ResolveWitness,
+        after_height: u32,
+    ) -> Result<UpdateRes, Self::Error> {
+        self.mark_dirty();
+        let res = self.loaded.update_witnesses(resolver, after_height)?;
+        // Segments read before the update may contain pruned outputs which are no longer spent
+        for contract in self.segments.values_mut().filter_map(OnceLock::get_mut) {
+            self.loaded.restore_unspent(contract);
+        }
+        self.store()?;
+        Ok(res)
+    }
+
+    fn prune_spent(&mut self) -> Result<usize, Self::Error> {
+        let contract_ids = self.segments.keys().copied().collect::<Vec<_>>();
+        for contract_id in contract_ids {
+            self.load_segment(contract_id)?;
+        }
+        self.touched
+            .extend(self.loaded.debug_contracts().keys().copied());
+        self.mark_dirty();
+        let count = self.loaded.prune_spent()?;
+        self.store()?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, mem};
+
+    use strict_encoding::StrictDumb;
+
+    use super::*;
+    use crate::persistence::ContractStateRead;
+
+    /// Simulates a crash of the process using the store: the files are not
+    /// committed, while the directory lock is released by the operating system.
+    fn crash(store: FsBinStore) {
+        let file = fs::File::open(env::current_exe().unwrap()).unwrap();
+        drop(mem::replace(&mut store.journal().lock, file));
+        mem::forget(store);
+    }
+
+    #[test]
+    fn test_generations() {
+        let mut dir = env::temp_dir();
+        dir.push(format!("rgb-std-fs-generations-{}", std::process::id()));
+        let store = FsBinStore::new(dir.clone()).unwrap();
+        assert_eq!(FsBinStore::new(dir.clone()).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+        let mut stock = Stock::in_memory();
+        stock.make_persistent(store.clone(), true).unwrap();
+        let committed = store.journal().committed.clone();
+        assert_eq!(committed, Manifest {
+            generation: 3,
+            files: bmap! { s!("stash") => 3, s!("state") => 2, s!("index") => 1 },
+        });
+        assert!(store.current_path(Component::Index).exists());
+
+        // A crash after a write of the state file must not affect the stock
+        PersistenceProvider::<MemState>::store(&store, &MemState::in_memory()).unwrap();
+        let staged = generation_path(&store.state, 4);
+        assert!(staged.exists());
+        mem::forget(stock);
+        crash(store);
+
+        // The lock file left by the crashed process doesn't block the directory
+        let mut lock = dir.clone();
+        lock.push(LOCK_FILE);
+        assert!(lock.exists());
+        let store = FsBinStore::new(dir.clone()).unwrap();
+        assert_eq!(store.journal().committed, committed);
+        assert!(!staged.exists());
+        Stock::<MemStash, MemState, MemIndex>::load(store.clone(), true).unwrap();
+
+        // Drop of the last copy of the store discards the uncommitted state and releases the lock
+        PersistenceProvider::<MemState>::store(&store, &MemState::in_memory()).unwrap();
+        let staged = generation_path(&store.state, 4);
+        assert!(staged.exists());
+        drop(store);
+        assert!(!staged.exists());
+        let store = FsBinStore::new(dir.clone()).unwrap();
+        assert_eq!(store.journal().committed, committed);
+
+        // An explicit commit makes the state effective
+        PersistenceProvider::<MemState>::store(&store, &MemState::in_memory()).unwrap();
+        store.commit().unwrap();
+        drop(store);
+        let store = FsBinStore::new(dir.clone()).unwrap();
+        assert_eq!(store.journal().committed.get("state"), Some(4));
+        assert!(!generation_path(&store.state, 2).exists());
+        drop(store);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_store() {
+        fn load_err(store: FsEncryptedStore) -> EncryptionError {
+            let err = Stock::<MemStash, MemState, MemIndex>::load(store, true).unwrap_err();
+            *err.0.downcast::<EncryptionError>().unwrap()
+        }
+
+        let mut dir = env::temp_dir();
+        dir.push(format!("rgb-std-fs-encrypted-{}", std::process::id()));
+        let params = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let open = |passphrase| {
+            FsEncryptedStore::with_kdf_params(dir.clone(), passphrase, params).unwrap()
+        };
+
+        let store = open("old");
+        let mut stock = Stock::in_memory();
+        stock.make_persistent(store.clone(), true).unwrap();
+        drop(stock);
+        let path = store.store.current_path(Component::Stash);
+        assert!(Header::read(&fs::read(&path).unwrap()).is_ok());
+        Stock::<MemStash, MemState, MemIndex>::load(store.clone(), true).unwrap();
+        drop(store);
+
+        assert_eq!(load_err(open("wrong")), EncryptionError::InvalidPassphrase);
+
+        let store = open("old");
+        store.rotate("new").unwrap();
+        drop(store);
+        assert_eq!(load_err(open("old")), EncryptionError::InvalidPassphrase);
+        let store = open("new");
+        Stock::<MemStash, MemState, MemIndex>::load(store.clone(), true).unwrap();
+        let (path, generation) = store.store.current_file(Component::Stash);
+        let generation = generation.unwrap();
+        drop(store);
+        let original = fs::read(&path).unwrap();
+        let mut manifest = dir.clone();
+        manifest.push(MANIFEST_FILE);
+        let committed = fs::read_to_string(&manifest).unwrap();
+
+        // A file can't be presented as another generation
+        let replaced = generation_path(&path.with_extension(""), generation + 10);
+        fs::copy(&path, &replaced).unwrap();
+        let record = format!("stash {generation}\n");
+        let replacement = format!("stash {}\n", generation + 10);
+        fs::write(&manifest, committed.replace(&record, &replacement)).unwrap();
+        assert_eq!(load_err(open("new")), EncryptionError::Tampered);
+        fs::write(&manifest, &committed).unwrap();
+
+        // Key derivation parameters from the file are limited
+        let mut data = original.clone();
+        data[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, data).unwrap();
+        assert!(matches!(load_err(open("new")), EncryptionError::UnsupportedKdf(_)));
+
+        let mut data = original;
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&path, data).unwrap();
+        assert_eq!(load_err(open("new")), EncryptionError::Tampered);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fs_state_segments() {
+        let mut dir = env::temp_dir();
+        dir.push(format!("rgb-std-fs-state-{}", std::process::id()));
+        let store = FsBinStore::new(dir.clone()).unwrap();
+
+        let genesis = Genesis::strict_dumb();
+        let contract_id = genesis.contract_id();
+        let mut state = FsState::in_memory();
+        state
+            .register_contract(&Schema::strict_dumb(), &genesis)
+            .unwrap();
+        state.make_persistent(store.clone(), true).unwrap();
+        store.commit().unwrap();
+        let segment = store.segment_path(&format!("{contract_id:x}")).unwrap();
+        assert!(segment.exists());
+
+        let state = FsState::load(store.clone(), true).unwrap();
+        assert!(state.loaded.debug_contracts().is_empty());
+        assert!(state.segments[&contract_id].get().is_none());
+        let contract = state.contract_state(contract_id).unwrap();
+        assert_eq!(contract.rights_all().count(), 0);
+        assert!(state.segments[&contract_id].get().is_some());
+        assert_eq!(
+            state.contract_state(ContractId::strict_dumb()).unwrap_err(),
+            FsStateError::Inconsistency(StateInconsistency::UnknownContract(
+                ContractId::strict_dumb()
+            ))
+        );
+
+        let mut state = state;
+        state.begin_transaction().unwrap();
+        state.update_contract(contract_id).unwrap().unwrap();
+        assert!(state.touched.contains(&contract_id));
+        state.commit_transaction().unwrap();
+        assert!(state.touched.is_empty());
+        assert!(state.contract_state(contract_id).is_ok());
+
+        // Segments are written as new generations, which are discarded after a crash unless
+        // committed with the stash
+        let updated = store.segment_path(&format!("{contract_id:x}")).unwrap();
+        assert_ne!(updated, segment);
+        assert!(segment.exists());
+        mem::forget(state);
+        crash(store);
+        let store = FsBinStore::new(dir.clone()).unwrap();
+        assert!(!updated.exists());
+        assert_eq!(store.segment_path(&format!("{contract_id:x}")), Some(segment));
+        let state = FsState::load(store.clone(), true).unwrap();
+        assert!(state.contract_state(contract_id).is_ok());
+        drop(state);
+        drop(store);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_segments() {
+        type FsStock = Stock<MemStash, FsState, MemIndex>;
+
+        let mut dir = env::temp_dir();
+        dir.push(format!("rgb-std-fs-encrypted-segments-{}", std::process::id()));
+        let params = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let open = |passphrase| {
+            FsEncryptedStore::with_kdf_params(dir.clone(), passphrase, params).unwrap()
+        };
+
+        let genesis = Genesis::strict_dumb();
+        let contract_id = genesis.contract_id();
+        let mut stock =
+            FsStock::with(MemStash::in_memory(), FsState::in_memory(), MemIndex::in_memory());
+        stock
+            .as_state_provider_mut()
+            .register_contract(&Schema::strict_dumb(), &genesis)
+            .unwrap();
+        let store = open("old");
+        stock.make_persistent(store.clone(), true).unwrap();
+        drop(stock);
+
+        // Segments don't contain plaintext data
+        let segments = fs::read_dir(&store.store.contracts)
+            .unwrap()
+            .map(|entry| fs::read(entry.unwrap().path()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(segments.len(), 2);
+        for data in segments {
+            assert!(Header::read(&data).is_ok());
+            assert!(!data.windows(8).any(|w| w == b"RGBSTORE"));
+            assert!(!data.windows(32).any(|w| w == contract_id.as_slice()));
+        }
+        let stock = FsStock::load(store.clone(), true).unwrap();
+        assert!(stock
+            .as_state_provider()
+            .contract_state(contract_id)
+            .is_ok());
+        drop(stock);
+        drop(store);
+
+        // Failed rotation keeps all the files encrypted with the old passphrase
+        let store = open("old");
+        let generation = store.store.journal().staged.generation;
+        // The witness segment is re-encrypted last, after the stash, index and contract segment
+        let mut blocker = OsString::from(
+            generation_path(
+                &segment_base(&store.store.contracts, WITNESSES_SEGMENT),
+                generation + 4,
+            )
+            .as_os_str(),
+        );
+        blocker.push(format!(".{TMP_EXT}"));
+        fs::create_dir(&blocker).unwrap();
+        assert!(store.rotate("new").is_err());
+        fs::remove_dir(&blocker).unwrap();
+        let journal = store.store.journal();
+        assert_eq!(journal.staged, journal.committed);
+        drop(journal);
+        let stock = FsStock::load(store.clone(), true).unwrap();
+        assert!(stock
+            .as_state_provider()
+            .contract_state(contract_id)
+            .is_ok());
+        drop(stock);
+        drop(store);
+        assert!(FsStock::load(open("new"), true).is_err());
+
+        // Successful rotation re-encrypts the segments
+        let store = open("old");
+        store.rotate("new").unwrap();
+        drop(store);
+        assert!(FsStock::load(open("old"), true).is_err());
+        let store = open("new");
+        let stock = FsStock::load(store.clone(), true).unwrap();
+        assert!(stock
+            .as_state_provider()
+            .contract_state(contract_id)
+            .is_ok());
+        drop(stock);
+        drop(store);
+
+        fs::remove_dir_all(dir).unwrap();
     }
 }
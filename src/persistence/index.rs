@@ -162,10 +162,7 @@ impl<P: IndexProvider> Index<P> {
     ) -> Result<(), IndexError<P>> {
         let contract_id = consignment.contract_id();
 
-        self.provider
-            .register_contract(contract_id)
-            .map_err(IndexError::WriteProvider)?;
-        self.index_genesis(contract_id, consignment.genesis())?;
+        self.index_contract(consignment.genesis())?;
         for extension in consignment.extensions() {
             self.index_extension(contract_id, extension)?;
         }
@@ -183,6 +180,14 @@ impl<P: IndexProvider> Index<P> {
         Ok(())
     }
 
+    pub(super) fn index_contract(&mut self, genesis: &Genesis) -> Result<(), IndexError<P>> {
+        let contract_id = genesis.contract_id();
+        self.provider
+            .register_contract(contract_id)
+            .map_err(IndexError::WriteProvider)?;
+        self.index_genesis(contract_id, genesis)
+    }
+
     fn index_genesis(&mut self, id: ContractId, genesis: &Genesis) -> Result<(), IndexError<P>> {
         let opid = genesis.id();
         for (type_id, assign) in genesis.assignments.iter() {
@@ -208,7 +213,7 @@ impl<P: IndexProvider> Index<P> {
         Ok(())
     }
 
-    fn index_extension(
+    pub(crate) fn index_extension(
         &mut self,
         id: ContractId,
         extension: &Extension,
@@ -349,12 +354,14 @@ impl<P: IndexProvider> StoreTransaction for Index<P> {
             .map_err(IndexError::WriteProvider)
     }
 
-    fn commit_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
         self.provider
-            .commit_transaction()
+            .prepare_transaction()
             .map_err(IndexError::WriteProvider)
     }
 
+    fn finish_transaction(&mut self) { self.provider.finish_transaction() }
+
     fn rollback_transaction(&mut self) { self.provider.rollback_transaction() }
 }
 
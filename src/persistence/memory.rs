@@ -52,11 +52,11 @@ use strict_encoding::{StrictDeserialize, StrictSerialize};
 use strict_types::TypeSystem;
 
 use super::{
-    ContractIfaceError, ContractStateRead, ContractStateWrite, IndexInconsistency, IndexProvider,
-    IndexReadError, IndexReadProvider, IndexWriteError, IndexWriteProvider, SchemaIfaces,
-    StashInconsistency, StashProvider, StashProviderError, StashReadProvider, StashWriteProvider,
-    StateInconsistency, StateProvider, StateReadProvider, StateWriteProvider, StoreTransaction,
-    UpdateRes,
+    ContractIfaceError, ContractStateRead, ContractStateWrite, HistoryPoint, IndexInconsistency,
+    IndexProvider, IndexReadError, IndexReadProvider, IndexWriteError, IndexWriteProvider,
+    LegacyMemStash, SchemaIfaces, StashInconsistency, StashProvider, StashProviderError,
+    StashReadProvider, StashWriteProvider, StateInconsistency, StateProvider, StateReadProvider,
+    StateWriteProvider, StoreTransaction, UpdateRes,
 };
 use crate::containers::{
     AnchorSet, ContentId, ContentRef, ContentSigs, SealWitness, SigBlob, Supplement, TrustLevel,
@@ -80,6 +80,11 @@ pub enum MemError {
 //////////
 
 /// Hoard is an in-memory stash useful for WASM implementations.
+///
+/// NB: Transactions are rolled back by restoring a copy of the whole stash,
+/// taken by [`StoreTransaction::begin_transaction`]. Thus, each transaction
+/// costs time and memory proportional to the size of the stash, independently
+/// of the amount of the data it changes.
 #[derive(Getters, Debug)]
 #[getter(prefix = "debug_")]
 #[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
@@ -88,20 +93,25 @@ pub struct MemStash {
     #[getter(skip)]
     #[strict_type(skip)]
     persistence: Option<Persistence<Self>>,
-
-    schemata: TinyOrdMap<SchemaId, SchemaIfaces>,
-    ifaces: TinyOrdMap<IfaceId, Iface>,
-    geneses: TinyOrdMap<ContractId, Genesis>,
-    suppl: TinyOrdMap<ContentRef, TinyOrdSet<Supplement>>,
-    bundles: LargeOrdMap<BundleId, TransitionBundle>,
-    extensions: LargeOrdMap<OpId, Extension>,
-    witnesses: LargeOrdMap<XWitnessId, SealWitness>,
-    attachments: SmallOrdMap<AttachId, MediumBlob>,
-    secret_seals: MediumOrdSet<XChain<GraphSeal>>,
-    type_system: TypeSystem,
-    identities: SmallOrdMap<Identity, TrustLevel>,
-    libs: SmallOrdMap<LibId, Lib>,
-    sigs: SmallOrdMap<ContentId, ContentSigs>,
+    /// Copy of the stash taken at the beginning of a transaction, which is
+    /// restored on its rollback.
+    #[getter(skip)]
+    #[strict_type(skip)]
+    snapshot: Option<Box<Self>>,
+
+    pub(super) schemata: SmallOrdMap<SchemaId, SchemaIfaces>,
+    pub(super) ifaces: SmallOrdMap<IfaceId, Iface>,
+    pub(super) geneses: MediumOrdMap<ContractId, Genesis>,
+    pub(super) suppl: MediumOrdMap<ContentRef, TinyOrdSet<Supplement>>,
+    pub(super) bundles: LargeOrdMap<BundleId, TransitionBundle>,
+    pub(super) extensions: LargeOrdMap<OpId, Extension>,
+    pub(super) witnesses: LargeOrdMap<XWitnessId, SealWitness>,
+    pub(super) attachments: MediumOrdMap<AttachId, MediumBlob>,
+    pub(super) secret_seals: MediumOrdSet<XChain<GraphSeal>>,
+    pub(super) type_system: TypeSystem,
+    pub(super) identities: SmallOrdMap<Identity, TrustLevel>,
+    pub(super) libs: SmallOrdMap<LibId, Lib>,
+    pub(super) sigs: MediumOrdMap<ContentId, ContentSigs>,
 }
 
 impl StrictSerialize for MemStash {}
@@ -111,6 +121,7 @@ impl MemStash {
     pub fn in_memory() -> Self {
         Self {
             persistence: none!(),
+            snapshot: None,
             schemata: empty!(),
             ifaces: empty!(),
             geneses: empty!(),
@@ -132,6 +143,7 @@ impl CloneNoPersistence for MemStash {
     fn clone_no_persistence(&self) -> Self {
         Self {
             persistence: None,
+            snapshot: None,
             schemata: self.schemata.clone(),
             ifaces: self.ifaces.clone(),
             geneses: self.geneses.clone(),
@@ -149,6 +161,27 @@ impl CloneNoPersistence for MemStash {
     }
 }
 
+impl From<LegacyMemStash> for MemStash {
+    fn from(legacy: LegacyMemStash) -> Self {
+        // All collections of the current layout have the same or larger bounds
+        let mut stash = MemStash::in_memory();
+        stash.schemata = Confined::from_checked(legacy.schemata.release());
+        stash.ifaces = Confined::from_checked(legacy.ifaces.release());
+        stash.geneses = Confined::from_checked(legacy.geneses.release());
+        stash.suppl = Confined::from_checked(legacy.suppl.release());
+        stash.bundles = legacy.bundles;
+        stash.extensions = legacy.extensions;
+        stash.witnesses = legacy.witnesses;
+        stash.attachments = Confined::from_checked(legacy.attachments.release());
+        stash.secret_seals = legacy.secret_seals;
+        stash.type_system = legacy.type_system;
+        stash.identities = legacy.identities;
+        stash.libs = legacy.libs;
+        stash.sigs = Confined::from_checked(legacy.sigs.release());
+        stash
+    }
+}
+
 impl Persisting for MemStash {
     #[inline]
     fn persistence(&self) -> Option<&Persistence<Self>> { self.persistence.as_ref() }
@@ -162,13 +195,27 @@ impl StoreTransaction for MemStash {
     type TransactionErr = MemError;
     #[inline]
     fn begin_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.snapshot = Some(Box::new(self.clone_no_persistence()));
         self.mark_dirty();
         Ok(())
     }
     #[inline]
-    fn commit_transaction(&mut self) -> Result<(), Self::TransactionErr> { Ok(self.store()?) }
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.store()?;
+        Ok(())
+    }
+    #[inline]
+    fn finish_transaction(&mut self) { self.snapshot = None; }
     #[inline]
-    fn rollback_transaction(&mut self) { unreachable!() }
+    fn rollback_transaction(&mut self) {
+        if let Some(snapshot) = self.snapshot.take() {
+            let persistence = self.persistence.take();
+            *self = *snapshot;
+            self.persistence = persistence;
+            // Overwrites the changes which might be already persisted
+            self.mark_dirty();
+        }
+    }
 }
 
 impl StashProvider for MemStash {}
@@ -323,6 +370,10 @@ impl StashReadProvider for MemStash {
             .ok_or(StashInconsistency::WitnessAbsent(witness_id).into())
     }
 
+    fn attachment(&self, id: AttachId) -> Result<Option<&MediumBlob>, Self::Error> {
+        Ok(self.attachments.get(&id))
+    }
+
     fn taprets(&self) -> Result<impl Iterator<Item = (XWitnessId, TapretCommitment)>, Self::Error> {
         Ok(self
             .witnesses
@@ -389,11 +440,7 @@ impl StashWriteProvider for MemStash {
         Ok(!present)
     }
 
-    fn set_trust(
-        &mut self,
-        identity: Identity,
-        trust: TrustLevel,
-    ) -> Result<(), confinement::Error> {
+    fn set_trust(&mut self, identity: Identity, trust: TrustLevel) -> Result<(), Self::Error> {
         self.identities.insert(identity, trust)?;
         Ok(())
     }
@@ -484,6 +531,11 @@ impl StashWriteProvider for MemStash {
 // STATE
 //////////
 
+/// In-memory state of all contracts.
+///
+/// NB: Like with [`MemStash`], a transaction copies the state of all contracts
+/// for its rollback, so its cost grows with the number and size of the
+/// contracts.
 #[derive(Getters, Debug)]
 #[getter(prefix = "debug_")]
 #[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
@@ -492,9 +544,14 @@ pub struct MemState {
     #[getter(skip)]
     #[strict_type(skip)]
     persistence: Option<Persistence<Self>>,
+    /// Copy of the state of all contracts taken at the beginning of a
+    /// transaction, which is restored on its rollback.
+    #[getter(skip)]
+    #[strict_type(skip)]
+    snapshot: Option<Box<Self>>,
 
-    witnesses: LargeOrdMap<XWitnessId, WitnessOrd>,
-    contracts: TinyOrdMap<ContractId, MemContractState>,
+    pub(super) witnesses: LargeOrdMap<XWitnessId, WitnessOrd>,
+    pub(super) contracts: MediumOrdMap<ContractId, MemContractState>,
 }
 
 impl StrictSerialize for MemState {}
@@ -504,6 +561,7 @@ impl MemState {
     pub fn in_memory() -> Self {
         Self {
             persistence: none!(),
+            snapshot: None,
             witnesses: empty!(),
             contracts: empty!(),
         }
@@ -514,6 +572,7 @@ impl CloneNoPersistence for MemState {
     fn clone_no_persistence(&self) -> Self {
         Self {
             persistence: None,
+            snapshot: None,
             witnesses: self.witnesses.clone(),
             contracts: self.contracts.clone(),
         }
@@ -533,13 +592,27 @@ impl StoreTransaction for MemState {
     type TransactionErr = MemError;
     #[inline]
     fn begin_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.snapshot = Some(Box::new(self.clone_no_persistence()));
         self.mark_dirty();
         Ok(())
     }
     #[inline]
-    fn commit_transaction(&mut self) -> Result<(), Self::TransactionErr> { Ok(self.store()?) }
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.store()?;
+        Ok(())
+    }
+    #[inline]
+    fn finish_transaction(&mut self) { self.snapshot = None; }
     #[inline]
-    fn rollback_transaction(&mut self) { unreachable!() }
+    fn rollback_transaction(&mut self) {
+        if let Some(snapshot) = self.snapshot.take() {
+            let persistence = self.persistence.take();
+            *self = *snapshot;
+            self.persistence = persistence;
+            // Overwrites the changes which might be already persisted
+            self.mark_dirty();
+        }
+    }
 }
 
 impl StateProvider for MemState {}
@@ -556,9 +629,60 @@ impl StateReadProvider for MemState {
             .contracts
             .get(&contract_id)
             .ok_or(StateInconsistency::UnknownContract(contract_id))?;
+        Ok(self.filtered(unfiltered))
+    }
+
+    fn contract_state_at(
+        &self,
+        contract_id: ContractId,
+        point: HistoryPoint,
+    ) -> Result<Self::ContractRead<'_>, Self::Error> {
+        let unfiltered = self
+            .contracts
+            .get(&contract_id)
+            .ok_or(StateInconsistency::UnknownContract(contract_id))?;
+        Ok(self.filtered_at(unfiltered, point))
+    }
+
+    fn is_valid_witness(&self, witness_id: XWitnessId) -> Result<bool, Self::Error> {
+        let ord = self
+            .witnesses
+            .get(&witness_id)
+            .ok_or(StateInconsistency::AbsentWitness(witness_id))?;
+        Ok(ord.is_valid())
+    }
+}
+
+impl MemState {
+    /// Constructs contract state filtered by the ordering of its witnesses
+    /// known to this state.
+    pub(super) fn filtered<'a>(
+        &self,
+        unfiltered: &'a MemContractState,
+    ) -> MemContract<&'a MemContractState> {
+        self.filtered_by(unfiltered, None)
+    }
+
+    /// Constructs contract state as it was at the given point of the
+    /// blockchain history, taking into account only witnesses mined up to
+    /// that point.
+    pub(super) fn filtered_at<'a>(
+        &self,
+        unfiltered: &'a MemContractState,
+        point: HistoryPoint,
+    ) -> MemContract<&'a MemContractState> {
+        self.filtered_by(unfiltered, Some(&point))
+    }
+
+    fn filtered_by<'a>(
+        &self,
+        unfiltered: &'a MemContractState,
+        point: Option<&HistoryPoint>,
+    ) -> MemContract<&'a MemContractState> {
         let filter = self
             .witnesses
             .iter()
+            .filter(|(id, ord)| point.map_or(true, |point| point.includes(**id, **ord)))
             .filter(|(id, _)| {
                 let id = Some(**id);
                 unfiltered
@@ -570,18 +694,53 @@ impl StateReadProvider for MemState {
                     || unfiltered.fungibles.iter().any(|a| a.witness == id)
                     || unfiltered.data.iter().any(|a| a.witness == id)
                     || unfiltered.attach.iter().any(|a| a.witness == id)
+                    || unfiltered
+                        .spent
+                        .values()
+                        .flat_map(|ops| ops.iter())
+                        .any(|op| Some(op.witness_id) == id)
             })
             .map(|(id, ord)| (*id, *ord))
             .collect();
-        Ok(MemContract { filter, unfiltered })
+        MemContract {
+            filter,
+            unfiltered,
+            historical: point.is_some(),
+        }
     }
+}
 
-    fn is_valid_witness(&self, witness_id: XWitnessId) -> Result<bool, Self::Error> {
-        let ord = self
-            .witnesses
-            .get(&witness_id)
-            .ok_or(StateInconsistency::AbsentWitness(witness_id))?;
-        Ok(ord.is_valid())
+#[cfg(any(feature = "fs", feature = "sqlite"))]
+impl MemState {
+    /// Restores outputs of a contract state, which were pruned as spent, if
+    /// their spending witnesses are no longer valid.
+    pub(super) fn restore_unspent(&self, contract: &mut MemContractState) {
+        let witnesses = &self.witnesses;
+        contract.restore_unspent(|id| witnesses.get(&id).is_some_and(|ord| ord.is_valid()));
+    }
+
+    /// Returns a copy of the witness ordering data, without contract states.
+    #[cfg(feature = "fs")]
+    pub(super) fn witnesses_only(&self) -> Self {
+        Self {
+            persistence: None,
+            snapshot: None,
+            witnesses: self.witnesses.clone(),
+            contracts: empty!(),
+        }
+    }
+
+    /// Adds state of a contract which was kept outside of this object (for
+    /// instance, loaded from a separate persistence segment). If the state of
+    /// the contract is already known, it is left unchanged.
+    pub(super) fn insert_contract(
+        &mut self,
+        contract: MemContractState,
+    ) -> Result<(), confinement::Error> {
+        if !self.contracts.contains_key(&contract.contract_id) {
+            self.contracts.insert(contract.contract_id, contract)?;
+        }
+        Ok(())
     }
 }
 
@@ -674,9 +833,44 @@ impl StateWriteProvider for MemState {
         let mut witnesses =
             LargeOrdMap::try_from(witnesses).inspect_err(|_| self.rollback_transaction())?;
         mem::swap(&mut self.witnesses, &mut witnesses);
+        // Outputs which were pruned as spent must be restored if their spending witnesses got
+        // archived after a re-org or RBF
+        let witnesses = &self.witnesses;
+        let is_valid = |id| witnesses.get(&id).is_some_and(|ord| ord.is_valid());
+        for contract in self.contracts.values_mut() {
+            contract.restore_unspent(is_valid);
+        }
         self.commit_transaction()?;
         Ok(UpdateRes { succeeded, failed })
     }
+
+    fn prune_spent(&mut self) -> Result<usize, Self::Error> {
+        self.begin_transaction()?;
+        let witnesses = &self.witnesses;
+        let is_valid = |id| witnesses.get(&id).is_some_and(|ord| ord.is_valid());
+        let mut count = 0;
+        for contract in self.contracts.values_mut() {
+            contract.restore_unspent(is_valid);
+            count += contract.prune_spent(is_valid);
+        }
+        self.commit_transaction()?;
+        Ok(count)
+    }
+}
+
+/// Operation spending a contract output, together with the witness under
+/// which the operation was anchored.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
+#[strict_type(lib = LIB_NAME_RGB_STORAGE)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct SpendingOp {
+    pub opid: OpId,
+    pub witness_id: XWitnessId,
 }
 
 #[derive(Getters, Clone, Eq, PartialEq, Debug)]
@@ -725,8 +919,19 @@ pub struct MemContractState {
     fungibles: LargeOrdSet<OutputAssignment<RevealedValue>>,
     data: LargeOrdSet<OutputAssignment<RevealedData>>,
     attach: LargeOrdSet<OutputAssignment<RevealedAttach>>,
+    /// Operations spending contract outputs.
+    spent: LargeOrdMap<Opout, TinyOrdSet<SpendingOp>>,
+    /// Assignments removed from the state by [`StateWriteProvider::prune_spent`]
+    /// and kept in case their spending witnesses will become archived.
+    pruned_rights: LargeOrdSet<OutputAssignment<VoidState>>,
+    pruned_fungibles: LargeOrdSet<OutputAssignment<RevealedValue>>,
+    pruned_data: LargeOrdSet<OutputAssignment<RevealedData>>,
+    pruned_attach: LargeOrdSet<OutputAssignment<RevealedAttach>>,
 }
 
+impl StrictSerialize for MemContractState {}
+impl StrictDeserialize for MemContractState {}
+
 impl MemContractState {
     pub fn new(schema: &Schema, contract_id: ContractId) -> Self {
         let global = TinyOrdMap::from_iter_checked(
@@ -743,9 +948,51 @@ impl MemContractState {
             fungibles: empty!(),
             data: empty!(),
             attach: empty!(),
+            spent: empty!(),
+            pruned_rights: empty!(),
+            pruned_fungibles: empty!(),
+            pruned_data: empty!(),
+            pruned_attach: empty!(),
         }
     }
 
+    /// Checks whether an output is spent by an operation with a valid witness.
+    fn is_spent(&self, opout: Opout, is_valid: impl Fn(XWitnessId) -> bool) -> bool {
+        self.spent
+            .get(&opout)
+            .is_some_and(|ops| ops.iter().any(|op| is_valid(op.witness_id)))
+    }
+
+    /// Moves assignments spent by operations with valid witnesses to the
+    /// pruned state, returning the number of the pruned assignments.
+    fn prune_spent(&mut self, is_valid: impl Fn(XWitnessId) -> bool + Copy) -> usize {
+        let spent = |opout| self.is_spent(opout, is_valid);
+        let rights = select_assignments(&self.rights, spent);
+        let fungibles = select_assignments(&self.fungibles, spent);
+        let data = select_assignments(&self.data, spent);
+        let attach = select_assignments(&self.attach, spent);
+        let count = rights.len() + fungibles.len() + data.len() + attach.len();
+        move_assignments(&mut self.rights, &mut self.pruned_rights, rights);
+        move_assignments(&mut self.fungibles, &mut self.pruned_fungibles, fungibles);
+        move_assignments(&mut self.data, &mut self.pruned_data, data);
+        move_assignments(&mut self.attach, &mut self.pruned_attach, attach);
+        count
+    }
+
+    /// Moves pruned assignments which are not spent by any operation with a
+    /// valid witness back to the state.
+    fn restore_unspent(&mut self, is_valid: impl Fn(XWitnessId) -> bool + Copy) {
+        let unspent = |opout| !self.is_spent(opout, is_valid);
+        let rights = select_assignments(&self.pruned_rights, unspent);
+        let fungibles = select_assignments(&self.pruned_fungibles, unspent);
+        let data = select_assignments(&self.pruned_data, unspent);
+        let attach = select_assignments(&self.pruned_attach, unspent);
+        move_assignments(&mut self.pruned_rights, &mut self.rights, rights);
+        move_assignments(&mut self.pruned_fungibles, &mut self.fungibles, fungibles);
+        move_assignments(&mut self.pruned_data, &mut self.data, data);
+        move_assignments(&mut self.pruned_attach, &mut self.attach, attach);
+    }
+
     fn add_operation(&mut self, op: OrdOpRef) {
         let opid = op.id();
 
@@ -767,40 +1014,23 @@ impl MemContractState {
             }
         }
 
-        // We skip removing of invalidated state for the cases of re-orgs or unmined
-        // witness transactions committing to the new state.
-        // TODO: Expose an API to prune historic state by witness txid
-        /*
-        // Remove invalidated state
-        for input in &op.inputs() {
-            if let Some(o) = self.rights.iter().find(|r| r.opout == input.prev_out) {
-                let o = o.clone(); // need this b/c of borrow checker
-                self.rights
-                    .remove(&o)
-                    .expect("collection allows zero elements");
-            }
-            if let Some(o) = self.fungibles.iter().find(|r| r.opout == input.prev_out) {
-                let o = o.clone();
-                self.fungibles
-                    .remove(&o)
-                    .expect("collection allows zero elements");
-            }
-            if let Some(o) = self.data.iter().find(|r| r.opout == input.prev_out) {
-                let o = o.clone();
-                self.data
-                    .remove(&o)
-                    .expect("collection allows zero elements");
-            }
-            if let Some(o) = self.attach.iter().find(|r| r.opout == input.prev_out) {
-                let o = o.clone();
-                self.attach
-                    .remove(&o)
-                    .expect("collection allows zero elements");
+        let witness_id = op.witness_id();
+
+        // We do not remove spent state here for the cases of re-orgs or unmined
+        // witness transactions committing to the new state; instead we track
+        // the spending operations, such that the spent state can be filtered out
+        // or pruned with `StateWriteProvider::prune_spent`.
+        if let Some(witness_id) = witness_id {
+            for input in &op.inputs() {
+                self.spent
+                    .entry(input.prev_out)
+                    .expect("contract state exceeded 2^32 items, which is unrealistic")
+                    .or_default()
+                    .push(SpendingOp { opid, witness_id })
+                    .expect("output spent by more than 255 operations, which is unrealistic");
             }
         }
-         */
 
-        let witness_id = op.witness_id();
         match op.assignments() {
             AssignmentsRef::Genesis(assignments) => {
                 self.add_assignments(witness_id, opid, assignments)
@@ -860,9 +1090,57 @@ impl MemContractState {
     }
 }
 
+fn select_assignments<State: KnownState>(
+    assignments: &LargeOrdSet<OutputAssignment<State>>,
+    f: impl Fn(Opout) -> bool,
+) -> Vec<OutputAssignment<State>> {
+    assignments
+        .iter()
+        .filter(|assignment| f(assignment.opout))
+        .cloned()
+        .collect()
+}
+
+fn move_assignments<State: KnownState>(
+    from: &mut LargeOrdSet<OutputAssignment<State>>,
+    to: &mut LargeOrdSet<OutputAssignment<State>>,
+    assignments: Vec<OutputAssignment<State>>,
+) {
+    for assignment in assignments {
+        from.remove(&assignment)
+            .expect("collection allows zero elements");
+        to.push(assignment)
+            .expect("contract state exceeded 2^32 items, which is unrealistic");
+    }
+}
+
 pub struct MemContract<M: Borrow<MemContractState> = MemContractState> {
     filter: HashMap<XWitnessId, WitnessOrd>,
     unfiltered: M,
+    /// Whether the state is reconstructed for a past point of the history,
+    /// such that the assignments pruned as spent may be still unspent.
+    historical: bool,
+}
+
+impl<M: Borrow<MemContractState>> MemContract<M> {
+    /// Iterates over assignments which are created by operations with valid
+    /// witnesses known to the filter.
+    fn visible<'a, State: KnownState>(
+        &'a self,
+        assignments: &'a LargeOrdSet<OutputAssignment<State>>,
+        pruned: &'a LargeOrdSet<OutputAssignment<State>>,
+    ) -> impl DoubleEndedIterator<Item = &'a OutputAssignment<State>> + 'a {
+        let pruned = self
+            .historical
+            .then_some(pruned)
+            .into_iter()
+            .flat_map(|pruned| pruned.iter())
+            .filter(|assignment| !self.is_spent(assignment.opout));
+        assignments
+            .iter()
+            .chain(pruned)
+            .filter(|assignment| assignment.check_witness(&self.filter))
+    }
 }
 
 impl<M: Borrow<MemContractState>> Debug for MemContract<M> {
@@ -965,14 +1243,11 @@ impl<M: Borrow<MemContractState>> ContractStateAccess for MemContract<M> {
     }
 
     fn rights(&self, outpoint: XOutpoint, ty: AssignmentType) -> u32 {
-        self.unfiltered
-            .borrow()
-            .rights
-            .iter()
+        let state = self.unfiltered.borrow();
+        self.visible(&state.rights, &state.pruned_rights)
             .filter(|assignment| {
                 assignment.seal.to_outpoint() == outpoint && assignment.opout.ty == ty
             })
-            .filter(|assignment| assignment.check_witness(&self.filter))
             .count() as u32
     }
 
@@ -981,14 +1256,11 @@ impl<M: Borrow<MemContractState>> ContractStateAccess for MemContract<M> {
         outpoint: XOutpoint,
         ty: AssignmentType,
     ) -> impl DoubleEndedIterator<Item = FungibleState> {
-        self.unfiltered
-            .borrow()
-            .fungibles
-            .iter()
+        let state = self.unfiltered.borrow();
+        self.visible(&state.fungibles, &state.pruned_fungibles)
             .filter(move |assignment| {
                 assignment.seal.to_outpoint() == outpoint && assignment.opout.ty == ty
             })
-            .filter(|assignment| assignment.check_witness(&self.filter))
             .map(|assignment| assignment.state.value)
     }
 
@@ -997,14 +1269,11 @@ impl<M: Borrow<MemContractState>> ContractStateAccess for MemContract<M> {
         outpoint: XOutpoint,
         ty: AssignmentType,
     ) -> impl DoubleEndedIterator<Item = impl Borrow<DataState>> {
-        self.unfiltered
-            .borrow()
-            .data
-            .iter()
+        let state = self.unfiltered.borrow();
+        self.visible(&state.data, &state.pruned_data)
             .filter(move |assignment| {
                 assignment.seal.to_outpoint() == outpoint && assignment.opout.ty == ty
             })
-            .filter(|assignment| assignment.check_witness(&self.filter))
             .map(|assignment| &assignment.state.value)
     }
 
@@ -1013,14 +1282,11 @@ impl<M: Borrow<MemContractState>> ContractStateAccess for MemContract<M> {
         outpoint: XOutpoint,
         ty: AssignmentType,
     ) -> impl DoubleEndedIterator<Item = impl Borrow<AttachState>> {
-        self.unfiltered
-            .borrow()
-            .attach
-            .iter()
+        let state = self.unfiltered.borrow();
+        self.visible(&state.attach, &state.pruned_attach)
             .filter(move |assignment| {
                 assignment.seal.to_outpoint() == outpoint && assignment.opout.ty == ty
             })
-            .filter(|assignment| assignment.check_witness(&self.filter))
             .map(|assignment| &assignment.state.file)
     }
 }
@@ -1032,6 +1298,7 @@ impl ContractStateEvolve for MemContract<MemContractState> {
         Self {
             filter: empty!(),
             unfiltered: MemContractState::new(context.0, context.1),
+            historical: false,
         }
     }
 
@@ -1089,38 +1356,53 @@ impl<M: Borrow<MemContractState>> ContractStateRead for MemContract<M> {
 
     #[inline]
     fn rights_all(&self) -> impl Iterator<Item = &OutputAssignment<VoidState>> {
-        self.unfiltered
-            .borrow()
-            .rights
-            .iter()
-            .filter(|assignment| assignment.check_witness(&self.filter))
+        let state = self.unfiltered.borrow();
+        self.visible(&state.rights, &state.pruned_rights)
     }
 
     #[inline]
     fn fungible_all(&self) -> impl Iterator<Item = &OutputAssignment<RevealedValue>> {
-        self.unfiltered
-            .borrow()
-            .fungibles
-            .iter()
-            .filter(|assignment| assignment.check_witness(&self.filter))
+        let state = self.unfiltered.borrow();
+        self.visible(&state.fungibles, &state.pruned_fungibles)
     }
 
     #[inline]
     fn data_all(&self) -> impl Iterator<Item = &OutputAssignment<RevealedData>> {
-        self.unfiltered
-            .borrow()
-            .data
-            .iter()
-            .filter(|assignment| assignment.check_witness(&self.filter))
+        let state = self.unfiltered.borrow();
+        self.visible(&state.data, &state.pruned_data)
     }
 
     #[inline]
     fn attach_all(&self) -> impl Iterator<Item = &OutputAssignment<RevealedAttach>> {
+        let state = self.unfiltered.borrow();
+        self.visible(&state.attach, &state.pruned_attach)
+    }
+
+    fn fungible_archived(&self) -> impl Iterator<Item = &OutputAssignment<RevealedValue>> {
         self.unfiltered
             .borrow()
-            .attach
+            .fungibles
             .iter()
-            .filter(|assignment| assignment.check_witness(&self.filter))
+            .filter(|assignment| {
+                assignment.witness.is_some_and(|witness_id| {
+                    self.filter.get(&witness_id) == Some(&WitnessOrd::Archived)
+                })
+            })
+    }
+
+    fn spent_by(&self, opout: Opout) -> impl Iterator<Item = (OpId, XWitnessId)> {
+        self.unfiltered
+            .borrow()
+            .spent
+            .get(&opout)
+            .into_iter()
+            .flat_map(|ops| ops.iter())
+            .filter(|op| {
+                self.filter
+                    .get(&op.witness_id)
+                    .is_some_and(|ord| ord.is_valid())
+            })
+            .map(|op| (op.opid, op.witness_id))
     }
 }
 
@@ -1199,6 +1481,9 @@ pub struct ContractIndex {
     outpoint_opouts: MediumOrdMap<XOutputSeal, MediumOrdSet<Opout>>,
 }
 
+/// In-memory index of the stash data.
+///
+/// NB: Each transaction copies the whole index for its rollback.
 #[derive(Getters, Debug)]
 #[getter(prefix = "debug_")]
 #[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
@@ -1207,12 +1492,17 @@ pub struct MemIndex {
     #[getter(skip)]
     #[strict_type(skip)]
     persistence: Option<Persistence<Self>>,
+    /// Copy of the whole index taken at the beginning of a transaction, which
+    /// is restored on its rollback.
+    #[getter(skip)]
+    #[strict_type(skip)]
+    snapshot: Option<Box<Self>>,
 
-    op_bundle_index: MediumOrdMap<OpId, BundleId>,
-    bundle_contract_index: MediumOrdMap<BundleId, ContractId>,
-    bundle_witness_index: MediumOrdMap<BundleId, TinyOrdSet<XWitnessId>>,
-    contract_index: TinyOrdMap<ContractId, ContractIndex>,
-    terminal_index: MediumOrdMap<XChain<SecretSeal>, TinyOrdSet<Opout>>,
+    pub(super) op_bundle_index: LargeOrdMap<OpId, BundleId>,
+    pub(super) bundle_contract_index: LargeOrdMap<BundleId, ContractId>,
+    pub(super) bundle_witness_index: LargeOrdMap<BundleId, TinyOrdSet<XWitnessId>>,
+    pub(super) contract_index: MediumOrdMap<ContractId, ContractIndex>,
+    pub(super) terminal_index: LargeOrdMap<XChain<SecretSeal>, TinyOrdSet<Opout>>,
 }
 
 impl StrictSerialize for MemIndex {}
@@ -1222,6 +1512,7 @@ impl MemIndex {
     pub fn in_memory() -> Self {
         Self {
             persistence: None,
+            snapshot: None,
             op_bundle_index: empty!(),
             bundle_contract_index: empty!(),
             bundle_witness_index: empty!(),
@@ -1235,6 +1526,7 @@ impl CloneNoPersistence for MemIndex {
     fn clone_no_persistence(&self) -> Self {
         Self {
             persistence: None,
+            snapshot: None,
             op_bundle_index: self.op_bundle_index.clone(),
             bundle_contract_index: self.bundle_contract_index.clone(),
             bundle_witness_index: self.bundle_witness_index.clone(),
@@ -1257,13 +1549,27 @@ impl StoreTransaction for MemIndex {
     type TransactionErr = MemError;
     #[inline]
     fn begin_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.snapshot = Some(Box::new(self.clone_no_persistence()));
         self.mark_dirty();
         Ok(())
     }
     #[inline]
-    fn commit_transaction(&mut self) -> Result<(), Self::TransactionErr> { Ok(self.store()?) }
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.store()?;
+        Ok(())
+    }
+    #[inline]
+    fn finish_transaction(&mut self) { self.snapshot = None; }
     #[inline]
-    fn rollback_transaction(&mut self) { unreachable!() }
+    fn rollback_transaction(&mut self) {
+        if let Some(snapshot) = self.snapshot.take() {
+            let persistence = self.persistence.take();
+            *self = *snapshot;
+            self.persistence = persistence;
+            // Overwrites the changes which might be already persisted
+            self.mark_dirty();
+        }
+    }
 }
 
 impl IndexProvider for MemIndex {}
@@ -1530,3 +1836,389 @@ impl MemIndex {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bp::Txid;
+    use invoice::Amount;
+    use rgb::validation::WitnessResolverError;
+    use rgb::vm::{WitnessPos, XWitnessTx};
+    use rgb::{AssetTag, Input, Inputs};
+    use strict_encoding::StrictDumb;
+
+    use super::*;
+    use crate::interface::{Balance, ContractDiff, ContractError, FilterExclude, FilterIncludeAll};
+
+    struct ArchivingResolver;
+
+    impl ResolveWitness for ArchivingResolver {
+        fn resolve_pub_witness(&self, _: XWitnessId) -> Result<XWitnessTx, WitnessResolverError> {
+            Ok(XWitnessTx::strict_dumb())
+        }
+
+        fn resolve_pub_witness_ord(
+            &self,
+            _: XWitnessId,
+        ) -> Result<WitnessOrd, WitnessResolverError> {
+            Ok(WitnessOrd::Archived)
+        }
+    }
+
+    #[test]
+    fn test_prune_spent() {
+        let mut state = MemState::in_memory();
+        let genesis = Genesis::strict_dumb();
+        let contract_id = genesis.contract_id();
+        state
+            .register_contract(&Schema::strict_dumb(), &genesis)
+            .unwrap();
+
+        let opout = Opout::new(genesis.id(), AssignmentType::with(0), 0);
+        let seal = XChain::<GenesisSeal>::with(
+            rgbcore::Layer1::Bitcoin,
+            GenesisSeal::opret_first_rand(Txid::strict_dumb(), 0),
+        );
+        state
+            .contracts
+            .get_mut(&contract_id)
+            .unwrap()
+            .rights
+            .push(OutputAssignment::with_no_witness(
+                seal,
+                VoidState::default(),
+                opout.op,
+                opout.ty,
+                opout.no,
+            ))
+            .unwrap();
+
+        let mut transition = Transition::strict_dumb();
+        transition.contract_id = contract_id;
+        transition.inputs = Inputs::from_inner(small_bset![Input::with(opout)]);
+        let opid = transition.id();
+        let witness_id = XWitnessId::strict_dumb();
+        state
+            .update_contract(contract_id)
+            .unwrap()
+            .unwrap()
+            .add_transition(&transition, witness_id, WitnessOrd::Tentative)
+            .unwrap();
+
+        let contract = state.contract_state(contract_id).unwrap();
+        assert_eq!(contract.spent_by(opout).collect::<Vec<_>>(), vec![(opid, witness_id)]);
+        assert_eq!(contract.rights_all().count(), 1);
+        assert_eq!(contract.rights_unspent().count(), 0);
+
+        assert_eq!(state.prune_spent().unwrap(), 1);
+        let contract = state.contract_state(contract_id).unwrap();
+        assert_eq!(contract.rights_all().count(), 0);
+
+        // Once the spending witness is archived the output must be restored
+        state.update_witnesses(ArchivingResolver, 0).unwrap();
+        let contract = state.contract_state(contract_id).unwrap();
+        assert!(!contract.is_spent(opout));
+        assert_eq!(contract.rights_all().count(), 1);
+        assert_eq!(contract.rights_unspent().count(), 1);
+        assert_eq!(state.prune_spent().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_history_point() {
+        let mut state = MemState::in_memory();
+        let genesis = Genesis::strict_dumb();
+        let contract_id = genesis.contract_id();
+        state
+            .register_contract(&Schema::strict_dumb(), &genesis)
+            .unwrap();
+
+        let witness = |no: u8| XWitnessId::Bitcoin(Txid::from([no; 32]));
+        let mined = |height: u32| {
+            let pos =
+                WitnessPos::bitcoin(NonZeroU32::new(height).unwrap(), 1231006505 + height as i64);
+            WitnessOrd::Mined(pos.unwrap())
+        };
+        let (early, late, tentative) = (witness(1), witness(2), witness(3));
+        state.witnesses.insert(early, mined(100)).unwrap();
+        state.witnesses.insert(late, mined(120)).unwrap();
+        state
+            .witnesses
+            .insert(tentative, WitnessOrd::Tentative)
+            .unwrap();
+
+        let ty = AssignmentType::with(0);
+        let allocate = |state: &mut MemState, no: u16, witness: Option<XWitnessId>| {
+            let seal = XChain::<GenesisSeal>::with(
+                rgbcore::Layer1::Bitcoin,
+                GenesisSeal::opret_first_rand(Txid::strict_dumb(), no as u32),
+            );
+            let mut assignment = OutputAssignment::with_no_witness(
+                seal,
+                RevealedValue::new_random_blinding(no as u64, AssetTag::strict_dumb()),
+                genesis.id(),
+                ty,
+                no,
+            );
+            assignment.witness = witness;
+            let contract = state.contracts.get_mut(&contract_id).unwrap();
+            contract.fungibles.push(assignment).unwrap();
+            assignment.opout
+        };
+        let issued = allocate(&mut state, 0, None);
+        allocate(&mut state, 1, Some(early));
+        allocate(&mut state, 2, Some(late));
+        allocate(&mut state, 3, Some(tentative));
+
+        let mut transition = Transition::strict_dumb();
+        transition.contract_id = contract_id;
+        transition.inputs = Inputs::from_inner(small_bset![Input::with(issued)]);
+        state
+            .update_contract(contract_id)
+            .unwrap()
+            .unwrap()
+            .add_transition(&transition, late, mined(120))
+            .unwrap();
+        assert_eq!(state.prune_spent().unwrap(), 1);
+
+        let unspent = |point: Option<HistoryPoint>| {
+            let contract = match point {
+                None => state.contract_state(contract_id).unwrap(),
+                Some(point) => state.contract_state_at(contract_id, point).unwrap(),
+            };
+            contract
+                .fungible_unspent()
+                .map(|a| a.opout.no)
+                .collect::<BTreeSet<_>>()
+        };
+        assert_eq!(unspent(None), bset![1, 2, 3]);
+        assert_eq!(unspent(Some(HistoryPoint::Height(90))), bset![0]);
+        assert_eq!(unspent(Some(HistoryPoint::Height(100))), bset![0, 1]);
+        assert_eq!(unspent(Some(HistoryPoint::Height(120))), bset![1, 2]);
+        assert_eq!(unspent(Some(HistoryPoint::Timestamp(1231006505 + 110))), bset![0, 1]);
+        assert_eq!(unspent(Some(HistoryPoint::Witnesses(bset![early, tentative]))), bset![0, 1, 3]);
+
+        let contract = state
+            .contract_state_at(contract_id, HistoryPoint::Height(110))
+            .unwrap();
+        assert_eq!(contract.witness_ord(late), None);
+        // Pruned outputs must be visible to the VM state access as well
+        let issued = contract.fungible_all().find(|a| a.opout == issued).unwrap();
+        assert_eq!(contract.fungible(XOutpoint::from(issued.seal), ty).count(), 1);
+    }
+
+    #[test]
+    fn test_contract_diff() {
+        let mut state = MemState::in_memory();
+        let genesis = Genesis::strict_dumb();
+        let contract_id = genesis.contract_id();
+        state
+            .register_contract(&Schema::strict_dumb(), &genesis)
+            .unwrap();
+
+        let witness = |no: u8| XWitnessId::Bitcoin(Txid::from([no; 32]));
+        let mined = |height: u32| {
+            let pos =
+                WitnessPos::bitcoin(NonZeroU32::new(height).unwrap(), 1231006505 + height as i64);
+            WitnessOrd::Mined(pos.unwrap())
+        };
+        let (early, late) = (witness(1), witness(2));
+        state.witnesses.insert(early, mined(100)).unwrap();
+        state.witnesses.insert(late, mined(120)).unwrap();
+
+        let ty = AssignmentType::with(0);
+        let allocate = |state: &mut MemState, no: u16, value: u64, witness: Option<XWitnessId>| {
+            let seal = XChain::<GenesisSeal>::with(
+                rgbcore::Layer1::Bitcoin,
+                GenesisSeal::opret_first_rand(Txid::strict_dumb(), no as u32),
+            );
+            let mut assignment = OutputAssignment::with_no_witness(
+                seal,
+                RevealedValue::new_random_blinding(value, AssetTag::strict_dumb()),
+                genesis.id(),
+                ty,
+                no,
+            );
+            assignment.witness = witness;
+            let contract = state.contracts.get_mut(&contract_id).unwrap();
+            contract.fungibles.push(assignment).unwrap();
+            assignment.opout
+        };
+        let issued = allocate(&mut state, 0, 10, None);
+        allocate(&mut state, 1, 20, Some(early));
+        allocate(&mut state, 2, 30, Some(late));
+
+        let mut transition = Transition::strict_dumb();
+        transition.contract_id = contract_id;
+        transition.inputs = Inputs::from_inner(small_bset![Input::with(issued)]);
+        state
+            .update_contract(contract_id)
+            .unwrap()
+            .unwrap()
+            .add_transition(&transition, late, mined(120))
+            .unwrap();
+
+        let diff = |state: &MemState, from: u32, to: u32| {
+            ContractDiff::compute(
+                &state
+                    .contract_state_at(contract_id, HistoryPoint::Height(from))
+                    .unwrap(),
+                &state
+                    .contract_state_at(contract_id, HistoryPoint::Height(to))
+                    .unwrap(),
+                None,
+                FilterIncludeAll,
+            )
+        };
+        let diff_100_120 = diff(&state, 100, 120).unwrap();
+        assert_eq!(
+            diff_100_120
+                .created
+                .iter()
+                .map(|a| a.opout.no)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(
+            diff_100_120
+                .spent
+                .iter()
+                .map(|a| a.opout.no)
+                .collect::<Vec<_>>(),
+            vec![0]
+        );
+        assert!(diff_100_120.global.is_empty());
+        assert_eq!(diff_100_120.fungible[&ty].net(), 20);
+
+        // Amounts which don't fit into the maximal amount value are rejected
+        // rather than capped
+        allocate(&mut state, 3, u64::MAX, Some(late));
+        assert_eq!(diff(&state, 100, 120), Err(ContractError::MovementOverflow(ty)));
+        assert!(diff(&state, 90, 100).is_ok());
+    }
+
+    #[test]
+    fn test_history_point_includes() {
+        let witness = |no: u8| XWitnessId::Bitcoin(Txid::from([no; 32]));
+        let mined = |height: u32| {
+            let pos =
+                WitnessPos::bitcoin(NonZeroU32::new(height).unwrap(), 1231006505 + height as i64);
+            WitnessOrd::Mined(pos.unwrap())
+        };
+        let (listed, unlisted) = (witness(1), witness(2));
+
+        let height = HistoryPoint::Height(100);
+        assert!(height.includes(unlisted, mined(100)));
+        assert!(!height.includes(unlisted, mined(101)));
+        assert!(!height.includes(unlisted, WitnessOrd::Tentative));
+        assert!(!height.includes(unlisted, WitnessOrd::Archived));
+
+        let timestamp = HistoryPoint::Timestamp(1231006505 + 100);
+        assert!(timestamp.includes(unlisted, mined(100)));
+        assert!(!timestamp.includes(unlisted, mined(101)));
+        assert!(!timestamp.includes(unlisted, WitnessOrd::Tentative));
+
+        let witnesses = HistoryPoint::Witnesses(bset![listed]);
+        assert!(witnesses.includes(listed, mined(100)));
+        assert!(witnesses.includes(listed, WitnessOrd::Tentative));
+        assert!(!witnesses.includes(listed, WitnessOrd::Archived));
+        assert!(!witnesses.includes(unlisted, mined(100)));
+    }
+
+    #[test]
+    fn test_fungible_balance() {
+        let mut state = MemState::in_memory();
+        let genesis = Genesis::strict_dumb();
+        let contract_id = genesis.contract_id();
+        state
+            .register_contract(&Schema::strict_dumb(), &genesis)
+            .unwrap();
+
+        let witness = |no: u8| XWitnessId::Bitcoin(Txid::from([no; 32]));
+        let mined = |height: u32| {
+            WitnessOrd::Mined(
+                WitnessPos::bitcoin(NonZeroU32::new(height).unwrap(), 1231006505).unwrap(),
+            )
+        };
+        let (deep, shallow, tentative, archived) = (witness(1), witness(2), witness(3), witness(4));
+        state.witnesses.insert(deep, mined(100)).unwrap();
+        state.witnesses.insert(shallow, mined(110)).unwrap();
+        state
+            .witnesses
+            .insert(tentative, WitnessOrd::Tentative)
+            .unwrap();
+        state
+            .witnesses
+            .insert(archived, WitnessOrd::Archived)
+            .unwrap();
+
+        let ty = AssignmentType::with(0);
+        let allocate = |state: &mut MemState,
+                        no: u16,
+                        ty: AssignmentType,
+                        value: u64,
+                        witness: Option<XWitnessId>| {
+            let seal = XChain::<GenesisSeal>::with(
+                rgbcore::Layer1::Bitcoin,
+                GenesisSeal::opret_first_rand(Txid::strict_dumb(), no as u32),
+            );
+            let mut assignment = OutputAssignment::with_no_witness(
+                seal,
+                RevealedValue::new_random_blinding(value, AssetTag::strict_dumb()),
+                genesis.id(),
+                ty,
+                no,
+            );
+            assignment.witness = witness;
+            let contract = state.contracts.get_mut(&contract_id).unwrap();
+            contract.fungibles.push(assignment).unwrap();
+            assignment.opout
+        };
+        allocate(&mut state, 0, ty, 10, None);
+        allocate(&mut state, 1, ty, 20, Some(deep));
+        allocate(&mut state, 2, ty, 30, Some(shallow));
+        allocate(&mut state, 3, ty, 40, Some(tentative));
+        allocate(&mut state, 4, ty, 50, Some(archived));
+        let pending_spent = allocate(&mut state, 5, ty, 60, None);
+        let settled_spent = allocate(&mut state, 6, ty, 70, None);
+        allocate(&mut state, 7, AssignmentType::with(1), 1000, None);
+
+        for (opout, witness_id) in [(pending_spent, tentative), (settled_spent, deep)] {
+            let mut transition = Transition::strict_dumb();
+            transition.contract_id = contract_id;
+            transition.inputs = Inputs::from_inner(small_bset![Input::with(opout)]);
+            let ord = state.witnesses.get(&witness_id).copied().unwrap();
+            state
+                .update_contract(contract_id)
+                .unwrap()
+                .unwrap()
+                .add_transition(&transition, witness_id, ord)
+                .unwrap();
+        }
+
+        let contract = state.contract_state(contract_id).unwrap();
+        let balance = contract
+            .fungible_balance(ty, FilterIncludeAll, 110, 6)
+            .unwrap();
+        assert_eq!(balance, Balance {
+            settled: Amount::from(30u64),
+            pending_incoming: Amount::from(70u64),
+            pending_outgoing: Amount::from(60u64),
+            invalidated: Amount::from(50u64),
+        });
+        assert_eq!(balance.expected(), Some(Amount::from(100u64)));
+
+        let balance = contract
+            .fungible_balance(ty, FilterIncludeAll, 110, 1)
+            .unwrap();
+        assert_eq!(balance.settled, Amount::from(60u64));
+        assert_eq!(balance.pending_incoming, Amount::from(40u64));
+
+        let excluded = FilterExclude(XOutpoint::from(contract.fungible_all().next().unwrap().seal));
+        let balance = contract.fungible_balance(ty, excluded, 110, 6).unwrap();
+        assert_eq!(balance.settled, Amount::from(20u64));
+
+        allocate(&mut state, 8, ty, u64::MAX, None);
+        let contract = state.contract_state(contract_id).unwrap();
+        assert_eq!(contract.fungible_balance(ty, FilterIncludeAll, 110, 6), None);
+    }
+}
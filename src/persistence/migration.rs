@@ -0,0 +1,336 @@
+// RGB standard library for working with smart contracts on Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2019-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2019-2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Versioning of the persisted stock data and data layouts used by the
+//! previous versions of the `RGBStorage` type library, which are kept to
+//! migrate persisted data into the current layout.
+//!
+//! Persisted data are wrapped into an envelope, specifying the storage version
+//! and the id of the `RGBStorage` type library used to encode the data. Data
+//! persisted before the introduction of the envelope are treated as having
+//! storage version 0.
+
+use std::str::FromStr;
+
+use aluvm::library::{Lib, LibId};
+use amplify::confinement::{
+    Confined, LargeOrdMap, MediumBlob, MediumOrdSet, SmallOrdMap, TinyOrdMap, TinyOrdSet,
+    U32 as U32MAX,
+};
+use amplify::ByteArray;
+use rgb::{
+    AttachId, BundleId, ContractId, Extension, Genesis, GraphSeal, Identity, OpId, SchemaId,
+    TransitionBundle, XChain, XWitnessId,
+};
+use strict_encoding::{
+    DecodeError, DeserializeError, SerializeError, StrictDeserialize, StrictSerialize,
+};
+use strict_types::{TypeLibId, TypeSystem};
+
+use super::{MemContractState, MemIndex, MemStash, MemState, SchemaIfaces};
+use crate::containers::{ContentId, ContentRef, ContentSigs, SealWitness, Supplement, TrustLevel};
+use crate::interface::{Iface, IfaceId};
+use crate::stl::{LIB_ID_RGB_STORAGE, STORAGE_VERSION};
+use crate::LIB_NAME_RGB_STORAGE;
+
+const ENVELOPE_MAGIC: [u8; 8] = *b"RGBSTORE";
+
+/// Ids of the `RGBStorage` type library used by each of the storage versions,
+/// starting from version 1.
+const STORAGE_LIB_IDS: [&str; STORAGE_VERSION as usize] = [LIB_ID_RGB_STORAGE];
+
+pub(super) fn storage_lib_id(version: u16) -> TypeLibId {
+    let id = STORAGE_LIB_IDS[version as usize - 1];
+    TypeLibId::from_str(id).expect("invalid RGBStorage library id constant")
+}
+
+fn split_envelope(data: &[u8], len: usize) -> Result<(&[u8], &[u8]), StorageError> {
+    if data.len() < len {
+        return Err(DecodeError::DataIntegrityError(s!("truncated storage envelope")).into());
+    }
+    Ok(data.split_at(len))
+}
+
+fn confine(data: Vec<u8>) -> Result<Confined<Vec<u8>, 0, U32MAX>, StorageError> {
+    Confined::try_from(data).map_err(|e| DecodeError::DataIntegrityError(e.to_string()).into())
+}
+
+/// Errors reading persisted stock data.
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum StorageError {
+    /// persisted data contain {found} while {expected} was expected.
+    DataMismatch {
+        expected: &'static str,
+        found: String,
+    },
+
+    /// {0} was persisted with storage version {1}, which is not known to this
+    /// version of the software.
+    FutureVersion(&'static str, u16),
+
+    /// {name} was persisted with storage version {version} using the type
+    /// library {found}, while the version requires {expected}. The layout of
+    /// the persisted data was changed without increasing the storage version.
+    LibMismatch {
+        name: &'static str,
+        version: u16,
+        expected: TypeLibId,
+        found: TypeLibId,
+    },
+
+    /// {0} persisted with storage version {1} can't be migrated and has to be
+    /// re-computed from the stash.
+    Unsupported(&'static str, u16),
+
+    /// invalid encoding of the persisted data. Details: {0}
+    #[from]
+    #[from(DecodeError)]
+    Decode(DeserializeError),
+}
+
+/// Stock data which are persisted in a versioned envelope.
+pub trait StorageData: StrictSerialize + StrictDeserialize {
+    /// Name of the data, which is stored in the envelope.
+    const NAME: &'static str;
+
+    /// Decodes data persisted with a previous storage version, migrating them
+    /// into the current layout.
+    fn migrate(version: u16, data: Confined<Vec<u8>, 0, U32MAX>) -> Result<Self, StorageError> {
+        let _ = data;
+        Err(StorageError::Unsupported(Self::NAME, version))
+    }
+
+    /// Serializes data into a versioned envelope.
+    fn to_envelope(&self) -> Result<Vec<u8>, SerializeError> {
+        let data = self.to_strict_serialized::<U32MAX>()?;
+        let mut envelope = Vec::with_capacity(data.len() + 64);
+        envelope.extend_from_slice(&ENVELOPE_MAGIC);
+        envelope.extend_from_slice(&STORAGE_VERSION.to_le_bytes());
+        envelope.extend_from_slice(storage_lib_id(STORAGE_VERSION).as_slice());
+        envelope.push(Self::NAME.len() as u8);
+        envelope.extend_from_slice(Self::NAME.as_bytes());
+        envelope.extend_from_slice(data.as_slice());
+        Ok(envelope)
+    }
+
+    /// Deserializes data from a versioned envelope, migrating data persisted
+    /// with the previous storage versions.
+    fn from_envelope(data: Vec<u8>) -> Result<Self, StorageError> {
+        let Some(data) = data.strip_prefix(&ENVELOPE_MAGIC) else {
+            return Self::migrate(0, confine(data)?);
+        };
+        let (version, data) = split_envelope(data, 2)?;
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        if version > STORAGE_VERSION || version == 0 {
+            return Err(StorageError::FutureVersion(Self::NAME, version));
+        }
+        let (lib_id, data) = split_envelope(data, 32)?;
+        let lib_id =
+            TypeLibId::from_byte_array(<[u8; 32]>::try_from(lib_id).expect("fixed length"));
+        let expected = storage_lib_id(version);
+        if lib_id != expected {
+            return Err(StorageError::LibMismatch {
+                name: Self::NAME,
+                version,
+                expected,
+                found: lib_id,
+            });
+        }
+        let (len, data) = split_envelope(data, 1)?;
+        let (name, data) = split_envelope(data, len[0] as usize)?;
+        if name != Self::NAME.as_bytes() {
+            return Err(StorageError::DataMismatch {
+                expected: Self::NAME,
+                found: String::from_utf8_lossy(name).to_string(),
+            });
+        }
+
+        let data = confine(data.to_vec())?;
+        if version < STORAGE_VERSION {
+            return Self::migrate(version, data);
+        }
+        Ok(Self::from_strict_serialized::<U32MAX>(data)?)
+    }
+}
+
+impl StorageData for MemStash {
+    const NAME: &'static str = "MemStash";
+
+    fn migrate(version: u16, data: Confined<Vec<u8>, 0, U32MAX>) -> Result<Self, StorageError> {
+        match version {
+            // Data without an envelope use either the current or the legacy layout
+            0 => MemStash::from_strict_serialized::<U32MAX>(data.clone())
+                .or_else(|_| Ok(LegacyMemStash::from_strict_serialized::<U32MAX>(data)?.into())),
+            _ => Err(StorageError::Unsupported(Self::NAME, version)),
+        }
+    }
+}
+
+impl StorageData for MemState {
+    const NAME: &'static str = "MemState";
+
+    fn migrate(version: u16, data: Confined<Vec<u8>, 0, U32MAX>) -> Result<Self, StorageError> {
+        match version {
+            // The legacy layout of the state is not supported and must be re-computed
+            0 => MemState::from_strict_serialized::<U32MAX>(data)
+                .map_err(|_| StorageError::Unsupported(Self::NAME, version)),
+            _ => Err(StorageError::Unsupported(Self::NAME, version)),
+        }
+    }
+}
+
+impl StorageData for MemIndex {
+    const NAME: &'static str = "MemIndex";
+
+    fn migrate(version: u16, data: Confined<Vec<u8>, 0, U32MAX>) -> Result<Self, StorageError> {
+        match version {
+            // The legacy layout of the index is not supported and must be re-computed
+            0 => MemIndex::from_strict_serialized::<U32MAX>(data)
+                .map_err(|_| StorageError::Unsupported(Self::NAME, version)),
+            _ => Err(StorageError::Unsupported(Self::NAME, version)),
+        }
+    }
+}
+
+impl StorageData for MemContractState {
+    const NAME: &'static str = "MemContractState";
+}
+
+/// Layout of [`MemStash`] limiting the number of schemata, interfaces and
+/// contracts to 255.
+#[derive(Debug)]
+#[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
+#[strict_type(lib = LIB_NAME_RGB_STORAGE, dumb = Self::empty())]
+pub struct LegacyMemStash {
+    pub(super) schemata: TinyOrdMap<SchemaId, SchemaIfaces>,
+    pub(super) ifaces: TinyOrdMap<IfaceId, Iface>,
+    pub(super) geneses: TinyOrdMap<ContractId, Genesis>,
+    pub(super) suppl: TinyOrdMap<ContentRef, TinyOrdSet<Supplement>>,
+    pub(super) bundles: LargeOrdMap<BundleId, TransitionBundle>,
+    pub(super) extensions: LargeOrdMap<OpId, Extension>,
+    pub(super) witnesses: LargeOrdMap<XWitnessId, SealWitness>,
+    pub(super) attachments: SmallOrdMap<AttachId, MediumBlob>,
+    pub(super) secret_seals: MediumOrdSet<XChain<GraphSeal>>,
+    pub(super) type_system: TypeSystem,
+    pub(super) identities: SmallOrdMap<Identity, TrustLevel>,
+    pub(super) libs: SmallOrdMap<LibId, Lib>,
+    pub(super) sigs: SmallOrdMap<ContentId, ContentSigs>,
+}
+
+impl StrictSerialize for LegacyMemStash {}
+impl StrictDeserialize for LegacyMemStash {}
+
+impl LegacyMemStash {
+    fn empty() -> Self {
+        Self {
+            schemata: empty!(),
+            ifaces: empty!(),
+            geneses: empty!(),
+            suppl: empty!(),
+            bundles: empty!(),
+            extensions: empty!(),
+            witnesses: empty!(),
+            attachments: empty!(),
+            secret_seals: empty!(),
+            type_system: none!(),
+            identities: empty!(),
+            libs: empty!(),
+            sigs: empty!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use amplify::confinement::U32;
+    use bp::dbc::Method;
+    use bp::Vout;
+
+    use super::*;
+    use crate::persistence::StashReadProvider;
+
+    #[test]
+    fn test_envelope() {
+        let stash = MemStash::in_memory();
+        let envelope = stash.to_envelope().unwrap();
+        assert!(MemStash::from_envelope(envelope.clone()).is_ok());
+        assert!(matches!(
+            MemIndex::from_envelope(envelope.clone()).unwrap_err(),
+            StorageError::DataMismatch { expected: "MemIndex", found } if found == "MemStash"
+        ));
+
+        let mut future = envelope.clone();
+        future[ENVELOPE_MAGIC.len()..ENVELOPE_MAGIC.len() + 2]
+            .copy_from_slice(&(STORAGE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            MemStash::from_envelope(future).unwrap_err(),
+            StorageError::FutureVersion("MemStash", v) if v == STORAGE_VERSION + 1
+        ));
+
+        let mut changed = envelope;
+        changed[ENVELOPE_MAGIC.len() + 2] ^= 0xFF;
+        assert!(matches!(
+            MemStash::from_envelope(changed).unwrap_err(),
+            StorageError::LibMismatch {
+                name: "MemStash",
+                ..
+            }
+        ));
+
+        // Data persisted without an envelope
+        let data = stash.to_strict_serialized::<U32>().unwrap().release();
+        assert!(MemStash::from_envelope(data).is_ok());
+        let data = LegacyMemStash::empty()
+            .to_strict_serialized::<U32>()
+            .unwrap()
+            .release();
+        assert!(MemStash::from_envelope(data.clone()).is_ok());
+        assert!(matches!(
+            MemState::from_envelope(data).unwrap_err(),
+            StorageError::Unsupported("MemState", 0)
+        ));
+    }
+
+    #[test]
+    fn test_legacy_stash() {
+        let seal = XChain::with(
+            rgbcore::Layer1::Bitcoin,
+            GraphSeal::new_random_vout(Method::OpretFirst, Vout::from_u32(0)),
+        );
+        let identity = Identity::from_str("ssi:trusted").unwrap();
+
+        let mut legacy = LegacyMemStash::empty();
+        legacy.secret_seals.push(seal).unwrap();
+        legacy
+            .identities
+            .insert(identity.clone(), TrustLevel::Trusted)
+            .unwrap();
+        let data = legacy.to_strict_serialized::<U32>().unwrap();
+
+        let legacy = LegacyMemStash::from_strict_serialized::<U32>(data).unwrap();
+        let stash = MemStash::from(legacy);
+        assert_eq!(stash.secret_seals().unwrap().collect::<Vec<_>>(), vec![seal]);
+        assert_eq!(stash.get_trust(&identity).unwrap(), TrustLevel::Trusted);
+    }
+}
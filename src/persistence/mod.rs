@@ -34,29 +34,39 @@ mod stock;
 mod stash;
 mod state;
 mod index;
+mod selection;
+mod migration;
 
 mod memory;
 #[cfg(feature = "fs")]
+mod crypto;
+#[cfg(feature = "fs")]
 pub mod fs;
+#[cfg(feature = "sqlite")]
+pub mod sql;
 
 pub use index::{
     Index, IndexError, IndexInconsistency, IndexProvider, IndexReadError, IndexReadProvider,
     IndexWriteError, IndexWriteProvider,
 };
 pub use memory::{
-    MemContract, MemContractState, MemError, MemGlobalState, MemIndex, MemStash, MemState,
+    MemContract, MemContractState, MemContractWriter, MemError, MemGlobalState, MemIndex, MemStash,
+    MemState, SpendingOp,
 };
+pub use migration::{LegacyMemStash, StorageData, StorageError};
+pub use selection::{CoinSelection, SelectionCandidate, SelectionOrder};
 pub use stash::{
     ProviderError as StashProviderError, SchemaIfaces, Stash, StashDataError, StashError,
     StashInconsistency, StashProvider, StashReadProvider, StashWriteProvider,
 };
 pub use state::{
-    ContractStateRead, ContractStateWrite, PersistedState, State, StateError, StateInconsistency,
-    StateProvider, StateReadProvider, StateWriteProvider,
+    ContractStateRead, ContractStateWrite, HistoryPoint, PersistedState, State, StateError,
+    StateInconsistency, StateProvider, StateReadProvider, StateWriteProvider,
 };
 pub use stock::{
-    ComposeError, ConsignError, ContractIfaceError, FasciaError, InputError as StockInputError,
-    Stock, StockError, StockErrorAll, StockErrorMem, UpdateRes,
+    ComposeError, ConsignError, ContractHoldings, ContractIfaceError, DisclosureError, FasciaError,
+    InputError as StockInputError, Portfolio, RepairAction, Stock, StockError, StockErrorAll,
+    StockErrorMem, StockIssue, UpdateRes,
 };
 
 pub trait StoreTransaction {
@@ -64,7 +74,22 @@ pub trait StoreTransaction {
 
     fn begin_transaction(&mut self) -> Result<(), Self::TransactionErr>;
 
-    fn commit_transaction(&mut self) -> Result<(), Self::TransactionErr>;
+    /// Persists changes made within the transaction, keeping it open such that
+    /// it can be still rolled back. The transaction must be closed with either
+    /// [`Self::finish_transaction`] or [`Self::rollback_transaction`].
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr>;
+
+    /// Closes a prepared transaction, dropping the data required to roll it
+    /// back.
+    fn finish_transaction(&mut self);
+
+    fn commit_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.prepare_transaction()?;
+        self.finish_transaction();
+        Ok(())
+    }
 
+    /// Reverts all changes made within an open transaction, including the
+    /// changes which were already persisted by [`Self::prepare_transaction`].
     fn rollback_transaction(&mut self);
 }
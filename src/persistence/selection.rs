@@ -0,0 +1,193 @@
+// RGB standard library for working with smart contracts on Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2019-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2019-2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Selection of the outputs which state is spent by a newly composed transfer.
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+use bp::seals::txout::{CloseMethod, TxoSeal};
+use rgb::XOutputSeal;
+
+/// Order in which outputs holding the invoiced state are selected for
+/// spending.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+#[display(lowercase)]
+pub enum SelectionOrder {
+    /// Spend outputs holding the largest amount of the state first, minimizing
+    /// the number of inputs.
+    #[default]
+    LargestFirst,
+
+    /// Spend outputs holding the smallest amount of the state first,
+    /// consolidating small allocations.
+    SmallestFirst,
+
+    /// Spend outputs which hold state of the fewest other contracts first,
+    /// minimizing the number of blank state transitions.
+    FewestBlanks,
+}
+
+/// Output holding the invoiced state which may be spent by a transfer.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SelectionCandidate {
+    pub output: XOutputSeal,
+    /// Amount of the invoiced state assigned to the output: fungible amount or
+    /// owned token fraction; rights and attachments count as one.
+    pub value: u64,
+    /// Number of other contracts assigning state to the output, each of which
+    /// requires a blank state transition.
+    pub blanks: usize,
+    /// Whether all witness transactions assigning the state to the output are
+    /// mined.
+    pub mined: bool,
+}
+
+/// Strategy for selecting outputs which state is spent by a transfer.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct CoinSelection {
+    pub order: SelectionOrder,
+    /// Prefer outputs using the given seal closing method, which allows to
+    /// avoid an additional state transition spending the rest of the outputs.
+    pub prefer_method: Option<CloseMethod>,
+    /// Do not spend state assigned by witness transactions which are not mined
+    /// yet.
+    pub mined_only: bool,
+}
+
+impl CoinSelection {
+    pub fn with(order: SelectionOrder) -> Self {
+        CoinSelection {
+            order,
+            prefer_method: None,
+            mined_only: false,
+        }
+    }
+
+    pub fn prefer_method(mut self, method: CloseMethod) -> Self {
+        self.prefer_method = Some(method);
+        self
+    }
+
+    pub fn mined_only(mut self) -> Self {
+        self.mined_only = true;
+        self
+    }
+
+    /// Selects outputs which together hold at least `target` amount of the
+    /// state. Returns `None` if the candidates satisfying the strategy can't
+    /// cover the target.
+    pub fn select(
+        &self,
+        candidates: impl IntoIterator<Item = SelectionCandidate>,
+        target: u64,
+    ) -> Option<BTreeSet<XOutputSeal>> {
+        let mut candidates = candidates
+            .into_iter()
+            .filter(|candidate| candidate.value > 0 && (candidate.mined || !self.mined_only))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| self.cmp(a, b));
+
+        let mut selected = BTreeSet::new();
+        let mut sum = 0u64;
+        for candidate in candidates {
+            if sum >= target {
+                break;
+            }
+            sum = sum.saturating_add(candidate.value);
+            selected.insert(candidate.output);
+        }
+        (sum >= target).then_some(selected)
+    }
+
+    fn cmp(&self, a: &SelectionCandidate, b: &SelectionCandidate) -> Ordering {
+        let other_method = |c: &SelectionCandidate| {
+            self.prefer_method
+                .map(|method| c.output.method() != method)
+                .unwrap_or_default()
+        };
+        other_method(a)
+            .cmp(&other_method(b))
+            .then_with(|| match self.order {
+                SelectionOrder::LargestFirst => b.value.cmp(&a.value),
+                SelectionOrder::SmallestFirst => a.value.cmp(&b.value),
+                SelectionOrder::FewestBlanks => a.blanks.cmp(&b.blanks).then(b.value.cmp(&a.value)),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bp::seals::txout::ExplicitSeal;
+    use bp::Txid;
+    use rgb::XChain;
+
+    use super::*;
+
+    fn candidate(
+        no: u32,
+        method: CloseMethod,
+        value: u64,
+        blanks: usize,
+        mined: bool,
+    ) -> SelectionCandidate {
+        let seal = ExplicitSeal::with(method, Txid::from([0x01; 32]), no);
+        SelectionCandidate {
+            output: XChain::Bitcoin(seal),
+            value,
+            blanks,
+            mined,
+        }
+    }
+
+    #[test]
+    fn select_order() {
+        let a = candidate(0, CloseMethod::OpretFirst, 10, 2, true);
+        let b = candidate(1, CloseMethod::OpretFirst, 5, 0, true);
+        let c = candidate(2, CloseMethod::OpretFirst, 3, 1, true);
+        let candidates = [a, b, c];
+
+        let select = |order: SelectionOrder, target: u64| {
+            CoinSelection::with(order).select(candidates, target)
+        };
+        assert_eq!(select(SelectionOrder::LargestFirst, 12), Some(bset![a.output, b.output]));
+        assert_eq!(select(SelectionOrder::SmallestFirst, 7), Some(bset![c.output, b.output]));
+        assert_eq!(select(SelectionOrder::FewestBlanks, 8), Some(bset![b.output, c.output]));
+        assert_eq!(select(SelectionOrder::LargestFirst, 19), None);
+    }
+
+    #[test]
+    fn select_filters() {
+        let tapret = candidate(0, CloseMethod::TapretFirst, 10, 0, true);
+        let opret = candidate(1, CloseMethod::OpretFirst, 5, 0, true);
+        let unmined = candidate(2, CloseMethod::OpretFirst, 20, 0, false);
+        let candidates = [tapret, opret, unmined];
+
+        let selection = CoinSelection::default();
+        assert_eq!(selection.select(candidates, 5), Some(bset![unmined.output]));
+        let selection = selection.mined_only();
+        assert_eq!(selection.select(candidates, 5), Some(bset![tapret.output]));
+        let selection = selection.prefer_method(CloseMethod::OpretFirst);
+        assert_eq!(selection.select(candidates, 5), Some(bset![opret.output]));
+        assert_eq!(selection.select(candidates, 12), Some(bset![opret.output, tapret.output]));
+        assert_eq!(selection.select(candidates, 20), None);
+    }
+}
@@ -0,0 +1,1770 @@
+// RGB standard library for working with smart contracts on Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2019-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2019-2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stock data kept in an embedded SQLite database.
+//!
+//! [`SqlStash`], [`SqlState`] and [`SqlIndex`] keep each item of their
+//! collections in a separate table row, which is written to the database once
+//! the item is added or changed. All three share a single database connection
+//! provided by [`SqlStore`], such that the changes made by them within a
+//! [`Stock`] transaction are committed to the database at once.
+//!
+//! [`Stock`]: super::Stock
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::fmt::{self, Debug, Formatter};
+use std::hash::Hash;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Duration;
+
+use aluvm::library::{Lib, LibId};
+use amplify::confinement::{self, Confined, MediumBlob, MediumOrdMap, U32 as U32MAX};
+use bp::dbc::tapret::TapretCommitment;
+use commit_verify::CommitId;
+use nonasync::persistence::{CloneNoPersistence, Persistence, PersistenceError, Persisting};
+use rgb::validation::ResolveWitness;
+use rgb::vm::WitnessOrd;
+use rgb::{
+    Assign, AssignmentType, AttachId, BundleId, ContractId, ExposedSeal, ExposedState, Extension,
+    Genesis, GenesisSeal, GraphSeal, Identity, OpId, Operation, Opout, Schema, SchemaId,
+    SecretSeal, TransitionBundle, XChain, XOutputSeal, XWitnessId,
+};
+use rusqlite::backup::Backup;
+use rusqlite::{params, Connection, OptionalExtension, Params};
+use strict_encoding::{
+    DecodeError, DeserializeError, SerializeError, StrictDecode, StrictEncode, StrictReader,
+    StrictWriter,
+};
+use strict_types::{SemId, Ty, TypeLibId, TypeSystem};
+
+use super::migration::storage_lib_id;
+use super::{
+    HistoryPoint, IndexInconsistency, IndexProvider, IndexReadError, IndexReadProvider,
+    IndexWriteError, IndexWriteProvider, MemContract, MemContractState, MemContractWriter,
+    MemError, MemStash, MemState, SchemaIfaces, StashInconsistency, StashProvider,
+    StashProviderError, StashReadProvider, StashWriteProvider, StateInconsistency, StateProvider,
+    StateReadProvider, StateWriteProvider, StorageError, StoreTransaction, UpdateRes,
+};
+use crate::containers::{
+    AnchorSet, ContentId, ContentRef, ContentSigs, SealWitness, SigBlob, Supplement, TrustLevel,
+};
+use crate::interface::{Iface, IfaceClass, IfaceImpl, IfaceRef};
+use crate::stl::{LIB_ID_RGB_STORAGE, STORAGE_VERSION};
+
+/// Migrations of the database schema. The database `user_version` keeps the
+/// number of the migrations which were applied to it.
+///
+/// The layout of the data in the table rows is versioned separately for each
+/// of the components, using the storage version of the data (see
+/// [`StorageError`]).
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE storage (
+        component TEXT NOT NULL PRIMARY KEY,
+        version INTEGER NOT NULL,
+        lib_id TEXT NOT NULL
+    ) STRICT;
+
+    CREATE TABLE stash_schemata (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_ifaces (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_geneses (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_suppl (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_bundles (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_extensions (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_witnesses (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_attachments (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_secret_seals (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_types (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_identities (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_libs (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE stash_sigs (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+
+    CREATE TABLE state_witnesses (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE state_contracts (key BLOB NOT NULL PRIMARY KEY, value BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+
+    CREATE TABLE index_contracts (contract_id BLOB NOT NULL PRIMARY KEY) STRICT, WITHOUT ROWID;
+    CREATE TABLE index_public_opouts (
+        contract_id BLOB NOT NULL,
+        opout BLOB NOT NULL,
+        PRIMARY KEY (contract_id, opout)
+    ) STRICT, WITHOUT ROWID;
+    CREATE TABLE index_outputs (
+        contract_id BLOB NOT NULL,
+        output BLOB NOT NULL,
+        opout BLOB NOT NULL,
+        PRIMARY KEY (contract_id, output, opout)
+    ) STRICT, WITHOUT ROWID;
+    CREATE INDEX index_outputs_by_output ON index_outputs (output);
+    CREATE TABLE index_terminals (
+        seal BLOB NOT NULL,
+        opout BLOB NOT NULL,
+        PRIMARY KEY (seal, opout)
+    ) STRICT, WITHOUT ROWID;
+    CREATE TABLE index_ops (opid BLOB NOT NULL PRIMARY KEY, bundle_id BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE index_bundles (bundle_id BLOB NOT NULL PRIMARY KEY, contract_id BLOB NOT NULL)
+        STRICT, WITHOUT ROWID;
+    CREATE TABLE index_bundle_witnesses (
+        bundle_id BLOB NOT NULL,
+        witness_id BLOB NOT NULL,
+        PRIMARY KEY (bundle_id, witness_id)
+    ) STRICT, WITHOUT ROWID;
+"];
+
+const STASH: &str = "stash";
+const STATE: &str = "state";
+const INDEX: &str = "index";
+
+const STASH_SCHEMATA: &str = "stash_schemata";
+const STASH_IFACES: &str = "stash_ifaces";
+const STASH_GENESES: &str = "stash_geneses";
+const STASH_SUPPL: &str = "stash_suppl";
+const STASH_BUNDLES: &str = "stash_bundles";
+const STASH_EXTENSIONS: &str = "stash_extensions";
+const STASH_WITNESSES: &str = "stash_witnesses";
+const STASH_ATTACHMENTS: &str = "stash_attachments";
+const STASH_SECRET_SEALS: &str = "stash_secret_seals";
+const STASH_TYPES: &str = "stash_types";
+const STASH_IDENTITIES: &str = "stash_identities";
+const STASH_LIBS: &str = "stash_libs";
+const STASH_SIGS: &str = "stash_sigs";
+
+const STATE_WITNESSES: &str = "state_witnesses";
+const STATE_CONTRACTS: &str = "state_contracts";
+
+/// Errors accessing stock data kept in an SQLite database.
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SqlError {
+    /// database error: {0}
+    #[from]
+    Sqlite(rusqlite::Error),
+
+    /// database schema version {0} is not known to this version of the
+    /// software.
+    FutureSchema(u32),
+
+    /// unable to serialize data for {0}. Details: {1}
+    Serialize(&'static str, SerializeError),
+
+    #[from]
+    #[from(DeserializeError)]
+    #[display(inner)]
+    Storage(StorageError),
+
+    #[from]
+    #[display(inner)]
+    Cache(MemError),
+}
+
+impl From<confinement::Error> for SqlError {
+    fn from(err: confinement::Error) -> Self { SqlError::Cache(err.into()) }
+}
+
+/// Errors reading stock data from an SQLite database.
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SqlReadError {
+    /// unable to read data from the database. Details: {0}
+    Database(String),
+
+    #[from]
+    #[display(inner)]
+    Inconsistency(StateInconsistency),
+}
+
+impl From<SqlError> for SqlReadError {
+    fn from(err: SqlError) -> Self { SqlReadError::Database(err.to_string()) }
+}
+
+impl From<Infallible> for SqlReadError {
+    fn from(_: Infallible) -> Self { unreachable!() }
+}
+
+/// Connection to an SQLite database keeping stock data, which is shared by
+/// [`SqlStash`], [`SqlState`] and [`SqlIndex`].
+///
+/// Transactions of the components are executed within a single database
+/// transaction. It is opened by the first of the components starting its
+/// transaction, and is committed once all the components which started their
+/// transactions have prepared them (see
+/// [`StoreTransaction::prepare_transaction`]). A rollback of any of the
+/// components reverts the changes of all of them.
+///
+/// Changes made outside of transactions are committed right away.
+#[derive(Clone)]
+pub struct SqlStore {
+    path: PathBuf,
+    inner: Arc<Mutex<SqlInner>>,
+}
+
+struct SqlInner {
+    conn: Connection,
+    /// Components which started their transactions within the open database
+    /// transaction, with the flag whether they have prepared them.
+    transaction: BTreeMap<&'static str, bool>,
+}
+
+impl SqlInner {
+    fn rollback(&mut self) {
+        self.transaction.clear();
+        if !self.conn.is_autocommit() {
+            // The rollback can't fail unless the database is already closed
+            self.conn.execute_batch("ROLLBACK").ok();
+        }
+    }
+}
+
+impl Debug for SqlStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqlStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SqlStore {
+    /// Opens the database at the `path`, creating it if necessary, and
+    /// migrates its schema to the current version.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SqlError> {
+        let path = path.as_ref().to_path_buf();
+        let conn = Connection::open(&path)?;
+        Self::with(path, conn)
+    }
+
+    /// Creates a new database kept in memory.
+    pub fn in_memory() -> Result<Self, SqlError> {
+        Self::with(PathBuf::from(":memory:"), Connection::open_in_memory()?)
+    }
+
+    fn with(path: PathBuf, mut conn: Connection) -> Result<Self, SqlError> {
+        migrate(&mut conn)?;
+        Ok(Self {
+            path,
+            inner: Arc::new(Mutex::new(SqlInner {
+                conn,
+                transaction: empty!(),
+            })),
+        })
+    }
+
+    fn inner(&self) -> MutexGuard<'_, SqlInner> {
+        // The lock may be poisoned only by a panic in the middle of a statement, which leaves
+        // the database consistent
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Creates a copy of the database in memory, which is not connected to
+    /// the database file.
+    fn detached_copy(&self) -> Self {
+        let inner = self.inner();
+        let mut conn = Connection::open_in_memory().expect("unable to create in-memory database");
+        Backup::new(&inner.conn, &mut conn)
+            .and_then(|backup| backup.run_to_completion(i32::MAX, Duration::ZERO, None))
+            .expect("unable to copy the database into memory");
+        Self {
+            path: PathBuf::from(":memory:"),
+            inner: Arc::new(Mutex::new(SqlInner {
+                conn,
+                transaction: empty!(),
+            })),
+        }
+    }
+
+    /// Checks that the data of a component were written with the current
+    /// storage version, registering the version for a component which has no
+    /// data yet.
+    fn check_storage(&self, component: &'static str) -> Result<(), SqlError> {
+        let inner = self.inner();
+        let storage = inner
+            .conn
+            .query_row(
+                "SELECT version, lib_id FROM storage WHERE component = ?1",
+                params![component],
+                |row| Ok((row.get::<_, u16>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        let Some((version, lib_id)) = storage else {
+            inner.conn.execute(
+                "INSERT INTO storage (component, version, lib_id) VALUES (?1, ?2, ?3)",
+                params![component, STORAGE_VERSION, LIB_ID_RGB_STORAGE],
+            )?;
+            return Ok(());
+        };
+        if version > STORAGE_VERSION {
+            return Err(StorageError::FutureVersion(component, version).into());
+        }
+        // Rows are persisted only since the current storage version; on its change a migration
+        // of the rows of the previous versions has to be added here
+        if version < STORAGE_VERSION {
+            return Err(StorageError::Unsupported(component, version).into());
+        }
+        let expected = storage_lib_id(version);
+        if lib_id != expected.to_string() {
+            let found = TypeLibId::from_str(&lib_id).map_err(|_| StorageError::DataMismatch {
+                expected: "type library id",
+                found: lib_id,
+            })?;
+            return Err(StorageError::LibMismatch {
+                name: component,
+                version,
+                expected,
+                found,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn begin(&self, component: &'static str) -> Result<(), SqlError> {
+        let mut inner = self.inner();
+        if inner.transaction.is_empty() {
+            inner.conn.execute_batch("BEGIN IMMEDIATE")?;
+        }
+        inner.transaction.insert(component, false);
+        Ok(())
+    }
+
+    fn prepare(&self, component: &'static str) -> Result<(), SqlError> {
+        let mut inner = self.inner();
+        let Some(prepared) = inner.transaction.get_mut(component) else {
+            return Ok(());
+        };
+        *prepared = true;
+        if inner.transaction.values().all(|prepared| *prepared) {
+            if let Err(err) = inner.conn.execute_batch("COMMIT") {
+                inner.rollback();
+                return Err(err.into());
+            }
+            inner.transaction.clear();
+        }
+        Ok(())
+    }
+
+    fn rollback(&self) { self.inner().rollback(); }
+
+    /// Reads a single column of the rows selected by a query.
+    fn select<T: StrictDecode>(&self, sql: &str, params: impl Params) -> Result<Vec<T>, SqlError> {
+        let inner = self.inner();
+        let mut stmt = inner.conn.prepare_cached(sql)?;
+        let rows = stmt.query_map(params, |row| row.get::<_, Vec<u8>>(0))?;
+        rows.map(|data| Ok(deserialize(data?)?)).collect()
+    }
+
+    fn execute(&self, sql: &str, params: impl Params) -> Result<usize, SqlError> {
+        let inner = self.inner();
+        let mut stmt = inner.conn.prepare_cached(sql)?;
+        Ok(stmt.execute(params)?)
+    }
+
+    fn read_value<V: StrictDecode>(
+        &self,
+        table: &'static str,
+        key: &impl StrictEncode,
+    ) -> Result<Option<V>, SqlError> {
+        let sql = format!("SELECT value FROM {table} WHERE key = ?1");
+        Ok(self.select(&sql, [blob(table, key)?])?.pop())
+    }
+
+    fn read_keys<K: StrictDecode>(&self, table: &'static str) -> Result<Vec<K>, SqlError> {
+        self.select(&format!("SELECT key FROM {table}"), [])
+    }
+
+    fn read_table<T: Table>(&self, table: &'static str) -> Result<T, SqlError> {
+        let inner = self.inner();
+        let mut stmt = inner
+            .conn
+            .prepare_cached(&format!("SELECT key, value FROM {table}"))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(T::from_rows(rows)?)
+    }
+
+    fn write_value(
+        &self,
+        table: &'static str,
+        key: &impl StrictEncode,
+        value: &impl StrictEncode,
+    ) -> Result<(), SqlError> {
+        let sql = format!(
+            "INSERT INTO {table} (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value"
+        );
+        self.execute(&sql, [blob(table, key)?, blob(table, value)?])?;
+        Ok(())
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), SqlError> {
+    let version = conn.pragma_query_value(None, "user_version", |row| row.get::<_, u32>(0))?;
+    if version as usize > MIGRATIONS.len() {
+        return Err(SqlError::FutureSchema(version));
+    }
+    let tx = conn.transaction()?;
+    for (no, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", no as u32 + 1)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Serialized key and value of a collection item.
+type Row = (Vec<u8>, Vec<u8>);
+
+/// Collection which is kept in memory and which items are persisted as
+/// separate rows.
+trait Table: Sized {
+    fn from_rows(rows: Vec<Row>) -> Result<Self, DeserializeError>;
+}
+
+impl<K, V, const MIN: usize, const MAX: usize> Table for Confined<BTreeMap<K, V>, MIN, MAX>
+where
+    K: Ord + Hash + StrictDecode,
+    V: StrictDecode,
+{
+    fn from_rows(rows: Vec<Row>) -> Result<Self, DeserializeError> {
+        let map = rows
+            .into_iter()
+            .map(|(key, value)| Ok((deserialize(key)?, deserialize(value)?)))
+            .collect::<Result<BTreeMap<_, _>, DeserializeError>>()?;
+        Confined::try_from(map).map_err(|e| DecodeError::DataIntegrityError(e.to_string()).into())
+    }
+}
+
+impl<T, const MIN: usize, const MAX: usize> Table for Confined<BTreeSet<T>, MIN, MAX>
+where T: Ord + Hash + StrictDecode
+{
+    fn from_rows(rows: Vec<Row>) -> Result<Self, DeserializeError> {
+        let set = rows
+            .into_iter()
+            .map(|(key, _)| deserialize(key))
+            .collect::<Result<BTreeSet<_>, DeserializeError>>()?;
+        Confined::try_from(set).map_err(|e| DecodeError::DataIntegrityError(e.to_string()).into())
+    }
+}
+
+impl Table for TypeSystem {
+    fn from_rows(rows: Vec<Row>) -> Result<Self, DeserializeError> {
+        MediumOrdMap::<SemId, Ty<SemId>>::from_rows(rows).map(TypeSystem::from)
+    }
+}
+
+fn blob(what: &'static str, value: &impl StrictEncode) -> Result<Vec<u8>, SqlError> {
+    serialize(value).map_err(|e| SqlError::Serialize(what, e))
+}
+
+fn serialize(value: &impl StrictEncode) -> Result<Vec<u8>, SerializeError> {
+    let writer = StrictWriter::in_memory::<U32MAX>();
+    Ok(value.strict_encode(writer)?.unbox().unconfine())
+}
+
+fn deserialize<T: StrictDecode>(data: Vec<u8>) -> Result<T, DeserializeError> {
+    let data = Confined::<Vec<u8>, 0, U32MAX>::try_from(data)
+        .map_err(|e| DecodeError::DataIntegrityError(e.to_string()))?;
+    let mut reader = StrictReader::in_memory::<U32MAX>(data);
+    let value = T::strict_decode(&mut reader)?;
+    let mut cursor = reader.into_cursor();
+    if !cursor.fill_buf()?.is_empty() {
+        return Err(DeserializeError::DataNotEntirelyConsumed);
+    }
+    Ok(value)
+}
+
+/// Returns an item of a collection, reading it from the database if it was
+/// not accessed before.
+fn lazy<'a, K, V>(
+    store: &SqlStore,
+    table: &'static str,
+    items: &'a BTreeMap<K, OnceLock<V>>,
+    key: K,
+) -> Result<Option<&'a V>, SqlReadError>
+where
+    K: Ord + StrictEncode,
+    V: StrictDecode,
+{
+    let Some(lock) = items.get(&key) else {
+        return Ok(None);
+    };
+    if lock.get().is_none() {
+        if let Some(value) = store.read_value(table, &key)? {
+            // The value may be already set only if it was read concurrently, in which case both
+            // values are equal.
+            let _ = lock.set(value);
+        }
+    }
+    Ok(lock.get())
+}
+
+fn lazy_keys<K: Ord + StrictDecode, V>(
+    store: &SqlStore,
+    table: &'static str,
+) -> Result<BTreeMap<K, OnceLock<V>>, SqlError> {
+    Ok(store
+        .read_keys(table)?
+        .into_iter()
+        .map(|key| (key, OnceLock::new()))
+        .collect())
+}
+
+fn persistence_error(err: SqlError) -> MemError {
+    MemError::Persistence(PersistenceError::with(err))
+}
+
+//////////
+// STASH
+//////////
+
+/// Stash keeping its data in an SQLite database.
+///
+/// State transition bundles, extensions, witnesses and attachments are read
+/// from the database on the first access; the rest of the stash data is read
+/// on open and kept in memory. Each change is written to the database right
+/// away, as a row of the changed item.
+///
+/// A rollback of a transaction drops the data kept in memory, which are read
+/// from the database again on the next access.
+#[derive(Debug)]
+pub struct SqlStash {
+    persistence: Option<Persistence<Self>>,
+    store: SqlStore,
+    cache: OnceLock<StashCache>,
+}
+
+#[derive(Debug)]
+struct StashCache {
+    /// All stash data except bundles, extensions, witnesses and attachments.
+    mem: MemStash,
+    bundles: BTreeMap<BundleId, OnceLock<TransitionBundle>>,
+    extensions: BTreeMap<OpId, OnceLock<Extension>>,
+    witnesses: BTreeMap<XWitnessId, OnceLock<SealWitness>>,
+    attachments: BTreeMap<AttachId, OnceLock<MediumBlob>>,
+}
+
+impl StashCache {
+    fn load(store: &SqlStore) -> Result<Self, SqlError> {
+        let mut mem = MemStash::in_memory();
+        mem.schemata = store.read_table(STASH_SCHEMATA)?;
+        mem.ifaces = store.read_table(STASH_IFACES)?;
+        mem.geneses = store.read_table(STASH_GENESES)?;
+        mem.suppl = store.read_table(STASH_SUPPL)?;
+        mem.secret_seals = store.read_table(STASH_SECRET_SEALS)?;
+        mem.type_system = store.read_table(STASH_TYPES)?;
+        mem.identities = store.read_table(STASH_IDENTITIES)?;
+        mem.libs = store.read_table(STASH_LIBS)?;
+        mem.sigs = store.read_table(STASH_SIGS)?;
+        Ok(Self {
+            mem,
+            bundles: lazy_keys(store, STASH_BUNDLES)?,
+            extensions: lazy_keys(store, STASH_EXTENSIONS)?,
+            witnesses: lazy_keys(store, STASH_WITNESSES)?,
+            attachments: lazy_keys(store, STASH_ATTACHMENTS)?,
+        })
+    }
+}
+
+impl SqlStash {
+    /// Opens stash kept in the database.
+    pub fn open(store: &SqlStore) -> Result<Self, SqlError> {
+        store.check_storage(STASH)?;
+        let cache = StashCache::load(store)?;
+        Ok(Self {
+            persistence: None,
+            store: store.clone(),
+            cache: OnceLock::from(cache),
+        })
+    }
+
+    fn cache(&self) -> Result<&StashCache, SqlReadError> {
+        if self.cache.get().is_none() {
+            // The value may be already set only if it was read concurrently
+            let _ = self.cache.set(StashCache::load(&self.store)?);
+        }
+        Ok(self.cache.get().expect("stash data are just read"))
+    }
+
+    fn writer(&mut self) -> Result<(&SqlStore, &mut StashCache), SqlError> {
+        if self.cache.get().is_none() {
+            let _ = self.cache.set(StashCache::load(&self.store)?);
+        }
+        Ok((&self.store, self.cache.get_mut().expect("stash data are just read")))
+    }
+}
+
+impl CloneNoPersistence for SqlStash {
+    fn clone_no_persistence(&self) -> Self {
+        let cache = OnceLock::new();
+        if let Some(data) = self.cache.get() {
+            let _ = cache.set(StashCache {
+                mem: data.mem.clone_no_persistence(),
+                bundles: data.bundles.clone(),
+                extensions: data.extensions.clone(),
+                witnesses: data.witnesses.clone(),
+                attachments: data.attachments.clone(),
+            });
+        }
+        Self {
+            persistence: None,
+            store: self.store.detached_copy(),
+            cache,
+        }
+    }
+}
+
+impl Persisting for SqlStash {
+    #[inline]
+    fn persistence(&self) -> Option<&Persistence<Self>> { self.persistence.as_ref() }
+    #[inline]
+    fn persistence_mut(&mut self) -> Option<&mut Persistence<Self>> { self.persistence.as_mut() }
+    #[inline]
+    fn as_mut_persistence(&mut self) -> &mut Option<Persistence<Self>> { &mut self.persistence }
+}
+
+impl StoreTransaction for SqlStash {
+    type TransactionErr = SqlError;
+    #[inline]
+    fn begin_transaction(&mut self) -> Result<(), Self::TransactionErr> { self.store.begin(STASH) }
+    #[inline]
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.store.prepare(STASH)
+    }
+    #[inline]
+    fn finish_transaction(&mut self) {}
+    #[inline]
+    fn rollback_transaction(&mut self) {
+        self.store.rollback();
+        self.cache = OnceLock::new();
+    }
+}
+
+impl StashProvider for SqlStash {}
+
+/// Converts errors of the in-memory stash, keeping the data read from the
+/// database.
+fn mem_read<T>(
+    res: Result<T, StashProviderError<Infallible>>,
+) -> Result<T, StashProviderError<SqlReadError>> {
+    res.map_err(|err| match err {
+        StashProviderError::Inconsistency(e) => StashProviderError::Inconsistency(e),
+        StashProviderError::Iface(e) => StashProviderError::Iface(e),
+        StashProviderError::Connectivity(e) => match e {},
+    })
+}
+
+impl StashReadProvider for SqlStash {
+    type Error = SqlReadError;
+
+    fn type_system(&self) -> Result<&TypeSystem, Self::Error> {
+        Ok(self.cache()?.mem.type_system()?)
+    }
+
+    fn lib(&self, id: LibId) -> Result<&Lib, StashProviderError<Self::Error>> {
+        let cache = self.cache().map_err(StashProviderError::Connectivity)?;
+        mem_read(cache.mem.lib(id))
+    }
+
+    fn ifaces(&self) -> Result<impl Iterator<Item = &Iface>, Self::Error> {
+        Ok(self.cache()?.mem.ifaces()?)
+    }
+
+    fn iface(&self, iface: impl Into<IfaceRef>) -> Result<&Iface, StashProviderError<Self::Error>> {
+        let cache = self.cache().map_err(StashProviderError::Connectivity)?;
+        mem_read(cache.mem.iface(iface))
+    }
+
+    fn schemata(&self) -> Result<impl Iterator<Item = &SchemaIfaces>, Self::Error> {
+        Ok(self.cache()?.mem.schemata()?)
+    }
+
+    fn schema(
+        &self,
+        schema_id: SchemaId,
+    ) -> Result<&SchemaIfaces, StashProviderError<Self::Error>> {
+        let cache = self.cache().map_err(StashProviderError::Connectivity)?;
+        mem_read(cache.mem.schema(schema_id))
+    }
+
+    fn schemata_by<C: IfaceClass>(
+        &self,
+    ) -> Result<impl Iterator<Item = &SchemaIfaces>, Self::Error> {
+        Ok(self.cache()?.mem.schemata_by::<C>()?)
+    }
+
+    fn impl_for<'a, C: IfaceClass + 'a>(
+        &'a self,
+        schema_ifaces: &'a SchemaIfaces,
+    ) -> Result<&'a IfaceImpl, StashProviderError<Self::Error>> {
+        let cache = self.cache().map_err(StashProviderError::Connectivity)?;
+        mem_read(cache.mem.impl_for::<C>(schema_ifaces))
+    }
+
+    fn geneses(&self) -> Result<impl Iterator<Item = &Genesis>, Self::Error> {
+        Ok(self.cache()?.mem.geneses()?)
+    }
+
+    fn geneses_by<C: IfaceClass>(&self) -> Result<impl Iterator<Item = &Genesis>, Self::Error> {
+        Ok(self.cache()?.mem.geneses_by::<C>()?)
+    }
+
+    fn genesis(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<&Genesis, StashProviderError<Self::Error>> {
+        let cache = self.cache().map_err(StashProviderError::Connectivity)?;
+        mem_read(cache.mem.genesis(contract_id))
+    }
+
+    fn get_trust(&self, identity: &Identity) -> Result<TrustLevel, Self::Error> {
+        Ok(self.cache()?.mem.get_trust(identity)?)
+    }
+
+    fn supplement(&self, content_ref: ContentRef) -> Result<Option<&Supplement>, Self::Error> {
+        Ok(self.cache()?.mem.supplement(content_ref)?)
+    }
+
+    fn supplements(
+        &self,
+        content_ref: ContentRef,
+    ) -> Result<impl Iterator<Item = Supplement>, Self::Error> {
+        Ok(self.cache()?.mem.supplements(content_ref)?)
+    }
+
+    fn sigs_for(&self, content_id: &ContentId) -> Result<Option<&ContentSigs>, Self::Error> {
+        Ok(self.cache()?.mem.sigs_for(content_id)?)
+    }
+
+    fn witness_ids(&self) -> Result<impl Iterator<Item = XWitnessId>, Self::Error> {
+        Ok(self.cache()?.witnesses.keys().copied())
+    }
+
+    fn bundle_ids(&self) -> Result<impl Iterator<Item = BundleId>, Self::Error> {
+        Ok(self.cache()?.bundles.keys().copied())
+    }
+
+    fn bundle(
+        &self,
+        bundle_id: BundleId,
+    ) -> Result<&TransitionBundle, StashProviderError<Self::Error>> {
+        let cache = self.cache().map_err(StashProviderError::Connectivity)?;
+        lazy(&self.store, STASH_BUNDLES, &cache.bundles, bundle_id)
+            .map_err(StashProviderError::Connectivity)?
+            .ok_or(StashInconsistency::BundleAbsent(bundle_id).into())
+    }
+
+    fn extension_ids(&self) -> Result<impl Iterator<Item = OpId>, Self::Error> {
+        Ok(self.cache()?.extensions.keys().copied())
+    }
+
+    fn extension(&self, op_id: OpId) -> Result<&Extension, StashProviderError<Self::Error>> {
+        let cache = self.cache().map_err(StashProviderError::Connectivity)?;
+        lazy(&self.store, STASH_EXTENSIONS, &cache.extensions, op_id)
+            .map_err(StashProviderError::Connectivity)?
+            .ok_or(StashInconsistency::OperationAbsent(op_id).into())
+    }
+
+    fn witness(
+        &self,
+        witness_id: XWitnessId,
+    ) -> Result<&SealWitness, StashProviderError<Self::Error>> {
+        let cache = self.cache().map_err(StashProviderError::Connectivity)?;
+        lazy(&self.store, STASH_WITNESSES, &cache.witnesses, witness_id)
+            .map_err(StashProviderError::Connectivity)?
+            .ok_or(StashInconsistency::WitnessAbsent(witness_id).into())
+    }
+
+    fn attachment(&self, id: AttachId) -> Result<Option<&MediumBlob>, Self::Error> {
+        let cache = self.cache()?;
+        lazy(&self.store, STASH_ATTACHMENTS, &cache.attachments, id)
+    }
+
+    /// Reads all the witnesses which were not accessed before.
+    fn taprets(&self) -> Result<impl Iterator<Item = (XWitnessId, TapretCommitment)>, Self::Error> {
+        let cache = self.cache()?;
+        let mut taprets = vec![];
+        for witness_id in cache.witnesses.keys() {
+            let witness = lazy(&self.store, STASH_WITNESSES, &cache.witnesses, *witness_id)?;
+            if let Some(
+                AnchorSet::Tapret(anchor)
+                | AnchorSet::Double {
+                    tapret: anchor,
+                    opret: _,
+                },
+            ) = witness.map(|witness| &witness.anchors)
+            {
+                taprets.push((*witness_id, TapretCommitment {
+                    mpc: anchor.mpc_proof.commit_id(),
+                    nonce: anchor.dbc_proof.path_proof.nonce(),
+                }));
+            }
+        }
+        Ok(taprets.into_iter())
+    }
+
+    fn seal_secret(
+        &self,
+        secret: XChain<SecretSeal>,
+    ) -> Result<Option<XChain<GraphSeal>>, Self::Error> {
+        Ok(self.cache()?.mem.seal_secret(secret)?)
+    }
+
+    fn secret_seals(&self) -> Result<impl Iterator<Item = XChain<GraphSeal>>, Self::Error> {
+        Ok(self.cache()?.mem.secret_seals()?)
+    }
+}
+
+impl StashWriteProvider for SqlStash {
+    type Error = SqlError;
+
+    fn replace_schema(&mut self, schema: Schema) -> Result<bool, Self::Error> {
+        let schema_id = schema.schema_id();
+        let (store, cache) = self.writer()?;
+        if !cache.mem.replace_schema(schema)? {
+            return Ok(false);
+        }
+        store.write_value(STASH_SCHEMATA, &schema_id, &cache.mem.schemata[&schema_id])?;
+        Ok(true)
+    }
+
+    fn replace_iface(&mut self, iface: Iface) -> Result<bool, Self::Error> {
+        let iface_id = iface.iface_id();
+        let (store, cache) = self.writer()?;
+        if !cache.mem.replace_iface(iface)? {
+            return Ok(false);
+        }
+        store.write_value(STASH_IFACES, &iface_id, &cache.mem.ifaces[&iface_id])?;
+        Ok(true)
+    }
+
+    fn replace_iimpl(&mut self, iimpl: IfaceImpl) -> Result<bool, Self::Error> {
+        let schema_id = iimpl.schema_id;
+        let (store, cache) = self.writer()?;
+        let added = cache.mem.replace_iimpl(iimpl)?;
+        store.write_value(STASH_SCHEMATA, &schema_id, &cache.mem.schemata[&schema_id])?;
+        Ok(added)
+    }
+
+    fn replace_genesis(&mut self, genesis: Genesis) -> Result<bool, Self::Error> {
+        let (store, cache) = self.writer()?;
+        store.write_value(STASH_GENESES, &genesis.contract_id(), &genesis)?;
+        Ok(cache.mem.replace_genesis(genesis)?)
+    }
+
+    fn replace_extension(&mut self, extension: Extension) -> Result<bool, Self::Error> {
+        let opid = extension.id();
+        let (store, cache) = self.writer()?;
+        store.write_value(STASH_EXTENSIONS, &opid, &extension)?;
+        let present = cache
+            .extensions
+            .insert(opid, OnceLock::from(extension))
+            .is_some();
+        Ok(!present)
+    }
+
+    fn replace_bundle(&mut self, bundle: TransitionBundle) -> Result<bool, Self::Error> {
+        let bundle_id = bundle.bundle_id();
+        let (store, cache) = self.writer()?;
+        store.write_value(STASH_BUNDLES, &bundle_id, &bundle)?;
+        let present = cache
+            .bundles
+            .insert(bundle_id, OnceLock::from(bundle))
+            .is_some();
+        Ok(!present)
+    }
+
+    fn replace_witness(&mut self, witness: SealWitness) -> Result<bool, Self::Error> {
+        let witness_id = witness.witness_id();
+        let (store, cache) = self.writer()?;
+        store.write_value(STASH_WITNESSES, &witness_id, &witness)?;
+        let present = cache
+            .witnesses
+            .insert(witness_id, OnceLock::from(witness))
+            .is_some();
+        Ok(!present)
+    }
+
+    fn replace_attachment(
+        &mut self,
+        id: AttachId,
+        attach: MediumBlob,
+    ) -> Result<bool, Self::Error> {
+        let (store, cache) = self.writer()?;
+        store.write_value(STASH_ATTACHMENTS, &id, &attach)?;
+        let present = cache
+            .attachments
+            .insert(id, OnceLock::from(attach))
+            .is_some();
+        Ok(!present)
+    }
+
+    fn replace_lib(&mut self, lib: Lib) -> Result<bool, Self::Error> {
+        let (store, cache) = self.writer()?;
+        store.write_value(STASH_LIBS, &lib.id(), &lib)?;
+        Ok(cache.mem.replace_lib(lib)?)
+    }
+
+    fn consume_types(&mut self, types: TypeSystem) -> Result<(), Self::Error> {
+        let (store, cache) = self.writer()?;
+        for (sem_id, ty) in types.iter() {
+            if cache.mem.type_system.get(*sem_id) != Some(ty) {
+                store.write_value(STASH_TYPES, sem_id, ty)?;
+            }
+        }
+        Ok(cache.mem.consume_types(types)?)
+    }
+
+    fn set_trust(&mut self, identity: Identity, trust: TrustLevel) -> Result<(), Self::Error> {
+        let (store, cache) = self.writer()?;
+        store.write_value(STASH_IDENTITIES, &identity, &trust)?;
+        Ok(cache.mem.set_trust(identity, trust)?)
+    }
+
+    fn add_supplement(&mut self, suppl: Supplement) -> Result<(), Self::Error> {
+        let content_ref = suppl.content_id;
+        let (store, cache) = self.writer()?;
+        cache.mem.add_supplement(suppl)?;
+        store.write_value(STASH_SUPPL, &content_ref, &cache.mem.suppl[&content_ref])
+    }
+
+    fn import_sigs<I>(&mut self, content_id: ContentId, sigs: I) -> Result<(), Self::Error>
+    where I: IntoIterator<Item = (Identity, SigBlob)> {
+        let (store, cache) = self.writer()?;
+        let known = cache
+            .mem
+            .identities
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        cache.mem.import_sigs(content_id, sigs)?;
+        // Signatures of unknown identities register them with the default trust level
+        for (identity, trust) in cache.mem.identities.iter() {
+            if !known.contains(identity) {
+                store.write_value(STASH_IDENTITIES, identity, trust)?;
+            }
+        }
+        if let Some(sigs) = cache.mem.sigs.get(&content_id) {
+            store.write_value(STASH_SIGS, &content_id, sigs)?;
+        }
+        Ok(())
+    }
+
+    fn add_secret_seal(&mut self, seal: XChain<GraphSeal>) -> Result<bool, Self::Error> {
+        let (store, cache) = self.writer()?;
+        if !cache.mem.add_secret_seal(seal)? {
+            return Ok(false);
+        }
+        // The set is kept as a table with empty values
+        store.write_value(STASH_SECRET_SEALS, &seal, &())?;
+        Ok(true)
+    }
+}
+
+//////////
+// STATE
+//////////
+
+/// Contract state kept in an SQLite database, with the state of each contract
+/// in a separate row.
+///
+/// On open only the ordering of the witnesses is read; the state of a contract
+/// is read once the contract is accessed for the first time. The state of the
+/// contracts which were registered or updated within a transaction, together
+/// with the changed witness ordering, is written to the database when the
+/// transaction is prepared.
+///
+/// NB: [`StateWriteProvider::prune_spent`] has to access the state of all
+/// contracts and thus reads all of them.
+#[derive(Debug)]
+pub struct SqlState {
+    persistence: Option<Persistence<Self>>,
+    store: SqlStore,
+    cache: OnceLock<StateCache>,
+    /// Contracts modified since the last write to the database.
+    touched: BTreeSet<ContractId>,
+}
+
+#[derive(Debug)]
+struct StateCache {
+    /// Witness ordering and the state of contracts which were registered or
+    /// updated since the load.
+    loaded: MemState,
+    /// State of the contracts which were not modified since the load; it is
+    /// read from the database on the first access.
+    segments: BTreeMap<ContractId, OnceLock<MemContractState>>,
+    /// Witness ordering as it is written to the database.
+    persisted: BTreeMap<XWitnessId, WitnessOrd>,
+}
+
+impl StateCache {
+    fn load(store: &SqlStore) -> Result<Self, SqlError> {
+        let mut loaded = MemState::in_memory();
+        loaded.witnesses = store.read_table(STATE_WITNESSES)?;
+        let persisted = loaded
+            .witnesses
+            .iter()
+            .map(|(id, ord)| (*id, *ord))
+            .collect();
+        Ok(Self {
+            loaded,
+            segments: lazy_keys(store, STATE_CONTRACTS)?,
+            persisted,
+        })
+    }
+
+    fn segment(
+        &self,
+        store: &SqlStore,
+        contract_id: ContractId,
+    ) -> Result<&MemContractState, SqlReadError> {
+        let lock = self
+            .segments
+            .get(&contract_id)
+            .ok_or(StateInconsistency::UnknownContract(contract_id))?;
+        if lock.get().is_none() {
+            // The value may be already set only if the state was read concurrently, in which
+            // case both values are equal.
+            let _ = lock.set(self.read_segment(store, contract_id)?);
+        }
+        Ok(lock.get().expect("contract state is just read"))
+    }
+
+    fn read_segment(
+        &self,
+        store: &SqlStore,
+        contract_id: ContractId,
+    ) -> Result<MemContractState, SqlReadError> {
+        let mut contract = store
+            .read_value::<MemContractState>(STATE_CONTRACTS, &contract_id)?
+            .ok_or(StateInconsistency::UnknownContract(contract_id))?;
+        self.loaded.restore_unspent(&mut contract);
+        Ok(contract)
+    }
+
+    /// Moves state of a contract from its segment to the loaded contracts,
+    /// such that it can be modified.
+    fn load_segment(&mut self, store: &SqlStore, contract_id: ContractId) -> Result<(), MemError> {
+        if self.loaded.debug_contracts().contains_key(&contract_id) {
+            return Ok(());
+        }
+        let Some(lock) = self.segments.get_mut(&contract_id) else {
+            return Ok(());
+        };
+        let contract = match lock.take() {
+            Some(contract) => contract,
+            None => self
+                .read_segment(store, contract_id)
+                .map_err(|e| MemError::Persistence(PersistenceError::with(e)))?,
+        };
+        self.segments.remove(&contract_id);
+        self.loaded.insert_contract(contract)?;
+        Ok(())
+    }
+}
+
+impl SqlState {
+    /// Opens contract state kept in the database.
+    pub fn open(store: &SqlStore) -> Result<Self, SqlError> {
+        store.check_storage(STATE)?;
+        let cache = StateCache::load(store)?;
+        Ok(Self {
+            persistence: None,
+            store: store.clone(),
+            cache: OnceLock::from(cache),
+            touched: empty!(),
+        })
+    }
+
+    fn cache(&self) -> Result<&StateCache, SqlReadError> {
+        if self.cache.get().is_none() {
+            // The value may be already set only if it was read concurrently
+            let _ = self.cache.set(StateCache::load(&self.store)?);
+        }
+        Ok(self.cache.get().expect("state data are just read"))
+    }
+
+    fn writer(&mut self) -> Result<(&SqlStore, &mut StateCache), MemError> {
+        if self.cache.get().is_none() {
+            let cache = StateCache::load(&self.store).map_err(persistence_error)?;
+            let _ = self.cache.set(cache);
+        }
+        Ok((&self.store, self.cache.get_mut().expect("state data are just read")))
+    }
+
+    /// Writes the state of the modified contracts and the changed witness
+    /// ordering to the database.
+    fn write_changes(&mut self) -> Result<(), SqlError> {
+        let Some(cache) = self.cache.get_mut() else {
+            return Ok(());
+        };
+        for contract_id in &self.touched {
+            if let Some(contract) = cache.loaded.debug_contracts().get(contract_id) {
+                self.store
+                    .write_value(STATE_CONTRACTS, contract_id, contract)?;
+            }
+        }
+        for (witness_id, ord) in cache.loaded.witnesses.iter() {
+            if cache.persisted.get(witness_id) != Some(ord) {
+                self.store.write_value(STATE_WITNESSES, witness_id, ord)?;
+                cache.persisted.insert(*witness_id, *ord);
+            }
+        }
+        self.touched.clear();
+        Ok(())
+    }
+
+    fn prune_all(&mut self) -> Result<usize, MemError> {
+        let (store, cache) = self.writer()?;
+        let contract_ids = cache.segments.keys().copied().collect::<Vec<_>>();
+        for contract_id in contract_ids {
+            cache.load_segment(store, contract_id)?;
+        }
+        let count = cache.loaded.prune_spent()?;
+        let contract_ids = cache.loaded.debug_contracts().keys().copied();
+        let contract_ids = contract_ids.collect::<Vec<_>>();
+        self.touched.extend(contract_ids);
+        Ok(count)
+    }
+}
+
+impl CloneNoPersistence for SqlState {
+    fn clone_no_persistence(&self) -> Self {
+        let cache = OnceLock::new();
+        if let Some(data) = self.cache.get() {
+            let _ = cache.set(StateCache {
+                loaded: data.loaded.clone_no_persistence(),
+                segments: data.segments.clone(),
+                persisted: data.persisted.clone(),
+            });
+        }
+        Self {
+            persistence: None,
+            store: self.store.detached_copy(),
+            cache,
+            touched: self.touched.clone(),
+        }
+    }
+}
+
+impl Persisting for SqlState {
+    #[inline]
+    fn persistence(&self) -> Option<&Persistence<Self>> { self.persistence.as_ref() }
+    #[inline]
+    fn persistence_mut(&mut self) -> Option<&mut Persistence<Self>> { self.persistence.as_mut() }
+    #[inline]
+    fn as_mut_persistence(&mut self) -> &mut Option<Persistence<Self>> { &mut self.persistence }
+}
+
+impl StoreTransaction for SqlState {
+    type TransactionErr = MemError;
+    #[inline]
+    fn begin_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.store.begin(STATE).map_err(persistence_error)
+    }
+    #[inline]
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.write_changes().map_err(persistence_error)?;
+        self.store.prepare(STATE).map_err(persistence_error)
+    }
+    #[inline]
+    fn finish_transaction(&mut self) {}
+    #[inline]
+    fn rollback_transaction(&mut self) {
+        self.store.rollback();
+        self.cache = OnceLock::new();
+        self.touched.clear();
+    }
+}
+
+impl StateProvider for SqlState {}
+
+impl StateReadProvider for SqlState {
+    type ContractRead<'a> = MemContract<&'a MemContractState>;
+    type Error = SqlReadError;
+
+    fn contract_state(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Self::ContractRead<'_>, Self::Error> {
+        let cache = self.cache()?;
+        if cache.loaded.debug_contracts().contains_key(&contract_id) {
+            return Ok(cache.loaded.contract_state(contract_id)?);
+        }
+        Ok(cache
+            .loaded
+            .filtered(cache.segment(&self.store, contract_id)?))
+    }
+
+    fn contract_state_at(
+        &self,
+        contract_id: ContractId,
+        point: HistoryPoint,
+    ) -> Result<Self::ContractRead<'_>, Self::Error> {
+        let cache = self.cache()?;
+        if cache.loaded.debug_contracts().contains_key(&contract_id) {
+            return Ok(cache.loaded.contract_state_at(contract_id, point)?);
+        }
+        Ok(cache
+            .loaded
+            .filtered_at(cache.segment(&self.store, contract_id)?, point))
+    }
+
+    fn is_valid_witness(&self, witness_id: XWitnessId) -> Result<bool, Self::Error> {
+        Ok(self.cache()?.loaded.is_valid_witness(witness_id)?)
+    }
+}
+
+impl StateWriteProvider for SqlState {
+    type ContractWrite<'a> = MemContractWriter<'a>;
+    type Error = MemError;
+
+    fn register_contract(
+        &mut self,
+        schema: &Schema,
+        genesis: &Genesis,
+    ) -> Result<Self::ContractWrite<'_>, Self::Error> {
+        let contract_id = genesis.contract_id();
+        self.touched.insert(contract_id);
+        let (store, cache) = self.writer()?;
+        cache.load_segment(store, contract_id)?;
+        cache.loaded.register_contract(schema, genesis)
+    }
+
+    fn update_contract(
+        &mut self,
+        contract_id: ContractId,
+    ) -> Result<Option<Self::ContractWrite<'_>>, Self::Error> {
+        self.touched.insert(contract_id);
+        let (store, cache) = self.writer()?;
+        cache.load_segment(store, contract_id)?;
+        cache.loaded.update_contract(contract_id)
+    }
+
+    fn update_witnesses(
+        &mut self,
+        resolver: impl ResolveWitness,
+        after_height: u32,
+    ) -> Result<UpdateRes, Self::Error> {
+        self.begin_transaction()?;
+        self.writer()
+            .and_then(|(_, cache)| {
+                let res = cache.loaded.update_witnesses(resolver, after_height)?;
+                // Contracts read before the update may contain pruned outputs which are no longer
+                // spent
+                for contract in cache.segments.values_mut().filter_map(OnceLock::get_mut) {
+                    cache.loaded.restore_unspent(contract);
+                }
+                Ok(res)
+            })
+            .and_then(|res| self.commit_transaction().map(|_| res))
+            .inspect_err(|_| self.rollback_transaction())
+    }
+
+    fn prune_spent(&mut self) -> Result<usize, Self::Error> {
+        self.begin_transaction()?;
+        self.prune_all()
+            .and_then(|count| self.commit_transaction().map(|_| count))
+            .inspect_err(|_| self.rollback_transaction())
+    }
+}
+
+//////////
+// INDEX
+//////////
+
+/// Index of the stash data kept in the SQLite database tables, which are
+/// queried on each access.
+#[derive(Debug)]
+pub struct SqlIndex {
+    persistence: Option<Persistence<Self>>,
+    store: SqlStore,
+}
+
+impl SqlIndex {
+    /// Opens index kept in the database.
+    pub fn open(store: &SqlStore) -> Result<Self, SqlError> {
+        store.check_storage(INDEX)?;
+        Ok(Self {
+            persistence: None,
+            store: store.clone(),
+        })
+    }
+
+    fn has_contract(&self, contract_id: ContractId) -> Result<bool, SqlError> {
+        let found = self.store.select::<ContractId>(
+            "SELECT contract_id FROM index_contracts WHERE contract_id = ?1",
+            [blob(INDEX, &contract_id)?],
+        )?;
+        Ok(!found.is_empty())
+    }
+
+    fn index_output(
+        &self,
+        contract_id: ContractId,
+        output: XOutputSeal,
+        opout: Opout,
+    ) -> Result<(), SqlError> {
+        self.store.execute(
+            "INSERT OR IGNORE INTO index_outputs (contract_id, output, opout) VALUES (?1, ?2, ?3)",
+            [blob(INDEX, &contract_id)?, blob(INDEX, &output)?, blob(INDEX, &opout)?],
+        )?;
+        Ok(())
+    }
+
+    fn extend_terminals<State: ExposedState, Seal: ExposedSeal>(
+        &self,
+        vec: &[Assign<State, Seal>],
+        opid: OpId,
+        type_id: AssignmentType,
+    ) -> Result<(), SqlError> {
+        for (no, assign) in vec.iter().enumerate() {
+            let opout = Opout::new(opid, type_id, no as u16);
+            if let Assign::Confidential { seal, .. } | Assign::ConfidentialSeal { seal, .. } =
+                assign
+            {
+                self.store.execute(
+                    "INSERT OR IGNORE INTO index_terminals (seal, opout) VALUES (?1, ?2)",
+                    [blob(INDEX, seal)?, blob(INDEX, &opout)?],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CloneNoPersistence for SqlIndex {
+    fn clone_no_persistence(&self) -> Self {
+        Self {
+            persistence: None,
+            store: self.store.detached_copy(),
+        }
+    }
+}
+
+impl Persisting for SqlIndex {
+    #[inline]
+    fn persistence(&self) -> Option<&Persistence<Self>> { self.persistence.as_ref() }
+    #[inline]
+    fn persistence_mut(&mut self) -> Option<&mut Persistence<Self>> { self.persistence.as_mut() }
+    #[inline]
+    fn as_mut_persistence(&mut self) -> &mut Option<Persistence<Self>> { &mut self.persistence }
+}
+
+impl StoreTransaction for SqlIndex {
+    type TransactionErr = SqlError;
+    #[inline]
+    fn begin_transaction(&mut self) -> Result<(), Self::TransactionErr> { self.store.begin(INDEX) }
+    #[inline]
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+        self.store.prepare(INDEX)
+    }
+    #[inline]
+    fn finish_transaction(&mut self) {}
+    #[inline]
+    fn rollback_transaction(&mut self) { self.store.rollback(); }
+}
+
+impl IndexProvider for SqlIndex {}
+
+impl IndexReadProvider for SqlIndex {
+    type Error = SqlReadError;
+
+    fn contracts_assigning(
+        &self,
+        outputs: BTreeSet<XOutputSeal>,
+    ) -> Result<impl Iterator<Item = ContractId> + '_, Self::Error> {
+        let mut contracts = BTreeSet::new();
+        for output in outputs {
+            contracts.extend(self.store.select::<ContractId>(
+                "SELECT DISTINCT contract_id FROM index_outputs WHERE output = ?1",
+                [blob(INDEX, &output)?],
+            )?);
+        }
+        Ok(contracts.into_iter())
+    }
+
+    fn public_opouts(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<BTreeSet<Opout>, IndexReadError<Self::Error>> {
+        let read = || -> Result<_, SqlError> {
+            if !self.has_contract(contract_id)? {
+                return Ok(None);
+            }
+            let opouts = self.store.select::<Opout>(
+                "SELECT opout FROM index_public_opouts WHERE contract_id = ?1",
+                [blob(INDEX, &contract_id)?],
+            )?;
+            Ok(Some(opouts.into_iter().collect()))
+        };
+        read()
+            .map_err(|e| IndexReadError::Connectivity(e.into()))?
+            .ok_or(IndexInconsistency::ContractAbsent(contract_id).into())
+    }
+
+    fn opouts_by_outputs(
+        &self,
+        contract_id: ContractId,
+        outputs: impl IntoIterator<Item = impl Into<XOutputSeal>>,
+    ) -> Result<BTreeSet<Opout>, IndexReadError<Self::Error>> {
+        let connectivity = |e: SqlError| IndexReadError::Connectivity(e.into());
+        if !self.has_contract(contract_id).map_err(connectivity)? {
+            return Err(IndexInconsistency::ContractAbsent(contract_id).into());
+        }
+        let mut opouts = BTreeSet::new();
+        for output in outputs.into_iter().map(|o| o.into()) {
+            let set = blob(INDEX, &contract_id)
+                .and_then(|contract| Ok([contract, blob(INDEX, &output)?]))
+                .and_then(|params| {
+                    self.store.select::<Opout>(
+                        "SELECT opout FROM index_outputs WHERE contract_id = ?1 AND output = ?2",
+                        params,
+                    )
+                })
+                .map_err(connectivity)?;
+            if set.is_empty() {
+                return Err(IndexInconsistency::OutpointUnknown(output, contract_id).into());
+            }
+            opouts.extend(set)
+        }
+        Ok(opouts)
+    }
+
+    fn opouts_by_terminals(
+        &self,
+        terminals: impl IntoIterator<Item = XChain<SecretSeal>>,
+    ) -> Result<BTreeSet<Opout>, Self::Error> {
+        let mut opouts = BTreeSet::new();
+        for seal in terminals {
+            opouts.extend(
+                self.store
+                    .select::<Opout>("SELECT opout FROM index_terminals WHERE seal = ?1", [
+                        blob(INDEX, &seal)?,
+                    ])?,
+            );
+        }
+        Ok(opouts)
+    }
+
+    fn bundle_id_for_op(&self, opid: OpId) -> Result<BundleId, IndexReadError<Self::Error>> {
+        blob(INDEX, &opid)
+            .and_then(|opid| {
+                self.store
+                    .select::<BundleId>("SELECT bundle_id FROM index_ops WHERE opid = ?1", [opid])
+            })
+            .map_err(|e| IndexReadError::Connectivity(e.into()))?
+            .pop()
+            .ok_or(IndexInconsistency::BundleAbsent(opid).into())
+    }
+
+    fn bundle_info(
+        &self,
+        bundle_id: BundleId,
+    ) -> Result<(impl Iterator<Item = XWitnessId>, ContractId), IndexReadError<Self::Error>> {
+        let read = || -> Result<_, SqlError> {
+            let id = blob(INDEX, &bundle_id)?;
+            let witness_ids = self.store.select::<XWitnessId>(
+                "SELECT witness_id FROM index_bundle_witnesses WHERE bundle_id = ?1",
+                [&id],
+            )?;
+            let contract_id = self.store.select::<ContractId>(
+                "SELECT contract_id FROM index_bundles WHERE bundle_id = ?1",
+                [&id],
+            )?;
+            Ok((witness_ids, contract_id.first().copied()))
+        };
+        let (witness_ids, contract_id) =
+            read().map_err(|e| IndexReadError::Connectivity(e.into()))?;
+        if witness_ids.is_empty() {
+            return Err(IndexInconsistency::BundleWitnessUnknown(bundle_id).into());
+        }
+        let contract_id =
+            contract_id.ok_or(IndexInconsistency::BundleContractUnknown(bundle_id))?;
+        Ok((witness_ids.into_iter(), contract_id))
+    }
+}
+
+impl IndexWriteProvider for SqlIndex {
+    type Error = SqlError;
+
+    fn register_contract(&mut self, contract_id: ContractId) -> Result<bool, Self::Error> {
+        let count =
+            self.store
+                .execute("INSERT OR IGNORE INTO index_contracts (contract_id) VALUES (?1)", [
+                    blob(INDEX, &contract_id)?,
+                ])?;
+        Ok(count > 0)
+    }
+
+    fn register_bundle(
+        &mut self,
+        bundle_id: BundleId,
+        witness_id: XWitnessId,
+        contract_id: ContractId,
+    ) -> Result<bool, IndexWriteError<Self::Error>> {
+        let write = || -> Result<_, SqlError> {
+            let id = blob(INDEX, &bundle_id)?;
+            let present = self
+                .store
+                .select::<ContractId>(
+                    "SELECT contract_id FROM index_bundles WHERE bundle_id = ?1",
+                    [&id],
+                )?
+                .pop();
+            if present.is_some_and(|alt| alt != contract_id) {
+                return Ok(present);
+            }
+            self.store.execute(
+                "INSERT OR IGNORE INTO index_bundle_witnesses (bundle_id, witness_id)
+                 VALUES (?1, ?2)",
+                [&id, &blob(INDEX, &witness_id)?],
+            )?;
+            self.store.execute(
+                "INSERT OR IGNORE INTO index_bundles (bundle_id, contract_id) VALUES (?1, ?2)",
+                [&id, &blob(INDEX, &contract_id)?],
+            )?;
+            Ok(present)
+        };
+        match write().map_err(IndexWriteError::Connectivity)? {
+            Some(present) if present != contract_id => {
+                Err(IndexInconsistency::DistinctBundleContract {
+                    bundle_id,
+                    present,
+                    expected: contract_id,
+                }
+                .into())
+            }
+            present => Ok(present.is_none()),
+        }
+    }
+
+    fn register_operation(
+        &mut self,
+        opid: OpId,
+        bundle_id: BundleId,
+    ) -> Result<bool, IndexWriteError<Self::Error>> {
+        let write = || -> Result<_, SqlError> {
+            let id = blob(INDEX, &opid)?;
+            let present = self
+                .store
+                .select::<BundleId>("SELECT bundle_id FROM index_ops WHERE opid = ?1", [&id])?
+                .pop();
+            if present.is_none() {
+                self.store
+                    .execute("INSERT INTO index_ops (opid, bundle_id) VALUES (?1, ?2)", [
+                        &id,
+                        &blob(INDEX, &bundle_id)?,
+                    ])?;
+            }
+            Ok(present)
+        };
+        match write().map_err(IndexWriteError::Connectivity)? {
+            Some(present) if present != bundle_id => Err(IndexInconsistency::DistinctBundleOp {
+                opid,
+                present,
+                expected: bundle_id,
+            }
+            .into()),
+            present => Ok(present.is_none()),
+        }
+    }
+
+    fn index_genesis_assignments<State: ExposedState>(
+        &mut self,
+        contract_id: ContractId,
+        vec: &[Assign<State, GenesisSeal>],
+        opid: OpId,
+        type_id: AssignmentType,
+    ) -> Result<(), IndexWriteError<Self::Error>> {
+        if !self
+            .has_contract(contract_id)
+            .map_err(IndexWriteError::Connectivity)?
+        {
+            return Err(IndexInconsistency::ContractAbsent(contract_id).into());
+        }
+        for (no, assign) in vec.iter().enumerate() {
+            let opout = Opout::new(opid, type_id, no as u16);
+            if let Assign::ConfidentialState { seal, .. } | Assign::Revealed { seal, .. } = assign {
+                let output = seal
+                    .to_output_seal()
+                    .expect("genesis seals always have outpoint");
+                self.index_output(contract_id, output, opout)
+                    .map_err(IndexWriteError::Connectivity)?;
+            }
+        }
+        self.extend_terminals(vec, opid, type_id)
+            .map_err(IndexWriteError::Connectivity)
+    }
+
+    fn index_transition_assignments<State: ExposedState>(
+        &mut self,
+        contract_id: ContractId,
+        vec: &[Assign<State, GraphSeal>],
+        opid: OpId,
+        type_id: AssignmentType,
+        witness_id: XWitnessId,
+    ) -> Result<(), IndexWriteError<Self::Error>> {
+        if !self
+            .has_contract(contract_id)
+            .map_err(IndexWriteError::Connectivity)?
+        {
+            return Err(IndexInconsistency::ContractAbsent(contract_id).into());
+        }
+        for (no, assign) in vec.iter().enumerate() {
+            let opout = Opout::new(opid, type_id, no as u16);
+            if let Assign::ConfidentialState { seal, .. } | Assign::Revealed { seal, .. } = assign {
+                let output = seal.try_to_output_seal(witness_id).unwrap_or_else(|_| {
+                    panic!(
+                        "chain mismatch between assignment vout seal ({}) and witness transaction \
+                         ({})",
+                        seal, witness_id
+                    )
+                });
+                self.index_output(contract_id, output, opout)
+                    .map_err(IndexWriteError::Connectivity)?;
+            }
+        }
+        self.extend_terminals(vec, opid, type_id)
+            .map_err(IndexWriteError::Connectivity)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use amplify::Bytes32;
+
+    use super::*;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let mut path = env::temp_dir();
+        path.push(format!("rgb-std-{name}-{}.sqlite", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn attach_id(no: u8) -> AttachId { AttachId::from(Bytes32::from_byte_array([no; 32])) }
+
+    #[test]
+    fn test_sql_store() {
+        let path = temp_db("sql-store");
+        let store = SqlStore::open(&path).unwrap();
+        let mut stash = SqlStash::open(&store).unwrap();
+        let mut state = SqlState::open(&store).unwrap();
+        let mut index = SqlIndex::open(&store).unwrap();
+        let other = Connection::open(&path).unwrap();
+        let count = |table: &str| -> u32 {
+            other
+                .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| row.get(0))
+                .unwrap()
+        };
+        let changes = || -> u64 {
+            let inner = store.inner();
+            inner
+                .conn
+                .query_row("SELECT total_changes()", [], |row| row.get(0))
+                .unwrap()
+        };
+        let blob = MediumBlob::try_from(vec![0xAB; 64]).unwrap();
+        let contract_id = ContractId::from([0xAA; 32]);
+
+        // Changes outside of transactions are written right away, one row per item
+        let before = changes();
+        assert!(stash
+            .replace_attachment(attach_id(1), blob.clone())
+            .unwrap());
+        assert_eq!(changes() - before, 1);
+        assert_eq!(count(STASH_ATTACHMENTS), 1);
+
+        // Changes within transactions are committed once all components have prepared them
+        stash.begin_transaction().unwrap();
+        state.begin_transaction().unwrap();
+        index.begin_transaction().unwrap();
+        stash
+            .replace_attachment(attach_id(2), blob.clone())
+            .unwrap();
+        assert!(index.register_contract(contract_id).unwrap());
+        index.commit_transaction().unwrap();
+        state.commit_transaction().unwrap();
+        assert_eq!(count(STASH_ATTACHMENTS), 1);
+        assert_eq!(count("index_contracts"), 0);
+        stash.commit_transaction().unwrap();
+        assert_eq!(count(STASH_ATTACHMENTS), 2);
+        assert_eq!(count("index_contracts"), 1);
+
+        // Rollback of any component reverts the changes of all of them
+        stash.begin_transaction().unwrap();
+        index.begin_transaction().unwrap();
+        stash
+            .replace_attachment(attach_id(3), blob.clone())
+            .unwrap();
+        index
+            .register_contract(ContractId::from([0xBB; 32]))
+            .unwrap();
+        index.rollback_transaction();
+        stash.rollback_transaction();
+        assert_eq!(stash.attachment(attach_id(3)).unwrap(), None);
+        assert_eq!(stash.attachment(attach_id(2)).unwrap(), Some(&blob));
+        assert!(index.public_opouts(ContractId::from([0xBB; 32])).is_err());
+        assert_eq!(count(STASH_ATTACHMENTS), 2);
+        drop((stash, state, index, store));
+
+        let store = SqlStore::open(&path).unwrap();
+        let stash = SqlStash::open(&store).unwrap();
+        let index = SqlIndex::open(&store).unwrap();
+        assert_eq!(stash.attachment(attach_id(1)).unwrap(), Some(&blob));
+        assert_eq!(stash.attachment(attach_id(3)).unwrap(), None);
+        assert!(index.public_opouts(contract_id).unwrap().is_empty());
+
+        // Data of unknown storage versions are not read
+        other
+            .execute("UPDATE storage SET version = ?1 WHERE component = ?2", params![
+                STORAGE_VERSION + 1,
+                STASH
+            ])
+            .unwrap();
+        assert!(matches!(
+            SqlStash::open(&store),
+            Err(SqlError::Storage(StorageError::FutureVersion(STASH, _)))
+        ));
+        drop((stash, index, store, other));
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -25,7 +25,7 @@ use std::fmt::Debug;
 
 use aluvm::library::{Lib, LibId};
 use amplify::confinement::{Confined, MediumBlob, TinyOrdMap};
-use amplify::{confinement, ByteArray};
+use amplify::ByteArray;
 use bp::dbc::anchor::MergeError;
 use bp::dbc::tapret::TapretCommitment;
 use bp::dbc::Anchor;
@@ -47,7 +47,8 @@ use crate::containers::{
     SigBlob, Supplement, TrustLevel, WitnessBundle,
 };
 use crate::interface::{
-    ContractBuilder, Iface, IfaceClass, IfaceId, IfaceImpl, IfaceRef, TransitionBuilder,
+    ContractBuilder, Iface, IfaceClass, IfaceId, IfaceImpl, IfaceRef, ImplInconsistency,
+    TransitionBuilder,
 };
 use crate::persistence::{ContractIfaceError, StoreTransaction};
 use crate::{MergeReveal, MergeRevealError, SecretSeal, LIB_NAME_RGB_STD};
@@ -190,6 +191,64 @@ impl SchemaIfaces {
     pub fn contains(&self, id: IfaceId) -> bool {
         self.iimpls.values().any(|iimpl| iimpl.iface_id == id)
     }
+
+    /// Checks the schema types against the types required by each of the
+    /// implemented interfaces present in `ifaces`.
+    pub fn check_types<'a>(
+        &self,
+        ifaces: impl IntoIterator<Item = (&'a IfaceId, &'a Iface)>,
+    ) -> Result<(), Vec<(IfaceId, ImplInconsistency)>> {
+        let ifaces = ifaces.into_iter().collect::<BTreeMap<_, _>>();
+        let mut errors = vec![];
+        for iimpl in self.iimpls.values() {
+            let Some(iface) = ifaces.get(&iimpl.iface_id) else {
+                continue;
+            };
+            if let Err(type_errors) = iimpl.check_types(iface, &self.schema) {
+                errors.extend(type_errors.into_iter().map(|err| (iimpl.iface_id, err)));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks that the schema conforms to all ancestors of the implemented
+    /// interfaces present in `ifaces`. Errors are reported together with the
+    /// id of the ancestor interface the schema doesn't conform to.
+    pub fn check_inheritance<'a>(
+        &self,
+        ifaces: impl IntoIterator<Item = (&'a IfaceId, &'a Iface)>,
+    ) -> Result<(), Vec<(IfaceId, ImplInconsistency)>> {
+        let ifaces = ifaces.into_iter().collect::<BTreeMap<_, _>>();
+        let mut errors = vec![];
+        for iimpl in self.iimpls.values() {
+            let Some(iface) = ifaces.get(&iimpl.iface_id) else {
+                continue;
+            };
+            let mut ancestors = iface.inherits.iter().copied().collect::<Vec<_>>();
+            let mut visited = BTreeSet::new();
+            while let Some(ancestor_id) = ancestors.pop() {
+                if !visited.insert(ancestor_id) {
+                    continue;
+                }
+                let Some(ancestor) = ifaces.get(&ancestor_id) else {
+                    continue;
+                };
+                ancestors.extend(ancestor.inherits.iter().copied());
+                if let Err(ancestor_errors) = iimpl.check_ancestor(ancestor, &self.schema) {
+                    errors.extend(ancestor_errors.into_iter().map(|err| (ancestor_id, err)));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -261,9 +320,17 @@ impl<P: StashProvider> Stash<P> {
     pub(super) fn bundle(&self, bundle_id: BundleId) -> Result<&TransitionBundle, StashError<P>> {
         Ok(self.provider.bundle(bundle_id)?)
     }
+    pub(super) fn extension(&self, opid: OpId) -> Result<&Extension, StashError<P>> {
+        Ok(self.provider.extension(opid)?)
+    }
     pub(super) fn witness(&self, witness_id: XWitnessId) -> Result<&SealWitness, StashError<P>> {
         Ok(self.provider.witness(witness_id)?)
     }
+    pub(super) fn attachment(&self, id: AttachId) -> Result<Option<&MediumBlob>, StashError<P>> {
+        self.provider
+            .attachment(id)
+            .map_err(StashError::ReadProvider)
+    }
 
     pub(super) fn supplements(
         &self,
@@ -274,21 +341,60 @@ impl<P: StashProvider> Stash<P> {
             .map_err(StashError::ReadProvider)
     }
 
-    pub(super) fn sigs_for(
-        &self,
-        content_id: &ContentId,
-    ) -> Result<Option<&ContentSigs>, StashError<P>> {
+    pub(super) fn trust(&self, identity: &Identity) -> Result<TrustLevel, StashError<P>> {
         self.provider
-            .sigs_for(content_id)
+            .get_trust(identity)
             .map_err(StashError::ReadProvider)
     }
 
-    pub(super) fn supplement(
+    /// Returns the supplement which should be used for a given content.
+    ///
+    /// Only supplements created by identities which are trusted by the stash
+    /// owner are taken into account. If there are multiple such supplements,
+    /// the ones created by ultimately trusted identities take precedence;
+    /// between supplements of the same trust level the most recent one is
+    /// selected.
+    pub(super) fn trusted_supplement(
         &self,
         content_ref: ContentRef,
-    ) -> Result<Option<&Supplement>, StashError<P>> {
+    ) -> Result<Option<Supplement>, StashError<P>> {
+        let mut best = None;
+        for suppl in self.supplements(content_ref)? {
+            let trust = self.trust(&suppl.creator)?;
+            if !trust.should_use() {
+                continue;
+            }
+            let key = (trust.must_use(), suppl.timestamp);
+            if best.as_ref().map(|(k, _)| key > *k).unwrap_or(true) {
+                best = Some((key, suppl));
+            }
+        }
+        Ok(best.map(|(_, suppl)| suppl))
+    }
+
+    /// Computes trust level for a piece of content as the highest trust level
+    /// among the identities which have signed it, either in the stash or in
+    /// the provided set of `extra` signatures. Content without any signatures
+    /// has [`TrustLevel::Unknown`] trust.
+    fn content_trust(
+        &self,
+        content_id: ContentId,
+        extra: Option<&ContentSigs>,
+    ) -> Result<TrustLevel, StashError<P>> {
+        let known = self.sigs_for(&content_id)?;
+        let mut level = None;
+        for identity in known.into_iter().chain(extra).flat_map(|sigs| sigs.keys()) {
+            let trust = self.trust(identity)?;
+            level = level.max(Some(trust));
+        }
+        Ok(level.unwrap_or_default())
+    }
+    pub(super) fn sigs_for(
+        &self,
+        content_id: &ContentId,
+    ) -> Result<Option<&ContentSigs>, StashError<P>> {
         self.provider
-            .supplement(content_ref)
+            .sigs_for(content_id)
             .map_err(StashError::ReadProvider)
     }
 
@@ -443,7 +549,8 @@ impl<P: StashProvider> Stash<P> {
                 .map_err(StashError::WriteProvider)?;
         }
 
-        // TODO: filter most trusted signers
+        // Schemata and interfaces are content-addressed, thus they can't
+        // override any existing data and are imported unconditionally.
         for schema in kit.schemata {
             self.provider
                 .replace_schema(schema)
@@ -454,23 +561,46 @@ impl<P: StashProvider> Stash<P> {
                 .replace_iface(iface)
                 .map_err(StashError::WriteProvider)?;
         }
+        // Interface implementations replace existing implementations of the
+        // same interface for a schema, so we keep the ones signed by the most
+        // trusted signers.
         for iimpl in kit.iimpls {
+            let impl_id = ContentId::IfaceImpl(iimpl.impl_id());
+            let trust = self.content_trust(impl_id, kit.signatures.get(&impl_id))?;
+            if !trust.should_accept() {
+                continue;
+            }
+            let name = &self.provider.iface(iimpl.iface_id)?.name;
+            let existing = self
+                .provider
+                .schema(iimpl.schema_id)?
+                .iimpls
+                .get(name)
+                .map(IfaceImpl::impl_id)
+                .filter(|id| *id != iimpl.impl_id());
+            if let Some(existing_id) = existing {
+                if self.content_trust(ContentId::IfaceImpl(existing_id), None)? > trust {
+                    continue;
+                }
+            }
             self.provider
                 .replace_iimpl(iimpl)
                 .map_err(StashError::WriteProvider)?;
         }
 
-        // TODO: filter out non-trusted signers
+        // Supplements from non-trusted creators are still stored, but are not
+        // used until the creator becomes trusted.
         for suppl in kit.supplements {
+            if !self.trust(&suppl.creator)?.should_accept() {
+                continue;
+            }
             self.provider
                 .add_supplement(suppl)
                 .map_err(StashError::WriteProvider)?;
         }
 
         for (content_id, sigs) in kit.signatures {
-            // TODO: Filter sigs by trust level
-            // Do not bother if we can't import all the sigs
-            self.provider.import_sigs(content_id, sigs).ok();
+            self.import_sigs(content_id, sigs)?;
         }
 
         Ok(())
@@ -503,14 +633,7 @@ impl<P: StashProvider> Stash<P> {
             .map_err(StashError::WriteProvider)?;
 
         for extension in consignment.extensions {
-            let opid = extension.id();
-            let extension = match self.provider.extension(opid) {
-                Ok(e) => e.clone().merge_reveal(extension)?,
-                Err(_) => extension,
-            };
-            self.provider
-                .replace_extension(extension)
-                .map_err(StashError::WriteProvider)?;
+            self.consume_extension(extension)?;
         }
 
         for witness_bundles in consignment.bundles {
@@ -659,6 +782,19 @@ impl<P: StashProvider> Stash<P> {
             .map_err(StashError::WriteProvider)
     }
 
+    pub(crate) fn consume_extension(
+        &mut self,
+        extension: Extension,
+    ) -> Result<bool, StashError<P>> {
+        let extension = match self.provider.extension(extension.id()).cloned() {
+            Ok(e) => e.merge_reveal(extension)?,
+            Err(_) => extension,
+        };
+        self.provider
+            .replace_extension(extension)
+            .map_err(StashError::WriteProvider)
+    }
+
     pub(crate) fn consume_bundle(
         &mut self,
         bundle: TransitionBundle,
@@ -672,6 +808,26 @@ impl<P: StashProvider> Stash<P> {
             .map_err(StashError::WriteProvider)
     }
 
+    /// Imports signatures on the content, skipping the ones made by the
+    /// identities which must not be accepted according to their trust level.
+    ///
+    /// Signatures must be validated before calling this method.
+    pub(super) fn import_sigs(
+        &mut self,
+        content_id: ContentId,
+        sigs: ContentSigs,
+    ) -> Result<(), StashError<P>> {
+        let mut accepted = Vec::with_capacity(sigs.len());
+        for (identity, sig) in sigs {
+            if self.trust(&identity)?.should_accept() {
+                accepted.push((identity, sig));
+            }
+        }
+        // Do not bother if we can't import all the sigs
+        self.provider.import_sigs(content_id, accepted).ok();
+        Ok(())
+    }
+
     pub(crate) fn store_secret_seal(
         &mut self,
         seal: XChain<GraphSeal>,
@@ -685,6 +841,20 @@ impl<P: StashProvider> Stash<P> {
         self.commit_transaction()?;
         Ok(seal)
     }
+
+    pub(crate) fn set_trust(
+        &mut self,
+        identity: Identity,
+        trust: TrustLevel,
+    ) -> Result<(), StashError<P>> {
+        self.begin_transaction()?;
+        self.provider
+            .set_trust(identity, trust)
+            .inspect_err(|_| self.rollback_transaction())
+            .map_err(StashError::WriteProvider)?;
+        self.commit_transaction()?;
+        Ok(())
+    }
 }
 
 impl<P: StashProvider> StoreTransaction for Stash<P> {
@@ -696,12 +866,14 @@ impl<P: StashProvider> StoreTransaction for Stash<P> {
             .map_err(StashError::WriteProvider)
     }
 
-    fn commit_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
         self.provider
-            .commit_transaction()
+            .prepare_transaction()
             .map_err(StashError::WriteProvider)
     }
 
+    fn finish_transaction(&mut self) { self.provider.finish_transaction() }
+
     fn rollback_transaction(&mut self) { self.provider.rollback_transaction() }
 }
 
@@ -754,6 +926,7 @@ pub trait StashReadProvider {
     fn extension_ids(&self) -> Result<impl Iterator<Item = OpId>, Self::Error>;
     fn extension(&self, op_id: OpId) -> Result<&Extension, ProviderError<Self::Error>>;
     fn witness(&self, witness_id: XWitnessId) -> Result<&SealWitness, ProviderError<Self::Error>>;
+    fn attachment(&self, id: AttachId) -> Result<Option<&MediumBlob>, Self::Error>;
 
     fn taprets(&self) -> Result<impl Iterator<Item = (XWitnessId, TapretCommitment)>, Self::Error>;
     fn seal_secret(
@@ -778,11 +951,7 @@ pub trait StashWriteProvider: StoreTransaction<TransactionErr = Self::Error> {
 
     fn replace_lib(&mut self, lib: Lib) -> Result<bool, Self::Error>;
     fn consume_types(&mut self, types: TypeSystem) -> Result<(), Self::Error>;
-    fn set_trust(
-        &mut self,
-        identity: Identity,
-        trust: TrustLevel,
-    ) -> Result<(), confinement::Error>;
+    fn set_trust(&mut self, identity: Identity, trust: TrustLevel) -> Result<(), Self::Error>;
     fn add_supplement(&mut self, suppl: Supplement) -> Result<(), Self::Error>;
     fn import_sigs<I>(&mut self, content_id: ContentId, sigs: I) -> Result<(), Self::Error>
     where I: IntoIterator<Item = (Identity, SigBlob)>;
@@ -20,7 +20,7 @@
 // limitations under the License.
 
 use std::borrow::Borrow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
 use std::fmt::Debug;
 use std::iter;
@@ -30,13 +30,14 @@ use nonasync::persistence::{CloneNoPersistence, Persisting};
 use rgb::validation::{ResolveWitness, WitnessResolverError};
 use rgb::vm::{ContractStateAccess, WitnessOrd};
 use rgb::{
-    AssetTag, AttachState, BlindingFactor, ContractId, DataState, Extension, Genesis, Operation,
-    RevealedAttach, RevealedData, RevealedValue, Schema, SchemaId, Transition, TransitionBundle,
-    VoidState, XWitnessId,
+    AssetTag, AssignmentType, AttachState, BlindingFactor, ContractId, DataState, Extension,
+    Genesis, OpId, Operation, Opout, RevealedAttach, RevealedData, RevealedValue, Schema, SchemaId,
+    Transition, TransitionBundle, VoidState, XWitnessId,
 };
 
 use crate::containers::{ConsignmentExt, ToWitnessId};
 use crate::contract::OutputAssignment;
+use crate::interface::{AssignmentsFilter, Balance, WitnessStatus};
 use crate::persistence::{StoreTransaction, UpdateRes};
 
 #[derive(Debug, Display, Error, From)]
@@ -74,6 +75,37 @@ pub enum StateInconsistency {
     AbsentWitness(XWitnessId),
 }
 
+/// Point in the blockchain history at which contract state can be
+/// reconstructed: either a block height or timestamp, or an explicit set of
+/// witnesses the state has to be limited to.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum HistoryPoint {
+    /// State with all witnesses mined at or below the block height.
+    Height(u32),
+    /// State with all witnesses mined in blocks with timestamps not later than
+    /// the given one.
+    Timestamp(i64),
+    /// State with only the given witnesses, as long as they are not archived.
+    Witnesses(BTreeSet<XWitnessId>),
+}
+
+impl HistoryPoint {
+    /// Checks whether a witness with the given ordering belongs to the history
+    /// up to this point. Archived witnesses never belong to it; tentative
+    /// witnesses belong to it only if they are listed explicitly.
+    pub fn includes(&self, witness_id: XWitnessId, ord: WitnessOrd) -> bool {
+        match (self, ord) {
+            (_, WitnessOrd::Archived) => false,
+            (HistoryPoint::Witnesses(witnesses), _) => witnesses.contains(&witness_id),
+            (HistoryPoint::Height(height), WitnessOrd::Mined(pos)) => pos.height().get() <= *height,
+            (HistoryPoint::Timestamp(timestamp), WitnessOrd::Mined(pos)) => {
+                pos.timestamp() <= *timestamp
+            }
+            (_, WitnessOrd::Tentative) => false,
+        }
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 pub enum PersistedState {
     Void,
@@ -137,6 +169,17 @@ impl<P: StateProvider> State<P> {
             .map_err(StateError::ReadProvider)
     }
 
+    #[inline]
+    pub fn contract_state_at(
+        &self,
+        contract_id: ContractId,
+        point: HistoryPoint,
+    ) -> Result<P::ContractRead<'_>, StateError<P>> {
+        self.provider
+            .contract_state_at(contract_id, point)
+            .map_err(StateError::ReadProvider)
+    }
+
     pub fn select_valid_witness(
         &self,
         witness_ids: impl IntoIterator<Item = impl Borrow<XWitnessId>>,
@@ -177,6 +220,47 @@ impl<P: StateProvider> State<P> {
         Ok(())
     }
 
+    /// Updates contract state with the data from an already known state
+    /// extension.
+    ///
+    /// State extensions are ordered by the witness of the state transition
+    /// spending them; this information is taken from the existing contract
+    /// state. If none of the extension assignments are present in the state,
+    /// the extension is not yet used in the transaction graph and the state is
+    /// left untouched.
+    pub fn update_from_extension(
+        &mut self,
+        contract_id: ContractId,
+        extension: &Extension,
+    ) -> Result<(), StateError<P>> {
+        let opid = extension.id();
+        let witness = {
+            let contract = self.contract_state(contract_id)?;
+            let witness = contract
+                .rights_all()
+                .map(|a| (a.opout, a.witness))
+                .chain(contract.fungible_all().map(|a| (a.opout, a.witness)))
+                .chain(contract.data_all().map(|a| (a.opout, a.witness)))
+                .chain(contract.attach_all().map(|a| (a.opout, a.witness)))
+                .filter(|(opout, _)| opout.op == opid)
+                .find_map(|(_, witness_id)| witness_id)
+                .and_then(|witness_id| Some((witness_id, contract.witness_ord(witness_id)?)));
+            witness
+        };
+        let Some((witness_id, witness_ord)) = witness else {
+            return Ok(());
+        };
+
+        let mut updater = self
+            .as_provider_mut()
+            .update_contract(contract_id)
+            .map_err(StateError::WriteProvider)?
+            .ok_or(StateInconsistency::UnknownContract(contract_id))?;
+        updater
+            .add_extension(extension, witness_id, witness_ord)
+            .map_err(StateError::WriteProvider)
+    }
+
     pub fn update_from_consignment<R: ResolveWitness>(
         &mut self,
         consignment: impl ConsignmentExt,
@@ -244,6 +328,12 @@ impl<P: StateProvider> State<P> {
             .update_witnesses(resolver, after_height)
             .map_err(StateError::WriteProvider)
     }
+
+    pub fn prune_spent(&mut self) -> Result<usize, StateError<P>> {
+        self.provider
+            .prune_spent()
+            .map_err(StateError::WriteProvider)
+    }
 }
 
 impl<P: StateProvider> StoreTransaction for State<P> {
@@ -255,12 +345,14 @@ impl<P: StateProvider> StoreTransaction for State<P> {
             .map_err(StateError::WriteProvider)
     }
 
-    fn commit_transaction(&mut self) -> Result<(), Self::TransactionErr> {
+    fn prepare_transaction(&mut self) -> Result<(), Self::TransactionErr> {
         self.provider
-            .commit_transaction()
+            .prepare_transaction()
             .map_err(StateError::WriteProvider)
     }
 
+    fn finish_transaction(&mut self) { self.provider.finish_transaction() }
+
     fn rollback_transaction(&mut self) { self.provider.rollback_transaction() }
 }
 
@@ -279,6 +371,16 @@ pub trait StateReadProvider {
         contract_id: ContractId,
     ) -> Result<Self::ContractRead<'_>, Self::Error>;
 
+    /// Reconstructs contract state as it was at the given point of the
+    /// blockchain history: operations with witnesses mined after the point,
+    /// tentative or archived are ignored, and outputs spent by them are
+    /// restored.
+    fn contract_state_at(
+        &self,
+        contract_id: ContractId,
+        point: HistoryPoint,
+    ) -> Result<Self::ContractRead<'_>, Self::Error>;
+
     fn is_valid_witness(&self, witness_id: XWitnessId) -> Result<bool, Self::Error>;
 }
 
@@ -303,6 +405,14 @@ pub trait StateWriteProvider: StoreTransaction<TransactionErr = Self::Error> {
         resolver: impl ResolveWitness,
         after_height: u32,
     ) -> Result<UpdateRes, Self::Error>;
+
+    /// Removes assignments spent by operations with valid witnesses from the
+    /// contract state, returning the number of the removed assignments.
+    ///
+    /// The removal must be re-org safe: if a spending witness gets archived
+    /// during [`Self::update_witnesses`], the assignments spent by it must be
+    /// restored.
+    fn prune_spent(&mut self) -> Result<usize, Self::Error>;
 }
 
 pub trait ContractStateRead: ContractStateAccess {
@@ -313,6 +423,82 @@ pub trait ContractStateRead: ContractStateAccess {
     fn fungible_all(&self) -> impl Iterator<Item = &OutputAssignment<RevealedValue>>;
     fn data_all(&self) -> impl Iterator<Item = &OutputAssignment<RevealedData>>;
     fn attach_all(&self) -> impl Iterator<Item = &OutputAssignment<RevealedAttach>>;
+
+    /// Lists fungible assignments created by operations whose witnesses were
+    /// archived, and which are therefore excluded from
+    /// [`Self::fungible_all`].
+    fn fungible_archived(&self) -> impl Iterator<Item = &OutputAssignment<RevealedValue>>;
+
+    /// Lists operations spending the output, together with their witnesses.
+    /// Operations with archived witnesses are not listed.
+    fn spent_by(&self, opout: Opout) -> impl Iterator<Item = (OpId, XWitnessId)>;
+
+    #[inline]
+    fn is_spent(&self, opout: Opout) -> bool { self.spent_by(opout).next().is_some() }
+
+    fn rights_unspent(&self) -> impl Iterator<Item = &OutputAssignment<VoidState>> {
+        self.rights_all().filter(|a| !self.is_spent(a.opout))
+    }
+    fn fungible_unspent(&self) -> impl Iterator<Item = &OutputAssignment<RevealedValue>> {
+        self.fungible_all().filter(|a| !self.is_spent(a.opout))
+    }
+    fn data_unspent(&self) -> impl Iterator<Item = &OutputAssignment<RevealedData>> {
+        self.data_all().filter(|a| !self.is_spent(a.opout))
+    }
+    fn attach_unspent(&self) -> impl Iterator<Item = &OutputAssignment<RevealedAttach>> {
+        self.attach_all().filter(|a| !self.is_spent(a.opout))
+    }
+
+    /// Computes balance of the fungible state of the given type, assigned to
+    /// the outputs matching the filter.
+    ///
+    /// Witnesses mined at `tip_height` have a single confirmation; witnesses
+    /// with less than `min_depth` confirmations are considered pending.
+    /// Assignments removed by [`StateWriteProvider::prune_spent`] are not
+    /// accounted, even if their spending witnesses are still pending.
+    ///
+    /// Returns `None` if the amounts overflow.
+    fn fungible_balance(
+        &self,
+        ty: AssignmentType,
+        filter: impl AssignmentsFilter,
+        tip_height: u32,
+        min_depth: u32,
+    ) -> Option<Balance> {
+        let settled = WitnessStatus::Confirmed {
+            tip: tip_height,
+            depth: min_depth,
+        };
+        let is_settled = |witness_id: XWitnessId| {
+            self.witness_ord(witness_id)
+                .is_some_and(|ord| settled.matches(ord))
+        };
+        let is_ours = |a: &&OutputAssignment<RevealedValue>| {
+            a.opout.ty == ty && filter.should_include(a.seal, a.witness)
+        };
+
+        let mut balance = Balance::default();
+        for assignment in self.fungible_all().filter(is_ours) {
+            let mut spenders = self.spent_by(assignment.opout).peekable();
+            let amount = if spenders.peek().is_none() {
+                match assignment.witness {
+                    Some(witness_id) if !is_settled(witness_id) => &mut balance.pending_incoming,
+                    _ => &mut balance.settled,
+                }
+            } else if spenders.all(|(_, witness_id)| !is_settled(witness_id)) {
+                &mut balance.pending_outgoing
+            } else {
+                continue;
+            };
+            amount.checked_add_assign(assignment.state.value.as_u64())?;
+        }
+        for assignment in self.fungible_archived().filter(is_ours) {
+            balance
+                .invalidated
+                .checked_add_assign(assignment.state.value.as_u64())?;
+        }
+        Some(balance)
+    }
 }
 
 pub trait ContractStateWrite {
@@ -25,45 +25,80 @@ use std::convert::Infallible;
 use std::error::Error;
 use std::fmt::Debug;
 
-use amplify::confinement::{Confined, U24};
+use amplify::confinement::{Confined, SmallVec, U24};
 use amplify::Wrapper;
 use bp::dbc::Method;
 use bp::seals::txout::CloseMethod;
 use bp::Vout;
 use chrono::Utc;
-use invoice::{Amount, Beneficiary, InvoiceState, NonFungible, RgbInvoice};
+use commit_verify::Conceal;
+use invoice::{
+    Allocation, Amount, Beneficiary, InvoiceState, NonFungible, OwnedFraction, RgbInvoice,
+    TokenIndex,
+};
 use nonasync::persistence::{CloneNoPersistence, PersistenceError, PersistenceProvider};
 use rgb::validation::{DbcProof, ResolveWitness, WitnessResolverError};
+use rgb::vm::WitnessOrd;
 use rgb::{
-    validation, AssignmentType, BlindingFactor, BundleId, ContractId, DataState, GraphSeal,
-    Identity, OpId, Operation, Opout, SchemaId, SecretSeal, Transition, TxoSeal, XChain, XOutpoint,
+    validation, Assign, AssignmentType, Assignments, AttachId, BlindingFactor, BundleId,
+    ContractId, ExposedSeal, ExposedState, Extension, GraphSeal, Identity, OpId, Operation, Opout,
+    SchemaId, SecretSeal, Transition, TransitionBundle, TxoSeal, TypedAssigns, XChain, XOutpoint,
     XOutputSeal, XWitnessId,
 };
-use strict_encoding::FieldName;
+use strict_encoding::{FieldName, StrictDeserialize, TypeName};
+use strict_types::typesys::UnknownType;
 
 use super::{
-    ContractStateRead, Index, IndexError, IndexInconsistency, IndexProvider, IndexReadProvider,
-    IndexWriteProvider, MemIndex, MemStash, MemState, PersistedState, SchemaIfaces, Stash,
-    StashDataError, StashError, StashInconsistency, StashProvider, StashReadProvider,
-    StashWriteProvider, State, StateError, StateInconsistency, StateProvider, StateReadProvider,
-    StateWriteProvider, StoreTransaction,
+    CoinSelection, ContractStateRead, ContractStateWrite, HistoryPoint, Index, IndexError,
+    IndexInconsistency, IndexProvider, IndexReadProvider, IndexWriteProvider, MemIndex, MemStash,
+    MemState, PersistedState, SchemaIfaces, SelectionCandidate, Stash, StashDataError, StashError,
+    StashInconsistency, StashProvider, StashProviderError, StashReadProvider, StashWriteProvider,
+    State, StateError, StateInconsistency, StateProvider, StateReadProvider, StateWriteProvider,
+    StoreTransaction,
 };
 use crate::containers::{
     AnchorSet, AnchoredBundleMismatch, Batch, BuilderSeal, ClientBundle, Consignment, ContainerVer,
-    ContentId, ContentRef, Contract, Fascia, Kit, SealWitness, SupplItem, SupplSub, Transfer,
-    TransitionDichotomy, TransitionInfo, TransitionInfoError, UnrelatedTransition,
-    ValidConsignment, ValidContract, ValidKit, ValidTransfer, VelocityHint, WitnessBundle,
-    SUPPL_ANNOT_VELOCITY,
+    ContentId, ContentRef, Contract, Disclosure, DumbValidator, Fascia, Kit, SealWitness,
+    SigValidator, SupplItem, SupplSub, Transfer, TransitionDichotomy, TransitionInfo,
+    TransitionInfoError, TrustLevel, UnrelatedTransition, ValidConsignment, ValidContract,
+    ValidKit, ValidTransfer, VelocityHint, WitnessBundle, SUPPL_ANNOT_VELOCITY,
 };
 use crate::info::{ContractInfo, IfaceInfo, SchemaInfo};
 use crate::interface::{
-    BuilderError, ContractBuilder, ContractIface, Iface, IfaceClass, IfaceId, IfaceRef,
-    IfaceWrapper, TransitionBuilder,
+    unspent_allocations, AssignmentsFilter, Balance, BuilderError, ContractBuilder, ContractDiff,
+    ContractError, ContractIface, Iface, IfaceClass, IfaceId, IfaceImpl, IfaceRef, IfaceWrapper,
+    OwnedAllocation, TransitionBuilder,
 };
 use crate::MergeRevealError;
 
 pub type ContractAssignments = HashMap<XOutputSeal, HashMap<Opout, PersistedState>>;
 
+/// State of a single contract assigned to a set of outputs, as it is seen
+/// through the most specific interface implemented by the contract schema.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct ContractHoldings {
+    pub info: ContractInfo,
+    /// Name of the interface used to name the allocations, or `None` if the
+    /// contract schema doesn't implement any known interface.
+    pub iface: Option<TypeName>,
+    /// Allocations grouped by their assignment names in the interface.
+    pub allocations: BTreeMap<FieldName, Vec<OwnedAllocation>>,
+    /// Allocations of the assignment types not exposed by the interface.
+    pub unnamed: Vec<OwnedAllocation>,
+}
+
+impl ContractHoldings {
+    pub fn is_empty(&self) -> bool { self.allocations.is_empty() && self.unnamed.is_empty() }
+}
+
+/// State assigned to a set of outputs across all known contracts.
+pub type Portfolio = BTreeMap<ContractId, ContractHoldings>;
+
 #[derive(Debug, Display, Error, From)]
 #[display(inner)]
 pub enum StockError<
@@ -169,6 +204,14 @@ pub enum ConsignError {
     /// too many transitions.
     TooManyBundles,
 
+    /// unable to construct consignment: history size too large, resulting in
+    /// too many state extensions.
+    TooManyExtensions,
+
+    /// unable to construct consignment: too many attachments referenced by the
+    /// contract state.
+    TooManyAttachments,
+
     #[from]
     #[display(inner)]
     MergeReveal(MergeRevealError),
@@ -250,6 +293,23 @@ pub enum ComposeError {
     #[from]
     #[display(inner)]
     Builder(BuilderError),
+
+    /// no invoices are provided.
+    NoInvoices,
+
+    /// invoices use different layer 1.
+    Layer1Mismatch,
+
+    /// invoices for contract {0} use different interfaces or operations.
+    OperationMismatch(ContractId),
+
+    #[from]
+    #[display(inner)]
+    Contract(ContractError),
+
+    #[from]
+    #[display(inner)]
+    ContractIface(ContractIfaceError),
 }
 
 impl<S: StashProvider, H: StateProvider, P: IndexProvider> From<ComposeError>
@@ -277,12 +337,37 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> From<FasciaError>
     fn from(err: FasciaError) -> Self { Self::InvalidInput(err) }
 }
 
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum DisclosureError {
+    /// bundle {1} is listed in the disclosure under a different id {0}.
+    BundleIdMismatch(BundleId, BundleId),
+
+    /// bundle {0} is not known; disclosures may reveal data only for the
+    /// bundles which were already consumed.
+    UnknownBundle(BundleId),
+
+    /// state extension {0} is not known; disclosures may reveal data only for
+    /// the state extensions which were already consumed.
+    UnknownExtension(OpId),
+}
+
+impl<S: StashProvider, H: StateProvider, P: IndexProvider> From<DisclosureError>
+    for StockError<S, H, P, DisclosureError>
+{
+    fn from(err: DisclosureError) -> Self { Self::InvalidInput(err) }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, Display, Error, From)]
 #[display(doc_comments)]
 pub enum ContractIfaceError {
     /// no known implementations of {0::<0} parent interfaces for
     /// the schema {1::<0}.
     NoAbstractImpl(IfaceId, SchemaId),
+
+    #[from]
+    #[display(inner)]
+    Contract(ContractError),
 }
 
 impl<S: StashProvider, H: StateProvider, P: IndexProvider> From<ContractIfaceError>
@@ -301,6 +386,8 @@ pub enum InputError {
     #[from]
     Fascia(FasciaError),
     #[from]
+    Disclosure(DisclosureError),
+    #[from]
     ContractIface(ContractIfaceError),
 }
 
@@ -343,6 +430,9 @@ impl From<Infallible> for ConsignError {
 impl From<Infallible> for FasciaError {
     fn from(_: Infallible) -> Self { unreachable!() }
 }
+impl From<Infallible> for DisclosureError {
+    fn from(_: Infallible) -> Self { unreachable!() }
+}
 impl From<Infallible> for ContractIfaceError {
     fn from(_: Infallible) -> Self { unreachable!() }
 }
@@ -350,11 +440,14 @@ impl From<Infallible> for ContractIfaceError {
 stock_err_conv!(Infallible, ComposeError);
 stock_err_conv!(Infallible, ConsignError);
 stock_err_conv!(Infallible, FasciaError);
+stock_err_conv!(Infallible, DisclosureError);
 stock_err_conv!(Infallible, ContractIfaceError);
 stock_err_conv!(Infallible, InputError);
+stock_err_conv!(ContractIfaceError, ComposeError);
 stock_err_conv!(ComposeError, InputError);
 stock_err_conv!(ConsignError, InputError);
 stock_err_conv!(FasciaError, InputError);
+stock_err_conv!(DisclosureError, InputError);
 stock_err_conv!(ContractIfaceError, InputError);
 
 pub type StockErrorMem<E = Infallible> = StockError<MemStash, MemState, MemIndex, E>;
@@ -428,14 +521,15 @@ impl<S: StashProvider, H: StateProvider, I: IndexProvider> Stock<S, H, I> {
             + PersistenceProvider<I>
             + 'static,
     {
-        let a = self
-            .as_stash_provider_mut()
+        // The stash is stored last, since providers may use it as a commit point for the data
+        let c = self
+            .as_index_provider_mut()
             .make_persistent(provider.clone(), autosave)?;
         let b = self
             .as_state_provider_mut()
             .make_persistent(provider.clone(), autosave)?;
-        let c = self
-            .as_index_provider_mut()
+        let a = self
+            .as_stash_provider_mut()
             .make_persistent(provider, autosave)?;
         Ok(a && b && c)
     }
@@ -443,9 +537,10 @@ impl<S: StashProvider, H: StateProvider, I: IndexProvider> Stock<S, H, I> {
     pub fn store(&mut self) -> Result<(), PersistenceError> {
         // TODO: Revert on failure
 
-        self.as_stash_provider_mut().store()?;
-        self.as_state_provider_mut().store()?;
+        // The stash is stored last, since providers may use it as a commit point for the data
         self.as_index_provider_mut().store()?;
+        self.as_state_provider_mut().store()?;
+        self.as_stash_provider_mut().store()?;
 
         Ok(())
     }
@@ -483,12 +578,30 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         Ok(self.stash.ifaces()?.map(move |iface| {
             let suppl = self
                 .stash
-                .supplement(ContentRef::Iface(iface.iface_id()))
+                .trusted_supplement(ContentRef::Iface(iface.iface_id()))
                 .ok()
                 .flatten();
-            IfaceInfo::new(iface, &names, suppl)
+            IfaceInfo::new(iface, &names, suppl.as_ref())
         }))
     }
+    /// Returns trust level of the identity, as it is known to the stock.
+    pub fn trust(&self, identity: &Identity) -> Result<TrustLevel, StockError<S, H, P>> {
+        Ok(self.stash.trust(identity)?)
+    }
+
+    /// Sets trust level for the identity.
+    ///
+    /// The trust level is used when importing kits and consignments to filter
+    /// supplements and signatures, and defines which of the stored supplements
+    /// are used by the stock.
+    pub fn set_trust(
+        &mut self,
+        identity: Identity,
+        trust: TrustLevel,
+    ) -> Result<(), StockError<S, H, P>> {
+        Ok(self.stash.set_trust(identity, trust)?)
+    }
+
     pub fn iface(&self, iface: impl Into<IfaceRef>) -> Result<&Iface, StockError<S, H, P>> {
         Ok(self.stash.iface(iface)?)
     }
@@ -582,13 +695,49 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         }))
     }
 
+    /// Returns contract state as it was at the given point of the blockchain
+    /// history. Outputs spent by witnesses outside of that point are reported
+    /// as unspent, even if they were already pruned from the current state.
+    pub fn contract_state_at(
+        &self,
+        contract_id: ContractId,
+        point: HistoryPoint,
+    ) -> Result<H::ContractRead<'_>, StockError<S, H, P>> {
+        self.state
+            .contract_state_at(contract_id, point)
+            .map_err(StockError::from)
+    }
+
     /// Returns the best matching abstract interface to a contract.
     pub fn contract_iface(
         &self,
         contract_id: ContractId,
         iface: impl Into<IfaceRef>,
     ) -> Result<ContractIface<H::ContractRead<'_>>, StockError<S, H, P, ContractIfaceError>> {
-        let (schema_ifaces, state, info) = self.contract_raw(contract_id)?;
+        let state = self.contract_state(contract_id)?;
+        self.contract_iface_with(state, iface)
+    }
+
+    /// Returns the best matching abstract interface to a contract, providing
+    /// access to the contract state as it was at the given point of the
+    /// blockchain history.
+    pub fn contract_iface_at(
+        &self,
+        contract_id: ContractId,
+        iface: impl Into<IfaceRef>,
+        point: HistoryPoint,
+    ) -> Result<ContractIface<H::ContractRead<'_>>, StockError<S, H, P, ContractIfaceError>> {
+        let state = self.contract_state_at(contract_id, point)?;
+        self.contract_iface_with(state, iface)
+    }
+
+    fn contract_iface_with<'a>(
+        &'a self,
+        state: H::ContractRead<'a>,
+        iface: impl Into<IfaceRef>,
+    ) -> Result<ContractIface<H::ContractRead<'a>>, StockError<S, H, P, ContractIfaceError>> {
+        let schema_ifaces = self.stash.schema(state.schema_id())?;
+        let info = self.contract_info(state.contract_id())?;
         let iface = self.stash.iface(iface)?;
         let iface_id = iface.iface_id();
 
@@ -607,6 +756,101 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         })
     }
 
+    /// Computes balance of the fungible state with the given name, owned by
+    /// the outputs matching the filter. See [`ContractIface::balance`] for the
+    /// details.
+    pub fn contract_balance(
+        &self,
+        contract_id: ContractId,
+        iface: impl Into<IfaceRef>,
+        name: impl Into<FieldName>,
+        filter: impl AssignmentsFilter,
+        tip_height: u32,
+        min_depth: u32,
+    ) -> Result<Balance, StockError<S, H, P, ContractIfaceError>> {
+        let contract = self.contract_iface(contract_id, iface)?;
+        Ok(contract
+            .balance(name, filter, tip_height, min_depth)
+            .map_err(ContractIfaceError::from)?)
+    }
+
+    /// Computes changes in the contract state owned by the outputs matching
+    /// the filter between two points of the blockchain history. If `to` is
+    /// not given, the current contract state is used instead.
+    ///
+    /// Useful for reporting what has changed since the last sync, i.e. after
+    /// [`Self::update_witnesses`] or [`Self::accept_transfer`].
+    pub fn contract_diff(
+        &self,
+        contract_id: ContractId,
+        from: HistoryPoint,
+        to: Option<HistoryPoint>,
+        filter: impl AssignmentsFilter + Copy,
+    ) -> Result<ContractDiff, StockError<S, H, P, ContractIfaceError>> {
+        let old = self.contract_state_at(contract_id, from)?;
+        let new = match to {
+            Some(point) => self.contract_state_at(contract_id, point)?,
+            None => self.contract_state(contract_id)?,
+        };
+        let schema = &self.stash.schema(new.schema_id())?.schema;
+        Ok(ContractDiff::compute(&old, &new, schema.global_types.keys().copied(), filter)
+            .map_err(ContractIfaceError::from)?)
+    }
+
+    /// Selects the implementation of the most specific interface (i.e. the one
+    /// with the longest inheritance chain) among the ones known for the schema.
+    fn best_impl<'a>(&self, schema_ifaces: &'a SchemaIfaces) -> Option<&'a IfaceImpl> {
+        schema_ifaces.iimpls.values().max_by_key(|iimpl| {
+            let depth = self
+                .stash
+                .iface(iimpl.iface_id)
+                .map(|iface| iface.inherits.len())
+                .unwrap_or_default();
+            (depth, iimpl.assignments.len())
+        })
+    }
+
+    /// Returns unspent state assigned to the outputs matching the filter
+    /// across all contracts known to the stock. Contracts with no unspent
+    /// state assigned to the outputs are not included.
+    ///
+    /// Allocations are named according to the most specific interface
+    /// implemented by each of the contracts.
+    pub fn portfolio(
+        &self,
+        filter: impl AssignmentsFilter + Copy,
+    ) -> Result<Portfolio, StockError<S, H, P>> {
+        let mut portfolio = Portfolio::new();
+        for genesis in self.stash.geneses()? {
+            let contract_id = genesis.contract_id();
+            let (schema_ifaces, state, info) = self.contract_raw(contract_id)?;
+            let iimpl = self.best_impl(schema_ifaces);
+            let mut holdings = ContractHoldings {
+                info,
+                iface: iimpl
+                    .and_then(|iimpl| self.stash.iface(iimpl.iface_id).ok())
+                    .map(|iface| iface.name.clone()),
+                allocations: empty!(),
+                unnamed: empty!(),
+            };
+            for allocation in unspent_allocations(&state, filter) {
+                match iimpl.and_then(|iimpl| iimpl.assignment_name(allocation.opout.ty)) {
+                    Some(name) => holdings
+                        .allocations
+                        .entry(name.clone())
+                        .or_default()
+                        .push(allocation),
+                    None => holdings.unnamed.push(allocation),
+                }
+            }
+            if !holdings.is_empty() {
+                portfolio.insert(contract_id, holdings);
+            }
+        }
+        Ok(portfolio)
+    }
+
+    /// Lists unspent state of the contract assigned to the provided outpoints.
     pub fn contract_assignments_for(
         &self,
         contract_id: ContractId,
@@ -619,7 +863,7 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         let mut res =
             HashMap::<XOutputSeal, HashMap<Opout, PersistedState>>::with_capacity(outputs.len());
 
-        for item in state.fungible_all() {
+        for item in state.fungible_unspent() {
             let outpoint = item.seal.into();
             if outputs.contains::<XOutpoint>(&outpoint) {
                 res.entry(item.seal).or_default().insert(
@@ -633,7 +877,7 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             }
         }
 
-        for item in state.data_all() {
+        for item in state.data_unspent() {
             let outpoint = item.seal.into();
             if outputs.contains::<XOutpoint>(&outpoint) {
                 res.entry(item.seal).or_default().insert(
@@ -643,7 +887,7 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             }
         }
 
-        for item in state.rights_all() {
+        for item in state.rights_unspent() {
             let outpoint = item.seal.into();
             if outputs.contains::<XOutpoint>(&outpoint) {
                 res.entry(item.seal)
@@ -652,7 +896,7 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             }
         }
 
-        for item in state.attach_all() {
+        for item in state.attach_unspent() {
             let outpoint = item.seal.into();
             if outputs.contains::<XOutpoint>(&outpoint) {
                 res.entry(item.seal).or_default().insert(
@@ -713,14 +957,19 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             .extend(scripts.into_values())
             .expect("type guarantees");
         kit.types = types;
-        Ok(kit.validate().expect("stock produced invalid kit"))
+        Ok(kit
+            .validate(&DumbValidator)
+            .expect("stock produced invalid kit"))
     }
 
+    /// Exports contract consignment containing the whole known contract history:
+    /// all its state extensions and transitions from the bundles which have a
+    /// valid witness, together with the attachments referenced by their state.
     pub fn export_contract(
         &self,
         contract_id: ContractId,
     ) -> Result<Contract, StockError<S, H, P, ConsignError>> {
-        let consignment = self.consign::<false>(contract_id, [], None)?;
+        let consignment = self.consign::<false>(contract_id, [], None, false)?;
         Ok(consignment)
     }
 
@@ -730,7 +979,36 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         outputs: impl AsRef<[XOutputSeal]>,
         secret_seal: Option<XChain<SecretSeal>>,
     ) -> Result<Transfer, StockError<S, H, P, ConsignError>> {
-        let consignment = self.consign(contract_id, outputs, secret_seal)?;
+        let consignment = self.consign(contract_id, outputs, secret_seal, false)?;
+        Ok(consignment)
+    }
+
+    /// Constructs transfer consignment which reveals only the data required
+    /// by the receiver to validate the state assigned to the provided
+    /// `outputs` and `secret_seal`.
+    ///
+    /// Unlike [`Self::transfer`], seals of the outputs which are neither
+    /// transferred nor spent within the history of the transferred state are
+    /// replaced with their concealed versions. Bundles can't be partially
+    /// revealed, so the consignment contains all transitions from the bundles
+    /// of that history, together with their own history. Assigned state of
+    /// these transitions and of their history, unless it is also a part of the
+    /// history of the transferred state, is concealed as well, and is reported
+    /// by [`Consignment::validate`] as uncheckable. Multi-protocol commitment
+    /// proofs in consignments always cover just the contract itself.
+    ///
+    /// Amounts assigned by the operations from the history of the transferred
+    /// state are kept revealed even if the receiver doesn't own them: until
+    /// range proofs are supported by the consensus library, concealed amounts
+    /// may hide an inflation of the transferred state.
+    #[allow(clippy::result_large_err)]
+    pub fn transfer_concealed(
+        &self,
+        contract_id: ContractId,
+        outputs: impl AsRef<[XOutputSeal]>,
+        secret_seal: Option<XChain<SecretSeal>>,
+    ) -> Result<Transfer, StockError<S, H, P, ConsignError>> {
+        let consignment = self.consign(contract_id, outputs, secret_seal, true)?;
         Ok(consignment)
     }
 
@@ -739,6 +1017,7 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         contract_id: ContractId,
         outputs: impl AsRef<[XOutputSeal]>,
         secret_seal: Option<XChain<SecretSeal>>,
+        conceal: bool,
     ) -> Result<Consignment<TRANSFER>, StockError<S, H, P, ConsignError>> {
         let outputs = outputs.as_ref();
 
@@ -752,10 +1031,10 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             .map(|genesis_sig| {
                 signatures.insert(ContentId::Genesis(contract_id), genesis_sig.clone())
             });
-        // Get genesis supplement by contract id
+        // Get trusted genesis supplement by contract id
         self.stash
-            .supplement(ContentRef::Genesis(contract_id))?
-            .map(|genesis_suppl| supplements.insert(genesis_suppl.clone()));
+            .trusted_supplement(ContentRef::Genesis(contract_id))?
+            .map(|genesis_suppl| supplements.insert(genesis_suppl));
         // 1. Collect initial set of anchored bundles
         // 1.1. Get all public outputs
         let mut opouts = self.index.public_opouts(contract_id)?;
@@ -767,14 +1046,26 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         );
         opouts.extend(self.index.opouts_by_terminals(secret_seal.into_iter())?);
 
-        // 1.3. Collect all state transitions assigning state to the provided outpoints
+        // 1.3. Collect all state transitions and extensions assigning state to the provided
+        // outpoints
         let mut anchored_bundles = BTreeMap::<BundleId, ClientBundle>::new();
         let mut transitions = BTreeMap::<OpId, Transition>::new();
+        let mut extensions = BTreeMap::<OpId, Extension>::new();
         let mut terminals = BTreeMap::<BundleId, XChain<SecretSeal>>::new();
+        let mut ids = vec![];
+        // Outputs which seals must remain revealed in the concealed consignment
+        let mut revealed = opouts.clone();
+        // Operations from the history of the transferred state, which assigned state must remain
+        // revealed in the concealed consignment
+        let mut history = opouts.iter().map(|opout| opout.op).collect::<BTreeSet<_>>();
         for opout in opouts {
             if opout.op == contract_id {
                 continue; // we skip genesis since it will be present anywhere
             }
+            if let Some(extension) = self.known_extension(opout.op)? {
+                extensions.insert(opout.op, extension.clone());
+                continue;
+            }
 
             let transition = self.transition(opout.op)?;
             transitions.insert(opout.op, transition.clone());
@@ -793,39 +1084,143 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
 
             if let Entry::Vacant(entry) = anchored_bundles.entry(bundle_id) {
                 entry.insert(self.client_bundle(bundle_id)?);
+                if conceal {
+                    ids.extend(self.stash.bundle(bundle_id)?.known_transitions.keys());
+                }
             }
         }
 
-        // 2. Collect all state transitions between terminals and genesis
-        let mut ids = vec![];
+        // 1.4. Contract exports contain the whole known history of the contract, including
+        // state extensions and transitions which do not assign state to the public outputs
+        if !TRANSFER {
+            for opid in self
+                .stash
+                .as_provider()
+                .extension_ids()
+                .map_err(StashError::ReadProvider)?
+            {
+                let extension = self.stash.extension(opid)?;
+                if extension.contract_id == contract_id {
+                    extensions.insert(opid, extension.clone());
+                }
+            }
+            for (id, bundle, witness_ids) in self.stash_bundles()? {
+                // Bundles which were never (or are no longer) mined do not contribute to the
+                // contract state
+                if id != contract_id || self.state.select_valid_witness(witness_ids).is_err() {
+                    continue;
+                }
+                ids.extend(bundle.known_transitions.keys().copied());
+            }
+        }
+
+        // 2. Collect all state transitions and extensions between terminals and genesis
         for transition in transitions.values() {
             ids.extend(transition.inputs().iter().map(|input| input.prev_out.op));
         }
+        for extension in extensions.values() {
+            ids.extend(extension.redeemed.values());
+        }
         while let Some(id) = ids.pop() {
             if id == contract_id {
                 continue; // we skip genesis since it will be present anywhere
             }
+            if let Some(extension) = self.known_extension(id)? {
+                ids.extend(extension.redeemed.values());
+                extensions.insert(id, extension.clone());
+                continue;
+            }
             let transition = self.transition(id)?;
             ids.extend(transition.inputs().iter().map(|input| input.prev_out.op));
             transitions.insert(id, transition.clone());
             let bundle_id = self.index.bundle_id_for_op(transition.id())?;
-            anchored_bundles
-                .entry(bundle_id)
-                .or_insert(self.client_bundle(bundle_id)?.clone())
-                .reveal_transition(transition.clone())?;
+            let bundle = match anchored_bundles.entry(bundle_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    // Bundles can't be partially revealed, so concealed consignments must
+                    // contain the history of all the transitions from the bundle
+                    if conceal {
+                        ids.extend(self.stash.bundle(bundle_id)?.known_transitions.keys());
+                    }
+                    entry.insert(self.client_bundle(bundle_id)?)
+                }
+            };
+            bundle.reveal_transition(transition.clone())?;
+        }
+
+        let mut genesis = self.stash.genesis(contract_id)?.clone();
+
+        // 3. Conceal everything the receiver doesn't need
+        if conceal {
+            for transition in transitions.values() {
+                revealed.extend(transition.inputs().iter().map(|input| input.prev_out));
+            }
+            // Transitions from the bundles of the history and their own history are not the
+            // part of the history unless they are ancestors of the transferred state
+            let mut queue = history.iter().copied().collect::<Vec<_>>();
+            while let Some(opid) = queue.pop() {
+                let prev_ids = match (transitions.get(&opid), extensions.get(&opid)) {
+                    (Some(transition), _) => transition
+                        .inputs()
+                        .iter()
+                        .map(|input| input.prev_out.op)
+                        .collect::<Vec<_>>(),
+                    (None, Some(extension)) => extension.redeemed.values().copied().collect(),
+                    (None, None) => continue,
+                };
+                queue.extend(prev_ids.into_iter().filter(|id| history.insert(*id)));
+            }
+            for bundle in anchored_bundles.values_mut() {
+                bundle.update_transitions(|opid, transition| {
+                    if history.contains(&opid) {
+                        conceal_seals(&mut transition.assignments, opid, &revealed)
+                    } else {
+                        conceal_state(&mut transition.assignments, opid, &revealed)
+                    }
+                });
+            }
+            let genesis_id = genesis.id();
+            conceal_seals(&mut genesis.assignments, genesis_id, &revealed);
+            for (opid, extension) in &mut extensions {
+                if history.contains(opid) {
+                    conceal_seals(&mut extension.assignments, *opid, &revealed);
+                } else {
+                    conceal_state(&mut extension.assignments, *opid, &revealed);
+                }
+            }
+        }
+
+        // 4. Collect attachments referenced by the state of the exported operations, skipping
+        // the operations which state is concealed
+        let mut attach_ids = BTreeSet::new();
+        collect_attach_ids(&genesis, &mut attach_ids);
+        for (opid, transition) in &transitions {
+            if !conceal || history.contains(opid) {
+                collect_attach_ids(transition, &mut attach_ids);
+            }
+        }
+        for (opid, extension) in &extensions {
+            if !conceal || history.contains(opid) {
+                collect_attach_ids(extension, &mut attach_ids);
+            }
+        }
+        let mut attachments = BTreeMap::new();
+        for id in attach_ids {
+            if let Some(attach) = self.stash.attachment(id)? {
+                attachments.insert(id, attach.clone());
+            }
         }
 
-        let genesis = self.stash.genesis(contract_id)?.clone();
         // Get schema signature by schema id
         self.stash
             .sigs_for(&ContentId::Schema(genesis.schema_id))?
             .map(|schema_signature| {
                 signatures.insert(ContentId::Schema(genesis.schema_id), schema_signature.clone())
             });
-        // Get schema supplement by schema id
+        // Get trusted schema supplement by schema id
         self.stash
-            .supplement(ContentRef::Schema(genesis.schema_id))?
-            .map(|schema_suppl| supplements.insert(schema_suppl.clone()));
+            .trusted_supplement(ContentRef::Schema(genesis.schema_id))?
+            .map(|schema_suppl| supplements.insert(schema_suppl));
 
         let schema_ifaces = self.stash.schema(genesis.schema_id)?.clone();
         let mut ifaces = BTreeMap::new();
@@ -843,14 +1238,14 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
                     signatures
                         .insert(ContentId::IfaceImpl(iimpl.impl_id()), iimpl_signature.clone())
                 });
-            // Get iface and iimpl supplement by iface id and iimpl id
+            // Get trusted iface and iimpl supplement by iface id and iimpl id
             self.stash
-                .supplement(ContentRef::Iface(iface.iface_id()))?
-                .map(|iface_suppl| supplements.insert(iface_suppl.clone()));
+                .trusted_supplement(ContentRef::Iface(iface.iface_id()))?
+                .map(|iface_suppl| supplements.insert(iface_suppl));
 
             self.stash
-                .supplement(ContentRef::IfaceImpl(iimpl.impl_id()))?
-                .map(|iimpl_suppl| supplements.insert(iimpl_suppl.clone()));
+                .trusted_supplement(ContentRef::IfaceImpl(iimpl.impl_id()))?
+                .map(|iimpl_suppl| supplements.insert(iimpl_suppl));
 
             ifaces.insert(iface.clone(), iimpl);
         }
@@ -872,6 +1267,10 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             .map_err(|_| ConsignError::TooManyBundles)?;
         let terminals =
             Confined::try_from(terminals).map_err(|_| ConsignError::TooManyTerminals)?;
+        let extensions = Confined::try_from_iter(extensions.into_values())
+            .map_err(|_| ConsignError::TooManyExtensions)?;
+        let attachments =
+            Confined::try_from(attachments).map_err(|_| ConsignError::TooManyAttachments)?;
 
         let (types, scripts) = self.stash.extract(&schema_ifaces.schema, ifaces.keys())?;
         let scripts = Confined::from_iter_checked(scripts.into_values());
@@ -879,7 +1278,6 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             Confined::try_from(supplements).map_err(|_| ConsignError::TooManySupplements)?;
         let signatures =
             Confined::try_from(signatures).map_err(|_| ConsignError::TooManySignatures)?;
-        // TODO: Conceal everything we do not need
         // TODO: Add known sigs to the consignment
 
         Ok(Consignment {
@@ -891,8 +1289,8 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             genesis,
             terminals,
             bundles,
-            extensions: none!(),
-            attachments: none!(),
+            extensions,
+            attachments,
 
             signatures,
             supplements,
@@ -925,6 +1323,113 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         )
     }
 
+    /// Composes a batch of state transitions satisfying requirements of the
+    /// invoice, selecting outputs to spend among the ones with unspent state
+    /// matching the `filter` using the provided `selection` strategy.
+    ///
+    /// Returns the selected outputs together with the composed batch.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn compose_auto(
+        &self,
+        invoice: &RgbInvoice,
+        filter: impl AssignmentsFilter,
+        selection: CoinSelection,
+        method: CloseMethod,
+        beneficiary_vout: Option<impl Into<Vout>>,
+        allocator: impl Fn(ContractId, AssignmentType, VelocityHint) -> Option<Vout>,
+    ) -> Result<(BTreeSet<XOutputSeal>, Batch), StockError<S, H, P, ComposeError>> {
+        let contract_id = invoice.contract.ok_or(ComposeError::NoContract)?;
+        let iface = invoice.iface.as_ref().ok_or(ComposeError::NoIface)?;
+        let builder =
+            self.transition_builder(contract_id, iface.clone(), invoice.operation.clone())?;
+        let assignment_name = invoice
+            .assignment
+            .as_ref()
+            .or_else(|| builder.default_assignment().ok())
+            .ok_or(BuilderError::NoDefaultAssignment)?
+            .clone();
+        let contract = self.contract_iface(contract_id, iface.clone())?;
+
+        // Enumerate allocations of the invoiced state
+        let (target, allocations) = match &invoice.owned_state {
+            InvoiceState::Void => {
+                let allocations = contract
+                    .rights(assignment_name, filter)
+                    .map_err(ComposeError::from)?
+                    .map(|a| (a.seal, a.witness, 1))
+                    .collect::<Vec<_>>();
+                (1, allocations)
+            }
+            InvoiceState::Amount(amount) => {
+                let allocations = contract
+                    .fungible(assignment_name, filter)
+                    .map_err(ComposeError::from)?
+                    .map(|a| (a.seal, a.witness, a.state.value()))
+                    .collect();
+                (amount.value(), allocations)
+            }
+            InvoiceState::Data(NonFungible::RGB21(allocation)) => {
+                let allocations = contract
+                    .data(assignment_name, filter)
+                    .map_err(ComposeError::from)?
+                    .filter_map(|a| {
+                        let input = Allocation::from_strict_serialized(a.state.to_inner()).ok()?;
+                        (input.token_index() == allocation.token_index()).then_some((
+                            a.seal,
+                            a.witness,
+                            input.fraction().value(),
+                        ))
+                    })
+                    .collect();
+                (allocation.fraction().value(), allocations)
+            }
+            InvoiceState::Attach(attach_id) => {
+                let allocations = contract
+                    .attachments(assignment_name, filter)
+                    .map_err(ComposeError::from)?
+                    .filter(|a| a.state.id == *attach_id)
+                    .map(|a| (a.seal, a.witness, 1))
+                    .collect();
+                (1, allocations)
+            }
+        };
+
+        let mut outputs = HashMap::<XOutputSeal, (u64, bool)>::new();
+        for (seal, witness, value) in allocations {
+            let mined = witness.map_or(true, |id| {
+                matches!(contract.state.witness_ord(id), Some(WitnessOrd::Mined(_)))
+            });
+            let entry = outputs.entry(seal).or_insert((0, true));
+            entry.0 = entry.0.saturating_add(value);
+            entry.1 &= mined;
+        }
+        let mut candidates = Vec::with_capacity(outputs.len());
+        for (output, (value, mined)) in outputs {
+            let blanks = self
+                .contracts_assigning([output])?
+                .filter(|id| *id != contract_id)
+                .count();
+            candidates.push(SelectionCandidate {
+                output,
+                value,
+                blanks,
+                mined,
+            });
+        }
+
+        let prev_outputs = selection
+            .select(candidates, target)
+            .ok_or(ComposeError::InsufficientState)?;
+        let batch = self.compose(
+            invoice,
+            prev_outputs.iter().copied(),
+            method,
+            beneficiary_vout,
+            allocator,
+        )?;
+        Ok((prev_outputs, batch))
+    }
+
     /// Composes a batch of state transitions updating state for the provided
     /// set of previous outputs, satisfying requirements of the invoice, paying
     /// the change back and including the necessary blank state transitions.
@@ -940,20 +1445,110 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         pedersen_blinder: impl Fn(ContractId, AssignmentType) -> BlindingFactor,
         seal_blinder: impl Fn(ContractId, AssignmentType) -> u64,
     ) -> Result<Batch, StockError<S, H, P, ComposeError>> {
-        let layer1 = invoice.layer1();
+        self.compose_many_deterministic(
+            [(invoice, beneficiary_vout.map(Into::into))],
+            prev_outputs,
+            method,
+            priority,
+            allocator,
+            pedersen_blinder,
+            seal_blinder,
+        )
+    }
+
+    /// Composes a batch of state transitions paying multiple invoices at once.
+    ///
+    /// Each invoice is provided together with the number of the beneficiary
+    /// output in the witness transaction, which must be present for invoices
+    /// using witness vout beneficiaries. See [`Self::compose_many_deterministic`]
+    /// for the details.
+    #[allow(clippy::result_large_err)]
+    pub fn compose_many<'i>(
+        &self,
+        invoices: impl IntoIterator<Item = (&'i RgbInvoice, Option<Vout>)>,
+        prev_outputs: impl IntoIterator<Item = impl Into<XOutputSeal>>,
+        method: CloseMethod,
+        allocator: impl Fn(ContractId, AssignmentType, VelocityHint) -> Option<Vout>,
+    ) -> Result<Batch, StockError<S, H, P, ComposeError>> {
+        self.compose_many_deterministic(
+            invoices,
+            prev_outputs,
+            method,
+            u64::MAX,
+            allocator,
+            |_, _| BlindingFactor::random(),
+            |_, _| rand::random(),
+        )
+    }
+
+    /// Composes a batch of state transitions updating state for the provided
+    /// set of previous outputs, satisfying requirements of all the invoices,
+    /// paying the change back and including the necessary blank state
+    /// transitions.
+    ///
+    /// Beneficiaries of the invoices for the same contract are paid by a single
+    /// state transition per seal closing method, which also contains a single
+    /// change output per each of the invoiced assignment types. Invoices for
+    /// the same contract must use the same interface and operation, and all
+    /// invoices must use the same layer 1.
+    ///
+    /// The main transition of the batch pays the contract of the first invoice;
+    /// transitions for the other invoiced contracts are put into the batch
+    /// next to the blank transitions.
+    #[allow(clippy::too_many_arguments, clippy::result_large_err)]
+    pub fn compose_many_deterministic<'i>(
+        &self,
+        invoices: impl IntoIterator<Item = (&'i RgbInvoice, Option<Vout>)>,
+        prev_outputs: impl IntoIterator<Item = impl Into<XOutputSeal>>,
+        method: CloseMethod,
+        priority: u64,
+        allocator: impl Fn(ContractId, AssignmentType, VelocityHint) -> Option<Vout>,
+        pedersen_blinder: impl Fn(ContractId, AssignmentType) -> BlindingFactor,
+        seal_blinder: impl Fn(ContractId, AssignmentType) -> u64,
+    ) -> Result<Batch, StockError<S, H, P, ComposeError>> {
         let prev_outputs = prev_outputs
             .into_iter()
             .map(|o| o.into())
             .collect::<HashSet<XOutputSeal>>();
 
+        // 1. Group invoices by contract
+        let mut layer1 = None;
+        let mut contracts = Vec::<(ContractId, Vec<(&RgbInvoice, Option<Vout>)>)>::new();
+        for (invoice, beneficiary_vout) in invoices {
+            if let Some(expiry) = invoice.expiry {
+                if expiry < Utc::now().timestamp() {
+                    return Err(ComposeError::InvoiceExpired.into());
+                }
+            }
+            if *layer1.get_or_insert(invoice.layer1()) != invoice.layer1() {
+                return Err(ComposeError::Layer1Mismatch.into());
+            }
+            let contract_id = invoice.contract.ok_or(ComposeError::NoContract)?;
+            if invoice.iface.is_none() {
+                return Err(ComposeError::NoIface.into());
+            }
+            match contracts.iter_mut().find(|(id, _)| *id == contract_id) {
+                Some((_, list)) => {
+                    let (first, _) = list[0];
+                    if first.iface != invoice.iface || first.operation != invoice.operation {
+                        return Err(ComposeError::OperationMismatch(contract_id).into());
+                    }
+                    list.push((invoice, beneficiary_vout));
+                }
+                None => contracts.push((contract_id, vec![(invoice, beneficiary_vout)])),
+            }
+        }
+        let layer1 = layer1.ok_or(ComposeError::NoInvoices)?;
+        let (_, first) = &contracts[0];
+        let iface = first[0].0.iface.clone().expect("checked above");
+
         #[allow(clippy::type_complexity)]
         let output_for_assignment =
             |id: ContractId,
              assignment_type: AssignmentType|
              -> Result<BuilderSeal<GraphSeal>, StockError<S, H, P, ComposeError>> {
-                let mut suppl = self.stash.supplements(ContentRef::Genesis(id))?;
+                let suppl = self.stash.trusted_supplement(ContentRef::Genesis(id))?;
                 let velocity = suppl
-                    .next()
                     .and_then(|suppl| {
                         suppl
                             .get(
@@ -973,275 +1568,395 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
                 Ok(BuilderSeal::Revealed(XChain::with(layer1, seal)))
             };
 
-        // 1. Prepare the data
-        if let Some(expiry) = invoice.expiry {
-            if expiry < Utc::now().timestamp() {
-                return Err(ComposeError::InvoiceExpired.into());
-            }
+        // 2. Prepare transitions paying the invoices
+        let mut paying = Vec::with_capacity(contracts.len());
+        for (contract_id, invoices) in &contracts {
+            paying.push(self.compose_contract(
+                *contract_id,
+                invoices,
+                &prev_outputs,
+                method,
+                &output_for_assignment,
+                &pedersen_blinder,
+                &seal_blinder,
+            )?);
         }
-        let contract_id = invoice.contract.ok_or(ComposeError::NoContract)?;
-        let iface = invoice.iface.as_ref().ok_or(ComposeError::NoIface)?;
-        let mut main_builder =
-            self.transition_builder(contract_id, iface.clone(), invoice.operation.clone())?;
-        let assignment_name = invoice
-            .assignment
-            .as_ref()
-            .or_else(|| main_builder.default_assignment().ok())
-            .ok_or(BuilderError::NoDefaultAssignment)?
-            .clone();
-        let assignment_id = main_builder
-            .assignments_type(&assignment_name)
-            .ok_or(BuilderError::InvalidStateField(assignment_name.clone()))?;
+        let mut paying = paying.into_iter();
+        let main = paying.next().expect("at least one contract is present");
+        let mut blanks = Confined::<Vec<_>, 0, { U24 - 1 }>::try_from_iter(paying)
+            .map_err(|_| ComposeError::TooManyBlanks)?;
 
-        // If there are inputs which are using different seal closing method from our
-        // wallet (and thus main state transition) we need to put them aside and
-        // allocate a different state transition spending them as a change.
-        let mut alt_builder =
-            self.transition_builder(contract_id, iface.clone(), invoice.operation.clone())?;
-        let mut alt_inputs = Vec::<XOutputSeal>::new();
-
-        let layer1 = invoice.beneficiary.chain_network().layer1();
-        let beneficiary = match (invoice.beneficiary.into_inner(), beneficiary_vout) {
-            (Beneficiary::BlindedSeal(seal), None) => {
-                BuilderSeal::Concealed(XChain::with(layer1, seal))
-            }
-            (Beneficiary::BlindedSeal(_), Some(_)) => {
-                return Err(ComposeError::BeneficiaryVout.into());
-            }
-            (Beneficiary::WitnessVout(payload), Some(vout)) => {
-                let blinding = seal_blinder(contract_id, assignment_id);
-                let seal = GraphSeal::with_blinded_vout(payload.method, vout, blinding);
-                BuilderSeal::Revealed(XChain::with(layer1, seal))
+        // 3. Prepare other transitions
+        // Enumerate state
+        let mut spent_state =
+            HashMap::<ContractId, HashMap<XOutputSeal, HashMap<Opout, PersistedState>>>::new();
+        for id in self.contracts_assigning(prev_outputs.iter().copied())? {
+            // Skip invoiced contracts
+            if contracts.iter().any(|(contract_id, _)| *contract_id == id) {
+                continue;
             }
-            (Beneficiary::WitnessVout(_), None) => {
-                return Err(ComposeError::NoBeneficiaryOutput.into());
+            let state = self.contract_assignments_for(id, prev_outputs.iter().copied())?;
+            let entry = spent_state.entry(id).or_default();
+            for (seal, assigns) in state {
+                entry.entry(seal).or_default().extend(assigns);
             }
-        };
+        }
 
-        // 2. Prepare transition
-        let mut main_inputs = Vec::<XOutputSeal>::new();
-        let mut sum_inputs = Amount::ZERO;
-        let mut sum_alt = Amount::ZERO;
-        let mut data_inputs = vec![];
-        let mut data_main = true;
-        let lookup_state =
-            if let InvoiceState::Data(NonFungible::RGB21(allocation)) = &invoice.owned_state {
-                Some(DataState::from(*allocation))
-            } else {
-                None
+        // Construct blank transitions
+        for (id, list) in spent_state {
+            let mut blank_builder_tapret = self.blank_builder(id, iface.clone())?;
+            let mut blank_builder_opret = self.blank_builder(id, iface.clone())?;
+            let mut outputs_tapret = Vec::with_capacity(list.len());
+            let mut outputs_opret = Vec::with_capacity(list.len());
+            for (output, assigns) in list {
+                match output.method() {
+                    Method::TapretFirst => outputs_tapret.push(output),
+                    Method::OpretFirst => outputs_opret.push(output),
+                }
+                for (opout, state) in assigns {
+                    let seal = output_for_assignment(id, opout.ty)?;
+                    match output.method() {
+                        Method::TapretFirst => {
+                            blank_builder_tapret = blank_builder_tapret
+                                .add_input(opout, state.clone())?
+                                .add_owned_state_raw(opout.ty, seal, state)?
+                        }
+                        Method::OpretFirst => {
+                            blank_builder_opret = blank_builder_opret
+                                .add_input(opout, state.clone())?
+                                .add_owned_state_raw(opout.ty, seal, state)?
+                        }
+                    }
+                }
+            }
+
+            let mut dicho = vec![];
+            for (blank_builder, outputs) in
+                [(blank_builder_tapret, outputs_tapret), (blank_builder_opret, outputs_opret)]
+            {
+                if !blank_builder.has_inputs() {
+                    continue;
+                }
+                let transition = blank_builder.complete_transition()?;
+                let info = TransitionInfo::new(transition, outputs).map_err(|e| {
+                    debug_assert!(!matches!(e, TransitionInfoError::CloseMethodDivergence(_)));
+                    ComposeError::TooManyInputs
+                })?;
+                dicho.push(info);
+            }
+            blanks
+                .push(TransitionDichotomy::from_iter(dicho))
+                .map_err(|_| ComposeError::TooManyBlanks)?;
+        }
+
+        let mut batch = Batch { main, blanks };
+        batch.set_priority(priority);
+        Ok(batch)
+    }
+
+    /// Composes state transitions for a single contract paying all the
+    /// provided invoices, split by the seal closing method of the spent
+    /// outputs.
+    #[allow(clippy::too_many_arguments, clippy::result_large_err, clippy::type_complexity)]
+    fn compose_contract(
+        &self,
+        contract_id: ContractId,
+        invoices: &[(&RgbInvoice, Option<Vout>)],
+        prev_outputs: &HashSet<XOutputSeal>,
+        method: CloseMethod,
+        output_for_assignment: &impl Fn(
+            ContractId,
+            AssignmentType,
+        ) -> Result<
+            BuilderSeal<GraphSeal>,
+            StockError<S, H, P, ComposeError>,
+        >,
+        pedersen_blinder: &impl Fn(ContractId, AssignmentType) -> BlindingFactor,
+        seal_blinder: &impl Fn(ContractId, AssignmentType) -> u64,
+    ) -> Result<TransitionDichotomy, StockError<S, H, P, ComposeError>> {
+        // 1. Prepare the data
+        let (invoice, _) = invoices[0];
+        let iface = invoice.iface.as_ref().ok_or(ComposeError::NoIface)?;
+        let mut main_builder =
+            self.transition_builder(contract_id, iface.clone(), invoice.operation.clone())?;
+
+        // If there are inputs which are using different seal closing method from our
+        // wallet (and thus main state transition) we need to put them aside and
+        // allocate a different state transition spending them as a change.
+        let mut alt_builder =
+            self.transition_builder(contract_id, iface.clone(), invoice.operation.clone())?;
+        let mut alt_inputs = Vec::<XOutputSeal>::new();
+
+        let mut payments = Vec::with_capacity(invoices.len());
+        for (invoice, beneficiary_vout) in invoices {
+            let assignment_name = invoice
+                .assignment
+                .as_ref()
+                .or_else(|| main_builder.default_assignment().ok())
+                .ok_or(BuilderError::NoDefaultAssignment)?
+                .clone();
+            let assignment_id = main_builder
+                .assignments_type(&assignment_name)
+                .ok_or(BuilderError::InvalidStateField(assignment_name.clone()))?;
+
+            let layer1 = invoice.beneficiary.chain_network().layer1();
+            let beneficiary = match (invoice.beneficiary.into_inner(), beneficiary_vout) {
+                (Beneficiary::BlindedSeal(seal), None) => {
+                    BuilderSeal::Concealed(XChain::with(layer1, seal))
+                }
+                (Beneficiary::BlindedSeal(_), Some(_)) => {
+                    return Err(ComposeError::BeneficiaryVout.into());
+                }
+                (Beneficiary::WitnessVout(payload), Some(vout)) => {
+                    let blinding = seal_blinder(contract_id, assignment_id);
+                    let seal = GraphSeal::with_blinded_vout(payload.method, *vout, blinding);
+                    BuilderSeal::Revealed(XChain::with(layer1, seal))
+                }
+                (Beneficiary::WitnessVout(_), None) => {
+                    return Err(ComposeError::NoBeneficiaryOutput.into());
+                }
             };
+            payments.push((assignment_id, beneficiary, invoice.owned_state.clone()));
+        }
+        let invoiced = payments
+            .iter()
+            .map(|(assignment_id, _, _)| *assignment_id)
+            .collect::<BTreeSet<_>>();
+
+        // 2. Prepare transition
+        let mut main_inputs = Vec::<XOutputSeal>::new();
+        // Fungible state of the invoiced types spent by the main and the alternative
+        // transitions
+        let mut fungible_inputs = BTreeMap::<AssignmentType, (Amount, Amount)>::new();
+        // Non-fungible state of the invoiced types, flagged whether it is spent by the
+        // main transition
+        let mut nonfungible_inputs = BTreeMap::<AssignmentType, Vec<(PersistedState, bool)>>::new();
 
         for (output, list) in
             self.contract_assignments_for(contract_id, prev_outputs.iter().copied())?
         {
-            if output.method() == method {
+            let main = output.method() == method;
+            if main {
                 main_inputs.push(output)
             } else {
                 alt_inputs.push(output)
             };
             for (opout, mut state) in list {
-                if output.method() == method {
+                if main {
                     main_builder = main_builder.add_input(opout, state.clone())?;
                 } else {
                     alt_builder = alt_builder.add_input(opout, state.clone())?;
                 }
-                if opout.ty != assignment_id {
+                if !invoiced.contains(&opout.ty) {
                     let seal = output_for_assignment(contract_id, opout.ty)?;
-                    state.update_blinding(pedersen_blinder(contract_id, assignment_id));
-                    if output.method() == method {
+                    state.update_blinding(pedersen_blinder(contract_id, opout.ty));
+                    if main {
                         main_builder = main_builder.add_owned_state_raw(opout.ty, seal, state)?;
                     } else {
                         alt_builder = alt_builder.add_owned_state_raw(opout.ty, seal, state)?;
                     }
                 } else if let PersistedState::Amount(value, _, _) = state {
-                    sum_inputs += value;
-                    if output.method() != method {
-                        sum_alt += value;
-                    }
-                } else if let PersistedState::Data(value, _) = state {
-                    if lookup_state.as_ref() == Some(&value) && output.method() != method {
-                        data_main = false;
+                    let (sum_main, sum_alt) = fungible_inputs.entry(opout.ty).or_default();
+                    if main {
+                        *sum_main += value;
+                    } else {
+                        *sum_alt += value;
                     }
-                    data_inputs.push(value);
+                } else {
+                    nonfungible_inputs
+                        .entry(opout.ty)
+                        .or_default()
+                        .push((state, main));
                 }
             }
         }
-        // Add payments to beneficiary and change
-        match invoice.owned_state.clone() {
-            InvoiceState::Amount(amt) => {
-                // Pay beneficiary
-                if sum_inputs < amt {
-                    return Err(ComposeError::InsufficientState.into());
-                }
 
-                let sum_main = sum_inputs - sum_alt;
-                let (paid_main, paid_alt) =
-                    if sum_main < amt { (sum_main, amt - sum_main) } else { (amt, Amount::ZERO) };
-                let blinding_beneficiary = pedersen_blinder(contract_id, assignment_id);
-
-                if paid_main > Amount::ZERO {
-                    main_builder = main_builder.add_fungible_state_raw(
-                        assignment_id,
-                        beneficiary,
-                        paid_main,
-                        blinding_beneficiary,
-                    )?;
-                }
-                if paid_alt > Amount::ZERO {
-                    alt_builder = alt_builder.add_fungible_state_raw(
-                        assignment_id,
-                        beneficiary,
-                        paid_alt,
-                        blinding_beneficiary,
-                    )?;
-                }
+        // Add payments to beneficiaries
+        let mut fractions =
+            BTreeMap::<(AssignmentType, TokenIndex), (OwnedFraction, OwnedFraction)>::new();
+        for (assignment_id, beneficiary, owned_state) in payments {
+            let nonfungible = nonfungible_inputs.entry(assignment_id).or_default();
+            match owned_state {
+                InvoiceState::Amount(amt) => {
+                    let (sum_main, sum_alt) = fungible_inputs.entry(assignment_id).or_default();
+                    if sum_main.saturating_add(*sum_alt) < amt {
+                        return Err(ComposeError::InsufficientState.into());
+                    }
 
-                let blinding_change = pedersen_blinder(contract_id, assignment_id);
-                let change_seal = output_for_assignment(contract_id, assignment_id)?;
-
-                // Pay change
-                if sum_main > paid_main {
-                    main_builder = main_builder.add_fungible_state_raw(
-                        assignment_id,
-                        change_seal,
-                        sum_main - paid_main,
-                        blinding_change,
-                    )?;
+                    let paid_main = amt.min(*sum_main);
+                    let paid_alt = amt - paid_main;
+                    *sum_main -= paid_main;
+                    *sum_alt -= paid_alt;
+                    let blinding_beneficiary = pedersen_blinder(contract_id, assignment_id);
+
+                    if paid_main > Amount::ZERO {
+                        main_builder = main_builder.add_fungible_state_raw(
+                            assignment_id,
+                            beneficiary,
+                            paid_main,
+                            blinding_beneficiary,
+                        )?;
+                    }
+                    if paid_alt > Amount::ZERO {
+                        alt_builder = alt_builder.add_fungible_state_raw(
+                            assignment_id,
+                            beneficiary,
+                            paid_alt,
+                            blinding_beneficiary,
+                        )?;
+                    }
+                }
+                InvoiceState::Void => {
+                    let (_, main) = take_input(nonfungible, |state| *state == PersistedState::Void)
+                        .ok_or(ComposeError::InsufficientState)?;
+                    if main {
+                        main_builder = main_builder.add_owned_state_raw(
+                            assignment_id,
+                            beneficiary,
+                            PersistedState::Void,
+                        )?;
+                    } else {
+                        alt_builder = alt_builder.add_owned_state_raw(
+                            assignment_id,
+                            beneficiary,
+                            PersistedState::Void,
+                        )?;
+                    }
                 }
-                if sum_alt > paid_alt {
-                    alt_builder = alt_builder.add_fungible_state_raw(
-                        assignment_id,
-                        change_seal,
-                        sum_alt - paid_alt,
-                        blinding_change,
-                    )?;
+                InvoiceState::Attach(attach_id) => {
+                    let (state, main) = take_input(nonfungible, |state| {
+                        matches!(state, PersistedState::Attachment(attach, _) if attach.id == attach_id)
+                    })
+                    .ok_or(ComposeError::InsufficientState)?;
+                    let PersistedState::Attachment(attach, _) = state else {
+                        unreachable!("filtered by take_input")
+                    };
+                    let state = PersistedState::Attachment(
+                        attach,
+                        seal_blinder(contract_id, assignment_id),
+                    );
+                    if main {
+                        main_builder =
+                            main_builder.add_owned_state_raw(assignment_id, beneficiary, state)?;
+                    } else {
+                        alt_builder =
+                            alt_builder.add_owned_state_raw(assignment_id, beneficiary, state)?;
+                    }
                 }
-            }
-            InvoiceState::Data(data) => match data {
-                NonFungible::RGB21(allocation) => {
-                    let lookup_state = DataState::from(allocation);
-                    if !data_inputs.into_iter().any(|x| x == lookup_state) {
+                InvoiceState::Data(NonFungible::RGB21(allocation)) => {
+                    // Collect all fractions of the invoiced token
+                    let token_index = allocation.token_index();
+                    let (sum_main, sum_alt) = fractions
+                        .entry((assignment_id, token_index))
+                        .or_insert_with(|| take_fractions(nonfungible, token_index));
+
+                    let fraction = allocation.fraction();
+                    if sum_main.saturating_add(*sum_alt) < fraction {
                         return Err(ComposeError::InsufficientState.into());
                     }
+                    let paid_main = fraction.min(*sum_main);
+                    let paid_alt = fraction - paid_main;
+                    *sum_main -= paid_main;
+                    *sum_alt -= paid_alt;
 
-                    let seal = seal_blinder(contract_id, assignment_id);
-                    if data_main {
+                    if paid_main > OwnedFraction::ZERO {
                         main_builder = main_builder.add_data_raw(
                             assignment_id,
                             beneficiary,
-                            allocation,
-                            seal,
+                            Allocation::with(token_index, paid_main),
+                            seal_blinder(contract_id, assignment_id),
                         )?;
-                    } else {
+                    }
+                    if paid_alt > OwnedFraction::ZERO {
                         alt_builder = alt_builder.add_data_raw(
                             assignment_id,
                             beneficiary,
-                            allocation,
-                            seal,
+                            Allocation::with(token_index, paid_alt),
+                            seal_blinder(contract_id, assignment_id),
                         )?;
                     }
                 }
-            },
-            _ => {
-                todo!(
-                    "only PersistedState::Amount and PersistedState::Allocation are currently \
-                     supported"
-                )
             }
         }
 
-        // 3. Prepare other transitions
-        // Enumerate state
-        let mut spent_state =
-            HashMap::<ContractId, HashMap<XOutputSeal, HashMap<Opout, PersistedState>>>::new();
-        for id in self.contracts_assigning(prev_outputs.iter().copied())? {
-            // Skip current contract
-            if id == contract_id {
+        // Pay change
+        for (assignment_id, (sum_main, sum_alt)) in fungible_inputs {
+            if sum_main == Amount::ZERO && sum_alt == Amount::ZERO {
                 continue;
             }
-            let state = self.contract_assignments_for(id, prev_outputs.iter().copied())?;
-            let entry = spent_state.entry(id).or_default();
-            for (seal, assigns) in state {
-                entry.entry(seal).or_default().extend(assigns);
+            let blinding_change = pedersen_blinder(contract_id, assignment_id);
+            let change_seal = output_for_assignment(contract_id, assignment_id)?;
+            if sum_main > Amount::ZERO {
+                main_builder = main_builder.add_fungible_state_raw(
+                    assignment_id,
+                    change_seal,
+                    sum_main,
+                    blinding_change,
+                )?;
+            }
+            if sum_alt > Amount::ZERO {
+                alt_builder = alt_builder.add_fungible_state_raw(
+                    assignment_id,
+                    change_seal,
+                    sum_alt,
+                    blinding_change,
+                )?;
             }
         }
-
-        // Construct blank transitions
-        let mut blanks = Confined::<Vec<_>, 0, { U24 - 1 }>::with_capacity(spent_state.len());
-        for (id, list) in spent_state {
-            let mut blank_builder_tapret = self.blank_builder(id, iface.clone())?;
-            let mut blank_builder_opret = self.blank_builder(id, iface.clone())?;
-            let mut outputs_tapret = Vec::with_capacity(list.len());
-            let mut outputs_opret = Vec::with_capacity(list.len());
-            for (output, assigns) in list {
-                match output.method() {
-                    Method::TapretFirst => outputs_tapret.push(output),
-                    Method::OpretFirst => outputs_opret.push(output),
-                }
-                for (opout, state) in assigns {
-                    let seal = output_for_assignment(id, opout.ty)?;
-                    match output.method() {
-                        Method::TapretFirst => {
-                            blank_builder_tapret = blank_builder_tapret
-                                .add_input(opout, state.clone())?
-                                .add_owned_state_raw(opout.ty, seal, state)?
-                        }
-                        Method::OpretFirst => {
-                            blank_builder_opret = blank_builder_opret
-                                .add_input(opout, state.clone())?
-                                .add_owned_state_raw(opout.ty, seal, state)?
-                        }
-                    }
-                }
+        for ((assignment_id, token_index), (sum_main, sum_alt)) in fractions {
+            if sum_main == OwnedFraction::ZERO && sum_alt == OwnedFraction::ZERO {
+                continue;
             }
-
-            let mut dicho = vec![];
-            for (blank_builder, outputs) in
-                [(blank_builder_tapret, outputs_tapret), (blank_builder_opret, outputs_opret)]
-            {
-                if !blank_builder.has_inputs() {
-                    continue;
+            let change_seal = output_for_assignment(contract_id, assignment_id)?;
+            if sum_main > OwnedFraction::ZERO {
+                main_builder = main_builder.add_data_raw(
+                    assignment_id,
+                    change_seal,
+                    Allocation::with(token_index, sum_main),
+                    seal_blinder(contract_id, assignment_id),
+                )?;
+            }
+            if sum_alt > OwnedFraction::ZERO {
+                alt_builder = alt_builder.add_data_raw(
+                    assignment_id,
+                    change_seal,
+                    Allocation::with(token_index, sum_alt),
+                    seal_blinder(contract_id, assignment_id),
+                )?;
+            }
+        }
+        // Non-fungible state of the invoiced types not used for the payments goes to
+        // change
+        for (assignment_id, list) in nonfungible_inputs {
+            for (state, main) in list {
+                let seal = output_for_assignment(contract_id, assignment_id)?;
+                if main {
+                    main_builder = main_builder.add_owned_state_raw(assignment_id, seal, state)?;
+                } else {
+                    alt_builder = alt_builder.add_owned_state_raw(assignment_id, seal, state)?;
                 }
-                let transition = blank_builder.complete_transition()?;
-                let info = TransitionInfo::new(transition, outputs).map_err(|e| {
-                    debug_assert!(!matches!(e, TransitionInfoError::CloseMethodDivergence(_)));
-                    ComposeError::TooManyInputs
-                })?;
-                dicho.push(info);
             }
-            blanks
-                .push(TransitionDichotomy::from_iter(dicho))
-                .map_err(|_| ComposeError::TooManyBlanks)?;
         }
 
-        let (first_builder, second_builder) =
-            match (main_builder.has_inputs(), alt_builder.has_inputs()) {
-                (true, true) => (main_builder, Some(alt_builder)),
-                (true, false) => (main_builder, None),
-                (false, true) => (alt_builder, None),
-                (false, false) => return Err(ComposeError::InsufficientState.into()),
-            };
-        let first = TransitionInfo::new(first_builder.complete_transition()?, main_inputs)
-            .map_err(|e| {
+        let complete = |builder: TransitionBuilder, inputs: Vec<XOutputSeal>| {
+            TransitionInfo::new(builder.complete_transition()?, inputs).map_err(|e| {
                 debug_assert!(!matches!(e, TransitionInfoError::CloseMethodDivergence(_)));
                 ComposeError::TooManyInputs
-            })?;
-        let second = if let Some(second_builder) = second_builder {
-            Some(TransitionInfo::new(second_builder.complete_transition()?, alt_inputs).map_err(
-                |e| {
-                    debug_assert!(!matches!(e, TransitionInfoError::CloseMethodDivergence(_)));
-                    ComposeError::TooManyInputs
-                },
-            )?)
-        } else {
-            None
-        };
-        let mut batch = Batch {
-            main: TransitionDichotomy::with(first, second),
-            blanks,
+            })
         };
-        batch.set_priority(priority);
-        Ok(batch)
+        match (main_builder.has_inputs(), alt_builder.has_inputs()) {
+            (true, true) => Ok(TransitionDichotomy::with(
+                complete(main_builder, main_inputs)?,
+                Some(complete(alt_builder, alt_inputs)?),
+            )),
+            (true, false) => {
+                Ok(TransitionDichotomy::with(complete(main_builder, main_inputs)?, None))
+            }
+            (false, true) => {
+                Ok(TransitionDichotomy::with(complete(alt_builder, alt_inputs)?, None))
+            }
+            (false, false) => Err(ComposeError::InsufficientState.into()),
+        }
     }
 
     fn store_transaction<E: Error>(
@@ -1252,31 +1967,40 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             &mut Index<P>,
         ) -> Result<(), StockError<S, H, P, E>>,
     ) -> Result<(), StockError<S, H, P, E>> {
-        self.state.begin_transaction()?;
-        self.stash
+        // Transactions are opened, persisted and rolled back in the same order, with the stash
+        // being the last one, since providers may use the write of the stash as a commit point
+        // for the data of all three components
+        self.index.begin_transaction()?;
+        self.state
             .begin_transaction()
-            .inspect_err(|_| self.stash.rollback_transaction())?;
-        self.index.begin_transaction().inspect_err(|_| {
+            .inspect_err(|_| self.index.rollback_transaction())?;
+        self.stash.begin_transaction().inspect_err(|_| {
+            self.index.rollback_transaction();
             self.state.rollback_transaction();
-            self.stash.rollback_transaction();
         })?;
-        f(&mut self.stash, &mut self.state, &mut self.index)?;
-        self.index
-            .commit_transaction()
-            .map_err(StockError::from)
-            .and_then(|_| self.state.commit_transaction().map_err(StockError::from))
-            .and_then(|_| self.stash.commit_transaction().map_err(StockError::from))
+        // Transactions are closed only once all the data are persisted, such that a failure to
+        // persist any of them reverts all of them
+        f(&mut self.stash, &mut self.state, &mut self.index)
+            .and_then(|_| self.index.prepare_transaction().map_err(StockError::from))
+            .and_then(|_| self.state.prepare_transaction().map_err(StockError::from))
+            .and_then(|_| self.stash.prepare_transaction().map_err(StockError::from))
             .inspect_err(|_| {
+                self.index.rollback_transaction();
                 self.state.rollback_transaction();
                 self.stash.rollback_transaction();
-                self.index.rollback_transaction();
-            })
+            })?;
+        self.index.finish_transaction();
+        self.state.finish_transaction();
+        self.stash.finish_transaction();
+        Ok(())
     }
 
     pub fn import_kit(&mut self, kit: ValidKit) -> Result<validation::Status, StockError<S, H, P>> {
         let (kit, status) = kit.split();
         self.stash.begin_transaction()?;
-        self.stash.consume_kit(kit)?;
+        self.stash
+            .consume_kit(kit)
+            .inspect_err(|_| self.stash.rollback_transaction())?;
         self.stash.commit_transaction()?;
         Ok(status)
     }
@@ -1352,6 +2076,65 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         })
     }
 
+    /// Imports disclosure into the stash, state and index.
+    ///
+    /// Disclosure may only reveal concealed data for the bundles and state
+    /// extensions which are already known to the stock; it can't be used to
+    /// add new operations to the contract history.
+    ///
+    /// Disclosure signatures are validated with the provided `sig_validator`;
+    /// invalid signatures and signatures made by untrusted identities are not
+    /// imported. The returned status reports the removed invalid signatures.
+    pub fn consume_disclosure<R: ResolveWitness>(
+        &mut self,
+        mut disclosure: Disclosure,
+        resolver: R,
+        sig_validator: &impl SigValidator,
+    ) -> Result<validation::Status, StockError<S, H, P, DisclosureError>> {
+        let status = disclosure.validate_sigs(sig_validator);
+        self.store_transaction(move |stash, state, index| {
+            for (bundle_id, bundle) in disclosure.bundles {
+                if bundle.bundle_id() != bundle_id {
+                    return Err(
+                        DisclosureError::BundleIdMismatch(bundle_id, bundle.bundle_id()).into()
+                    );
+                }
+                let (witness_ids, contract_id) = index
+                    .bundle_info(bundle_id)
+                    .map(|(ids, contract_id)| (ids.collect::<Vec<_>>(), contract_id))
+                    .map_err(|_| DisclosureError::UnknownBundle(bundle_id))?;
+
+                stash.consume_bundle(bundle)?;
+                let bundle = stash.bundle(bundle_id)?;
+                for witness_id in &witness_ids {
+                    index.index_bundle(contract_id, bundle, *witness_id)?;
+                }
+                let witness_id = state.select_valid_witness(&witness_ids)?;
+                state.update_from_bundle(contract_id, bundle, witness_id, &resolver)?;
+            }
+
+            for extension in disclosure.extensions {
+                let opid = extension.id();
+                let contract_id = extension.contract_id;
+                if stash.extension(opid).is_err() {
+                    return Err(DisclosureError::UnknownExtension(opid).into());
+                }
+
+                stash.consume_extension(extension)?;
+                let extension = stash.extension(opid)?;
+                index.index_extension(contract_id, extension)?;
+                state.update_from_extension(contract_id, extension)?;
+            }
+
+            for (content_id, sigs) in disclosure.signatures {
+                stash.import_sigs(content_id, sigs)?;
+            }
+
+            Ok(())
+        })?;
+        Ok(status)
+    }
+
     fn transition(&self, opid: OpId) -> Result<&Transition, StockError<S, H, P, ConsignError>> {
         let bundle_id = self.index.bundle_id_for_op(opid)?;
         let bundle = self.stash.bundle(bundle_id)?;
@@ -1361,6 +2144,14 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
             .ok_or(ConsignError::Concealed(bundle_id, opid).into())
     }
 
+    fn known_extension(&self, opid: OpId) -> Result<Option<&Extension>, StashError<S>> {
+        match self.stash.extension(opid) {
+            Ok(extension) => Ok(Some(extension)),
+            Err(StashError::Inconsistency(StashInconsistency::OperationAbsent(_))) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
     fn client_bundle(&self, bundle_id: BundleId) -> Result<ClientBundle, StockError<S, H, P>> {
         let (witness_ids, contract_id) = self.index.bundle_info(bundle_id)?;
 
@@ -1409,28 +2200,829 @@ impl<S: StashProvider, H: StateProvider, P: IndexProvider> Stock<S, H, P> {
         resolver: impl ResolveWitness,
         after_height: u32,
     ) -> Result<UpdateRes, StockError<S, H, P>> {
-        Ok(self.state.update_witnesses(resolver, after_height)?)
+        self.state_transaction(|state| state.update_witnesses(resolver, after_height))
     }
-}
 
-#[derive(Clone, Eq, PartialEq, Debug)]
-pub struct UpdateRes {
-    pub succeeded: usize,
-    pub failed: HashMap<XWitnessId, String>,
-}
+    /// Removes spent assignments from the contract state.
+    ///
+    /// Assignments are restored back once their spending witnesses get
+    /// archived during [`Self::update_witnesses`].
+    pub fn prune_spent(&mut self) -> Result<usize, StockError<S, H, P>> {
+        self.state_transaction(State::prune_spent)
+    }
 
-#[cfg(test)]
-mod test {
-    use std::str::FromStr;
+    /// Updates the contract state, persisting the stash afterwards, since
+    /// providers may use the write of the stash as a commit point for the state.
+    fn state_transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut State<H>) -> Result<T, StateError<H>>,
+    ) -> Result<T, StockError<S, H, P>> {
+        self.stash.begin_transaction()?;
+        let res = f(&mut self.state).inspect_err(|_| self.stash.rollback_transaction())?;
+        // Providers sharing the commit point with the stash have to drop the state changes
+        // which were not persisted
+        self.stash
+            .commit_transaction()
+            .inspect_err(|_| self.state.rollback_transaction())?;
+        Ok(res)
+    }
 
-    use baid64::FromBaid64Str;
-    use commit_verify::{Conceal, DigestExt, Sha256};
-    use strict_encoding::TypeName;
+    /// Re-computes contract state from the data kept in the stash, putting it
+    /// into a new state provider.
+    ///
+    /// Replays all known geneses, state transition bundles and state
+    /// extensions, resolving the ordering of their witnesses with the
+    /// `resolver`. Used for recovering from a loss or corruption of the state
+    /// data, or for switching to a different state provider without
+    /// re-importing consignments.
+    pub fn rebuild_state<H2: StateProvider>(
+        self,
+        provider: H2,
+        resolver: impl ResolveWitness,
+    ) -> Result<Stock<S, H2, P>, StockError<S, H2, P>> {
+        let mut state = State::new(provider);
+
+        for genesis in self.stash.geneses()? {
+            let schema = &self.stash.schema(genesis.schema_id)?.schema;
+            state
+                .as_provider_mut()
+                .register_contract(schema, genesis)
+                .map_err(StateError::WriteProvider)?;
+        }
 
-    use super::*;
-    use crate::containers::ConsignmentExt;
+        let extension_ids = self
+            .stash
+            .as_provider()
+            .extension_ids()
+            .map_err(StashError::ReadProvider)?
+            .collect::<BTreeSet<_>>();
+        let mut ordered_extensions = BTreeMap::<OpId, (XWitnessId, WitnessOrd)>::new();
+        for (contract_id, bundle, witness_ids) in self.stash_bundles()? {
+            // Like on the consumption, the state is defined by the first valid witness; bundles
+            // with all their witnesses archived don't contribute to the state
+            let mut valid = None;
+            for witness_id in witness_ids {
+                let witness_ord = resolver
+                    .resolve_pub_witness_ord(witness_id)
+                    .map_err(|e| StateError::Resolver(witness_id, e))?;
+                if witness_ord.is_valid() {
+                    valid = Some((witness_id, witness_ord));
+                    break;
+                }
+            }
+            let Some((witness_id, witness_ord)) = valid else {
+                continue;
+            };
+            state.update_from_bundle(contract_id, bundle, witness_id, &resolver)?;
+            // State extensions are ordered by the latest witness spending them
+            for input in bundle.known_transitions.values().flat_map(|t| &t.inputs) {
+                let id = input.prev_out.op;
+                if !extension_ids.contains(&id) {
+                    continue;
+                }
+                match ordered_extensions.entry(id) {
+                    Entry::Vacant(entry) => {
+                        entry.insert((witness_id, witness_ord));
+                    }
+                    Entry::Occupied(mut entry) if entry.get().1 < witness_ord => {
+                        entry.insert((witness_id, witness_ord));
+                    }
+                    Entry::Occupied(_) => {}
+                }
+            }
+        }
 
-    #[test]
+        for (opid, (witness_id, witness_ord)) in ordered_extensions {
+            let extension = self.stash.extension(opid)?;
+            let contract_id = extension.contract_id;
+            state
+                .as_provider_mut()
+                .update_contract(contract_id)
+                .map_err(StateError::WriteProvider)?
+                .ok_or(StateError::from(StateInconsistency::UnknownContract(contract_id)))?
+                .add_extension(extension, witness_id, witness_ord)
+                .map_err(StateError::WriteProvider)?;
+        }
+
+        Ok(Stock {
+            stash: self.stash,
+            state,
+            index: self.index,
+        })
+    }
+
+    /// Re-computes the index from the data kept in the stash, putting it into a
+    /// new index provider.
+    ///
+    /// Used for recovering from a loss or corruption of the index data, or for
+    /// switching to a different index provider.
+    pub fn rebuild_index<P2: IndexProvider>(
+        self,
+        provider: P2,
+    ) -> Result<Stock<S, H, P2>, StockError<S, H, P2>> {
+        let mut index = Index::new(provider);
+
+        for genesis in self.stash.geneses()? {
+            index.index_contract(genesis)?;
+        }
+        for opid in self
+            .stash
+            .as_provider()
+            .extension_ids()
+            .map_err(StashError::ReadProvider)?
+        {
+            let extension = self.stash.extension(opid)?;
+            index.index_extension(extension.contract_id, extension)?;
+        }
+        for (contract_id, bundle, witness_ids) in self.stash_bundles()? {
+            for witness_id in witness_ids {
+                index.index_bundle(contract_id, bundle, witness_id)?;
+            }
+        }
+
+        Ok(Stock {
+            stash: self.stash,
+            state: self.state,
+            index,
+        })
+    }
+
+    /// Checks consistency of the stash, state and index data, returning all
+    /// detected issues.
+    ///
+    /// Unlike other methods, which fail on the first inconsistency they meet,
+    /// this walks through all schemata, contracts and bundles known to the
+    /// stash. Issues with the state and index may be repaired by the action
+    /// returned from [`StockIssue::repair_action`].
+    ///
+    /// Errors are returned only if the data can't be read from a provider.
+    pub fn check(&self) -> Result<Vec<StockIssue>, StockError<S, H, P>> {
+        let mut issues = vec![];
+
+        // Schemata must have all their types and libraries known
+        let types = self
+            .stash
+            .as_provider()
+            .type_system()
+            .map_err(StashError::ReadProvider)?;
+        for schema_ifaces in self.stash.schemata()? {
+            let schema = &schema_ifaces.schema;
+            if let Err(err) = types.extract(schema.types()) {
+                issues.push(err.into());
+            }
+            for id in schema.libs() {
+                match self.stash.as_provider().lib(id) {
+                    Ok(_) => {}
+                    Err(StashProviderError::Inconsistency(e)) => issues.push(e.into()),
+                    Err(err) => return Err(StashError::from(err).into()),
+                }
+            }
+        }
+
+        // Contract state must know ordering of all witnesses it references
+        for genesis in self.stash.geneses()? {
+            let contract_id = genesis.contract_id();
+            let Ok(contract) = self.state.contract_state(contract_id) else {
+                issues.push(StateInconsistency::UnknownContract(contract_id).into());
+                continue;
+            };
+            let witness_ids = contract
+                .rights_all()
+                .map(|a| a.witness)
+                .chain(contract.fungible_all().map(|a| a.witness))
+                .chain(contract.data_all().map(|a| a.witness))
+                .chain(contract.attach_all().map(|a| a.witness))
+                .flatten()
+                .collect::<BTreeSet<_>>();
+            for witness_id in witness_ids {
+                if contract.witness_ord(witness_id).is_none() {
+                    issues.push(StateInconsistency::AbsentWitness(witness_id).into());
+                }
+            }
+        }
+
+        // Bundles must be anchored and indexed
+        for (contract_id, bundle, witness_ids) in self.stash_bundles()? {
+            let bundle_id = bundle.bundle_id();
+            if witness_ids.is_empty() {
+                issues
+                    .push(StashInconsistency::BundleMissedInAnchors(bundle_id, contract_id).into());
+            }
+            for witness_id in &witness_ids {
+                let witness = self.stash.witness(*witness_id)?;
+                let merkle_block = match (bundle.close_method, &witness.anchors) {
+                    (
+                        CloseMethod::TapretFirst,
+                        AnchorSet::Tapret(tapret) | AnchorSet::Double { tapret, .. },
+                    ) => Some(&tapret.mpc_proof),
+                    (
+                        CloseMethod::OpretFirst,
+                        AnchorSet::Opret(opret) | AnchorSet::Double { opret, .. },
+                    ) => Some(&opret.mpc_proof),
+                    _ => None,
+                };
+                if !merkle_block
+                    .is_some_and(|block| block.to_merkle_proof(contract_id.into()).is_ok())
+                {
+                    issues.push(
+                        StashInconsistency::WitnessMissesContract(
+                            *witness_id,
+                            bundle_id,
+                            contract_id,
+                            bundle.close_method,
+                        )
+                        .into(),
+                    );
+                }
+            }
+
+            match self.index.bundle_info(bundle_id) {
+                Ok((_, present)) if present != contract_id => issues.push(
+                    IndexInconsistency::DistinctBundleContract {
+                        bundle_id,
+                        present,
+                        expected: contract_id,
+                    }
+                    .into(),
+                ),
+                Ok((indexed, _)) => {
+                    let indexed = indexed.collect::<BTreeSet<_>>();
+                    if !witness_ids.is_subset(&indexed) {
+                        issues.push(IndexInconsistency::BundleWitnessUnknown(bundle_id).into());
+                    }
+                }
+                Err(IndexError::Inconsistency(e)) => issues.push(e.into()),
+                Err(err) => return Err(err.into()),
+            }
+            for opid in bundle.known_transitions.keys().copied() {
+                match self.index.bundle_id_for_op(opid) {
+                    Ok(present) if present != bundle_id => issues.push(
+                        IndexInconsistency::DistinctBundleOp {
+                            opid,
+                            present,
+                            expected: bundle_id,
+                        }
+                        .into(),
+                    ),
+                    Ok(_) => {}
+                    Err(IndexError::Inconsistency(e)) => issues.push(e.into()),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Lists all bundles known to the stash together with the contract they
+    /// belong to and the witnesses anchoring them.
+    #[allow(clippy::type_complexity)]
+    fn stash_bundles(
+        &self,
+    ) -> Result<Vec<(ContractId, &TransitionBundle, BTreeSet<XWitnessId>)>, StashError<S>> {
+        let mut bundles = BTreeMap::new();
+        for bundle_id in self
+            .stash
+            .as_provider()
+            .bundle_ids()
+            .map_err(StashError::ReadProvider)?
+        {
+            let bundle = self.stash.bundle(bundle_id)?;
+            let Some(transition) = bundle.known_transitions.values().next() else {
+                continue;
+            };
+            bundles.insert(bundle_id, (transition.contract_id, bundle, BTreeSet::new()));
+        }
+        for witness_id in self
+            .stash
+            .as_provider()
+            .witness_ids()
+            .map_err(StashError::ReadProvider)?
+        {
+            let witness = self.stash.witness(witness_id)?;
+            for bundle_id in witness.anchors.known_bundle_ids() {
+                if let Some((_, _, witness_ids)) = bundles.get_mut(&bundle_id) {
+                    witness_ids.insert(witness_id);
+                }
+            }
+        }
+        Ok(bundles.into_values().collect())
+    }
+}
+
+/// Replaces seals of all assignments of the operation which are not listed in
+/// `revealed` with their concealed versions.
+fn conceal_seals<Seal: ExposedSeal>(
+    assignments: &mut Assignments<Seal>,
+    opid: OpId,
+    revealed: &BTreeSet<Opout>,
+) {
+    for (ty, assigns) in assignments.keyed_values_mut() {
+        let keep = |no: usize| revealed.contains(&Opout::new(opid, *ty, no as u16));
+        match assigns {
+            TypedAssigns::Declarative(vec) => conceal_assign_seals(vec, keep),
+            TypedAssigns::Fungible(vec) => conceal_assign_seals(vec, keep),
+            TypedAssigns::Structured(vec) => conceal_assign_seals(vec, keep),
+            TypedAssigns::Attachment(vec) => conceal_assign_seals(vec, keep),
+        }
+    }
+}
+
+/// Replaces state of all assignments of the operation with its concealed
+/// version. Seals of the assignments not listed in `revealed` are concealed as
+/// well.
+fn conceal_state<Seal: ExposedSeal>(
+    assignments: &mut Assignments<Seal>,
+    opid: OpId,
+    revealed: &BTreeSet<Opout>,
+) {
+    for (ty, assigns) in assignments.keyed_values_mut() {
+        let keep = |no: usize| revealed.contains(&Opout::new(opid, *ty, no as u16));
+        match assigns {
+            TypedAssigns::Declarative(vec) => conceal_assign_state(vec, keep),
+            TypedAssigns::Fungible(vec) => conceal_assign_state(vec, keep),
+            TypedAssigns::Structured(vec) => conceal_assign_state(vec, keep),
+            TypedAssigns::Attachment(vec) => conceal_assign_state(vec, keep),
+        }
+    }
+}
+
+fn conceal_assign_state<State: ExposedState, Seal: ExposedSeal>(
+    assigns: &mut SmallVec<Assign<State, Seal>>,
+    keep_seal: impl Fn(usize) -> bool,
+) {
+    for (no, assign) in assigns.iter_mut().enumerate() {
+        *assign = match assign {
+            Assign::Revealed { seal, state, lock } if keep_seal(no) => Assign::ConfidentialState {
+                seal: *seal,
+                state: state.conceal(),
+                lock: *lock,
+            },
+            Assign::Revealed { seal, state, lock } => Assign::Confidential {
+                seal: seal.conceal(),
+                state: state.conceal(),
+                lock: *lock,
+            },
+            Assign::ConfidentialSeal { seal, state, lock } => Assign::Confidential {
+                seal: *seal,
+                state: state.conceal(),
+                lock: *lock,
+            },
+            Assign::ConfidentialState { seal, state, lock } if !keep_seal(no) => {
+                Assign::Confidential {
+                    seal: seal.conceal(),
+                    state: *state,
+                    lock: *lock,
+                }
+            }
+            Assign::ConfidentialState { .. } | Assign::Confidential { .. } => continue,
+        };
+    }
+}
+
+fn conceal_assign_seals<State: ExposedState, Seal: ExposedSeal>(
+    assigns: &mut SmallVec<Assign<State, Seal>>,
+    keep: impl Fn(usize) -> bool,
+) {
+    for (no, assign) in assigns.iter_mut().enumerate() {
+        if keep(no) {
+            continue;
+        }
+        *assign = match assign {
+            Assign::Revealed { seal, state, lock } => Assign::ConfidentialSeal {
+                seal: seal.conceal(),
+                state: state.clone(),
+                lock: *lock,
+            },
+            Assign::ConfidentialState { seal, state, lock } => Assign::Confidential {
+                seal: seal.conceal(),
+                state: *state,
+                lock: *lock,
+            },
+            Assign::Confidential { .. } | Assign::ConfidentialSeal { .. } => continue,
+        };
+    }
+}
+
+/// Takes the first input matching `f`, preferring the ones spent by the main
+/// transition.
+fn take_input(
+    inputs: &mut Vec<(PersistedState, bool)>,
+    f: impl Fn(&PersistedState) -> bool,
+) -> Option<(PersistedState, bool)> {
+    let pos = inputs
+        .iter()
+        .position(|(state, main)| *main && f(state))
+        .or_else(|| inputs.iter().position(|(state, _)| f(state)))?;
+    Some(inputs.remove(pos))
+}
+
+/// Takes all fractions of the token with the given index, returning their sums
+/// spent by the main and the alternative transitions.
+fn take_fractions(
+    inputs: &mut Vec<(PersistedState, bool)>,
+    token_index: TokenIndex,
+) -> (OwnedFraction, OwnedFraction) {
+    let mut sum_main = OwnedFraction::ZERO;
+    let mut sum_alt = OwnedFraction::ZERO;
+    inputs.retain(|(state, main)| {
+        let PersistedState::Data(data, _) = state else {
+            return true;
+        };
+        match Allocation::from_strict_serialized(data.to_inner()) {
+            Ok(input) if input.token_index() == token_index => {
+                if *main {
+                    sum_main.saturating_add_assign(input.fraction());
+                } else {
+                    sum_alt.saturating_add_assign(input.fraction());
+                }
+                false
+            }
+            _ => true,
+        }
+    });
+    (sum_main, sum_alt)
+}
+
+fn collect_attach_ids(op: &impl Operation, ids: &mut BTreeSet<AttachId>) {
+    let assignments = op.assignments();
+    for ty in assignments.types() {
+        let Some(assigns) = assignments.get(ty) else {
+            continue;
+        };
+        ids.extend(
+            assigns
+                .as_attachment()
+                .iter()
+                .filter_map(|assign| assign.as_revealed_state())
+                .map(|state| state.file.id),
+        );
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct UpdateRes {
+    pub succeeded: usize,
+    pub failed: HashMap<XWitnessId, String>,
+}
+
+/// Issue in the stock data detected by [`Stock::check`].
+#[derive(Clone, PartialEq, Eq, Debug, Display, From)]
+#[display(inner)]
+pub enum StockIssue {
+    #[from]
+    Stash(StashInconsistency),
+
+    #[from]
+    #[from(UnknownType)]
+    StashData(StashDataError),
+
+    #[from]
+    Index(IndexInconsistency),
+
+    #[from]
+    State(StateInconsistency),
+}
+
+impl StockIssue {
+    /// Returns action repairing the issue, if the issue can be fixed by
+    /// re-computing the data from the stash.
+    ///
+    /// Issues with the stash itself can't be repaired, since stash is the
+    /// source of the data for all other stock components.
+    pub fn repair_action(&self) -> Option<RepairAction> {
+        match self {
+            StockIssue::Stash(_) | StockIssue::StashData(_) => None,
+            StockIssue::Index(_) => Some(RepairAction::RebuildIndex),
+            StockIssue::State(_) => Some(RepairAction::RebuildState),
+        }
+    }
+}
+
+/// Action repairing an issue detected by [`Stock::check`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum RepairAction {
+    /// re-compute contract state from the stash with `Stock::rebuild_state`.
+    RebuildState,
+
+    /// re-compute index from the stash with `Stock::rebuild_index`.
+    RebuildIndex,
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use baid64::FromBaid64Str;
+    use commit_verify::{Conceal, DigestExt, Sha256};
+    use strict_encoding::TypeName;
+
+    use super::*;
+    use crate::containers::ConsignmentExt;
+
+    mod fixture {
+        use std::num::NonZeroU32;
+
+        use bp::dbc::opret::{OpretFirst, OpretProof};
+        use bp::{
+            LockTime, Outpoint, ScriptPubkey, SeqNo, SigScript, Tx, TxIn, TxOut, TxVer, Txid,
+            Witness,
+        };
+        use commit_verify::mpc::{MerkleBlock, MerkleTree, Message, MultiSource};
+        use commit_verify::{CommitId, EmbedCommitVerify, TryCommitVerify};
+        use rgb::vm::{WitnessPos, XWitnessTx};
+        use rgb::{
+            AssetTag, ExtensionSchema, ExtensionType, FungibleType, Genesis, GenesisSchema,
+            GenesisSeal, Input, InputMap, Occurrences, OwnedStateSchema, RevealedValue, Schema,
+            TransitionSchema, TransitionType, ValencyType, VoidState,
+        };
+        use strict_encoding::StrictDumb;
+
+        use super::*;
+        use crate::containers::{Contract, PubWitness};
+
+        pub const AMOUNT: AssignmentType = AssignmentType::with(0);
+        pub const RIGHT: AssignmentType = AssignmentType::with(1);
+        pub const VALENCY: ValencyType = ValencyType::with(0);
+        pub const ISSUE: ExtensionType = ExtensionType::with(1);
+        pub const TRANSFER: TransitionType = TransitionType::with(1);
+
+        pub fn mined(height: u32) -> WitnessOrd {
+            let timestamp = 1_700_000_000 + height as i64;
+            let height = NonZeroU32::new(height).unwrap();
+            WitnessOrd::Mined(WitnessPos::bitcoin(height, timestamp).unwrap())
+        }
+
+        /// Resolver for the witness transactions constructed by the test.
+        #[derive(Default)]
+        pub struct TestResolver(pub BTreeMap<XWitnessId, (Tx, WitnessOrd)>);
+
+        impl TestResolver {
+            pub fn add(&mut self, tx: Tx, ord: WitnessOrd) -> XWitnessId {
+                let witness_id = XWitnessId::Bitcoin(tx.txid());
+                self.0.insert(witness_id, (tx, ord));
+                witness_id
+            }
+        }
+
+        impl ResolveWitness for TestResolver {
+            fn resolve_pub_witness(
+                &self,
+                witness_id: XWitnessId,
+            ) -> Result<XWitnessTx, WitnessResolverError> {
+                self.0
+                    .get(&witness_id)
+                    .map(|(tx, _)| XChain::Bitcoin(tx.clone()))
+                    .ok_or(WitnessResolverError::Unknown(witness_id))
+            }
+
+            fn resolve_pub_witness_ord(
+                &self,
+                witness_id: XWitnessId,
+            ) -> Result<WitnessOrd, WitnessResolverError> {
+                self.0
+                    .get(&witness_id)
+                    .map(|(_, ord)| *ord)
+                    .ok_or(WitnessResolverError::Unknown(witness_id))
+            }
+        }
+
+        /// Contract with a genesis, a state extension and a single bundle of
+        /// two state transitions:
+        /// - `t1` spends genesis amount #0 and the right issued by the extension, assigning 60 and
+        ///   the right to the witness output #1 and 40 to the output #2;
+        /// - `t2` spends genesis amount #1, assigning 25 to the output #2.
+        pub struct Fixture {
+            pub schema: Schema,
+            pub genesis: Genesis,
+            pub extension: Extension,
+            pub t1: Transition,
+            pub t2: Transition,
+        }
+
+        impl Fixture {
+            pub fn new() -> Self {
+                let prev_txid = Txid::from([0x01; 32]);
+                let genesis_seal =
+                    |vout: u32| XChain::Bitcoin(GenesisSeal::opret_first_rand(prev_txid, vout));
+                let graph_seal = |vout: u32| {
+                    XChain::Bitcoin(GraphSeal::new_random_vout(
+                        Method::OpretFirst,
+                        Vout::from_u32(vout),
+                    ))
+                };
+                let tag = AssetTag::strict_dumb();
+                let value = |value: u64| RevealedValue::new_random_blinding(value, tag);
+
+                let mut schema = Schema::strict_dumb();
+                schema.owned_types = tiny_bmap! {
+                    AMOUNT => OwnedStateSchema::Fungible(FungibleType::Unsigned64Bit),
+                    RIGHT => OwnedStateSchema::Declarative,
+                };
+                schema.valency_types = tiny_bset![VALENCY];
+                schema.genesis = GenesisSchema {
+                    assignments: tiny_bmap! { AMOUNT => Occurrences::OnceOrMore },
+                    valencies: tiny_bset![VALENCY],
+                    ..default!()
+                };
+                schema.extensions = tiny_bmap! {
+                    ISSUE => ExtensionSchema {
+                        redeems: tiny_bset![VALENCY],
+                        assignments: tiny_bmap! { RIGHT => Occurrences::Once },
+                        ..default!()
+                    },
+                };
+                schema.transitions = tiny_bmap! {
+                    TRANSFER => TransitionSchema {
+                        inputs: tiny_bmap! {
+                            AMOUNT => Occurrences::OnceOrMore,
+                            RIGHT => Occurrences::NoneOrOnce,
+                        },
+                        assignments: tiny_bmap! {
+                            AMOUNT => Occurrences::OnceOrMore,
+                            RIGHT => Occurrences::NoneOrOnce,
+                        },
+                        ..default!()
+                    },
+                };
+
+                let mut genesis = Genesis::strict_dumb();
+                genesis.schema_id = schema.schema_id();
+                genesis.asset_tags = tiny_bmap! { AMOUNT => tag }.into();
+                genesis.assignments = Assignments::from_inner(tiny_bmap! {
+                    AMOUNT => TypedAssigns::Fungible(small_vec![
+                        Assign::revealed(genesis_seal(0), value(100)),
+                        Assign::revealed(genesis_seal(1), value(25)),
+                    ]),
+                });
+                genesis.valencies = tiny_bset![VALENCY].into();
+                let contract_id = genesis.contract_id();
+
+                let mut extension = Extension::strict_dumb();
+                extension.contract_id = contract_id;
+                extension.extension_type = ISSUE;
+                extension.redeemed = tiny_bmap! { VALENCY => genesis.id() }.into();
+                extension.assignments = Assignments::from_inner(tiny_bmap! {
+                    RIGHT => TypedAssigns::Declarative(small_vec![Assign::revealed(
+                        genesis_seal(2),
+                        VoidState::default(),
+                    )]),
+                });
+
+                let input =
+                    |op: OpId, ty: AssignmentType, no: u16| Input::with(Opout::new(op, ty, no));
+                let mut t1 = Transition::strict_dumb();
+                t1.contract_id = contract_id;
+                t1.transition_type = TRANSFER;
+                t1.inputs =
+                    small_bset![input(genesis.id(), AMOUNT, 0), input(extension.id(), RIGHT, 0)]
+                        .into();
+                t1.assignments = Assignments::from_inner(tiny_bmap! {
+                    AMOUNT => TypedAssigns::Fungible(small_vec![
+                        Assign::revealed(graph_seal(1), value(60)),
+                        Assign::revealed(graph_seal(2), value(40)),
+                    ]),
+                    RIGHT => TypedAssigns::Declarative(small_vec![Assign::revealed(
+                        graph_seal(1),
+                        VoidState::default(),
+                    )]),
+                });
+                let mut t2 = Transition::strict_dumb();
+                t2.contract_id = contract_id;
+                t2.transition_type = TRANSFER;
+                t2.inputs = small_bset![input(genesis.id(), AMOUNT, 1)].into();
+                t2.assignments = Assignments::from_inner(tiny_bmap! {
+                    AMOUNT => TypedAssigns::Fungible(small_vec![
+                        Assign::revealed(graph_seal(2), value(25)),
+                    ]),
+                });
+
+                Fixture {
+                    schema,
+                    genesis,
+                    extension,
+                    t1,
+                    t2,
+                }
+            }
+
+            pub fn contract_id(&self) -> ContractId { self.genesis.contract_id() }
+
+            pub fn bundle(&self) -> TransitionBundle {
+                self.bundle_with(self.t1.clone(), self.t2.clone())
+            }
+
+            pub fn bundle_with(&self, t1: Transition, t2: Transition) -> TransitionBundle {
+                let mut input_map = InputMap::with(Vout::from_u32(0), t1.id());
+                input_map.insert(Vout::from_u32(1), t1.id()).unwrap();
+                input_map.insert(Vout::from_u32(2), t2.id()).unwrap();
+                TransitionBundle {
+                    close_method: CloseMethod::OpretFirst,
+                    input_map,
+                    known_transitions: Confined::from_iter_checked([(t1.id(), t1), (t2.id(), t2)]),
+                }
+            }
+
+            /// Constructs witness transaction closing the seals spent by the
+            /// bundle and committing to the bundle. Different `lock_time`
+            /// values produce different witnesses for the same bundle.
+            pub fn witness(&self, bundle_id: BundleId, lock_time: u32) -> (Tx, MerkleBlock) {
+                let prev_txid = Txid::from([0x01; 32]);
+                let input = |vout: u32| TxIn {
+                    prev_output: Outpoint::new(prev_txid, vout),
+                    sig_script: SigScript::new(),
+                    sequence: SeqNo::from_consensus_u32(0),
+                    witness: Witness::new(),
+                };
+                let output = |script_pubkey: ScriptPubkey| TxOut::new(script_pubkey, 1000u64);
+                let mut tx = Tx {
+                    version: TxVer::V2,
+                    inputs: Confined::from_iter_checked([input(0), input(2), input(1)]),
+                    outputs: Confined::from_iter_checked([
+                        output(ScriptPubkey::from_unsafe(vec![bp::opcodes::OP_RETURN])),
+                        output(ScriptPubkey::new()),
+                        output(ScriptPubkey::new()),
+                    ]),
+                    lock_time: LockTime::from_consensus_u32(lock_time),
+                };
+
+                let mut source = MultiSource::with_static_entropy(0);
+                source
+                    .messages
+                    .insert(self.contract_id().into(), Message::from(bundle_id))
+                    .unwrap();
+                let tree = MerkleTree::try_commit(&source).unwrap();
+                EmbedCommitVerify::<_, OpretFirst>::embed_commit(&mut tx, &tree.commit_id())
+                    .unwrap();
+                (tx, MerkleBlock::from(&tree))
+            }
+
+            pub fn client_bundle(
+                &self,
+                bundle: TransitionBundle,
+                block: &MerkleBlock,
+            ) -> ClientBundle {
+                let mpc_proof = block.to_merkle_proof(self.contract_id().into()).unwrap();
+                ClientBundle::new(mpc_proof, DbcProof::Opret(OpretProof::default()), bundle)
+            }
+
+            /// Constructs contract consignment with the provided bundle, which
+            /// is anchored to a new witness registered with the resolver.
+            pub fn consignment(
+                &self,
+                bundle: TransitionBundle,
+                resolver: &mut TestResolver,
+            ) -> Contract {
+                let (tx, block) = self.witness(bundle.bundle_id(), 0);
+                let client_bundle = self.client_bundle(bundle, &block);
+                resolver.add(tx.clone(), mined(100));
+                Consignment {
+                    version: ContainerVer::V2,
+                    transfer: false,
+                    terminals: none!(),
+                    genesis: self.genesis.clone(),
+                    extensions: Confined::from_iter_checked([self.extension.clone()]),
+                    bundles: Confined::from_iter_checked([WitnessBundle::with(
+                        XChain::Bitcoin(PubWitness::with(tx)),
+                        client_bundle,
+                    )]),
+                    schema: self.schema.clone(),
+                    ifaces: none!(),
+                    supplements: none!(),
+                    types: none!(),
+                    scripts: none!(),
+                    attachments: none!(),
+                    signatures: none!(),
+                }
+            }
+
+            /// Creates stock with the contract imported from the consignment
+            /// containing the provided bundle.
+            pub fn stock(&self, bundle: TransitionBundle, resolver: &mut TestResolver) -> Stock {
+                let mut stock = Stock::in_memory();
+                self.import(&mut stock, bundle, resolver);
+                stock
+            }
+
+            /// Imports the contract from the consignment containing the
+            /// provided bundle into a stock using arbitrary providers.
+            pub fn import<S: StashProvider, H: StateProvider, P: IndexProvider>(
+                &self,
+                stock: &mut Stock<S, H, P>,
+                bundle: TransitionBundle,
+                resolver: &mut TestResolver,
+            ) {
+                let contract = self
+                    .consignment(bundle, resolver)
+                    .validate(resolver, &DumbValidator, false)
+                    .unwrap();
+                stock.import_contract(contract, &*resolver).unwrap();
+            }
+        }
+    }
+
+    #[test]
     fn test_consign() {
         let mut stock = Stock::in_memory();
         let seal = XChain::with(
@@ -1443,7 +3035,7 @@ mod test {
         let contract_id =
             ContractId::from_baid64_str("rgb:qFuT6DN8-9AuO95M-7R8R8Mc-AZvs7zG-obum1Va-BRnweKk")
                 .unwrap();
-        if let Ok(transfer) = stock.consign::<true>(contract_id, [], Some(secret_seal)) {
+        if let Ok(transfer) = stock.consign::<true>(contract_id, [], Some(secret_seal), false) {
             println!("{:?}", transfer.supplements)
         }
     }
@@ -1459,6 +3051,86 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_export_contract_history() {
+        use amplify::confinement::{MediumBlob, U32};
+        use fixture::*;
+        use rgb::{AttachId, MediaType, RevealedAttach};
+        use strict_encoding::StrictSerialize;
+
+        // Imports consignment bypassing validation, which in the current
+        // consensus version rejects any revealed attachment state
+        fn import(stock: &mut Stock, contract: Contract, resolver: &TestResolver) {
+            stock
+                .store_transaction(|stash, state, index| {
+                    state.update_from_consignment(&contract, resolver)?;
+                    index.index_consignment(&contract)?;
+                    stash.consume_consignment(contract)?;
+                    Ok::<_, StockError<MemStash, MemState, MemIndex>>(())
+                })
+                .unwrap();
+        }
+        fn state(stock: &Stock) -> Vec<u8> {
+            stock
+                .state
+                .as_provider()
+                .to_strict_serialized::<U32>()
+                .unwrap()
+                .release()
+        }
+
+        let fixture = Fixture::new();
+        let mut resolver = TestResolver::default();
+        let stock = fixture.stock(fixture.bundle(), &mut resolver);
+
+        let export = stock.export_contract(fixture.contract_id()).unwrap();
+        assert_eq!(export.extensions.len(), 1);
+        assert!(export.extensions.contains(&fixture.extension));
+        let transitions = export
+            .bundles
+            .iter()
+            .flat_map(|wb| wb.anchored_bundles.bundles())
+            .flat_map(|bundle| bundle.known_transitions.keys());
+        assert_eq!(transitions.copied().collect::<BTreeSet<_>>(), bset![
+            fixture.t1.id(),
+            fixture.t2.id()
+        ]);
+
+        let export = export.validate(&resolver, &DumbValidator, false).unwrap();
+        let mut imported = Stock::in_memory();
+        imported.import_contract(export, &resolver).unwrap();
+        assert_eq!(state(&imported), state(&stock));
+
+        // Attachments referenced by the contract state are exported alongside
+        let attach_id = AttachId::from([0xAA; 32]);
+        let blob = MediumBlob::from_checked(b"attachment".to_vec());
+        let mut t2 = fixture.t2.clone();
+        t2.assignments
+            .insert(
+                AssignmentType::with(2),
+                TypedAssigns::Attachment(small_vec![Assign::revealed(
+                    XChain::Bitcoin(GraphSeal::new_random_vout(
+                        Method::OpretFirst,
+                        Vout::from_u32(1)
+                    )),
+                    RevealedAttach::new_random_salt(attach_id, MediaType::Any),
+                )]),
+            )
+            .unwrap();
+        let mut contract =
+            fixture.consignment(fixture.bundle_with(fixture.t1.clone(), t2), &mut resolver);
+        contract.attachments = Confined::from_iter_checked([(attach_id, blob.clone())]);
+        let mut stock = Stock::in_memory();
+        import(&mut stock, contract, &resolver);
+
+        let export = stock.export_contract(fixture.contract_id()).unwrap();
+        assert_eq!(export.attachments.get(&attach_id), Some(&blob));
+        let mut imported = Stock::in_memory();
+        import(&mut imported, export, &resolver);
+        assert_eq!(state(&imported), state(&stock));
+        assert_eq!(imported.stash.attachment(attach_id).unwrap(), Some(&blob));
+    }
+
     #[test]
     fn test_export_schema() {
         let stock = Stock::in_memory();
@@ -1511,4 +3183,1162 @@ mod test {
             println!("{:?}", builder.transition_type())
         }
     }
+
+    #[test]
+    fn test_kit_trust() {
+        use crate::containers::Supplement;
+
+        let mut stock = Stock::in_memory();
+        let malicious = Identity::from_str("ssi:malicious").unwrap();
+        let unknown = Identity::from_str("ssi:unknown").unwrap();
+        let trusted = Identity::from_str("ssi:trusted").unwrap();
+        stock
+            .set_trust(malicious.clone(), TrustLevel::Malicious)
+            .unwrap();
+        stock
+            .set_trust(trusted.clone(), TrustLevel::Trusted)
+            .unwrap();
+        assert_eq!(stock.trust(&malicious).unwrap(), TrustLevel::Malicious);
+        assert_eq!(stock.trust(&unknown).unwrap(), TrustLevel::Unknown);
+        assert_eq!(stock.trust(&trusted).unwrap(), TrustLevel::Trusted);
+
+        let content_ref = ContentRef::Genesis(ContractId::from([0xAA; 32]));
+        let suppl = |creator: &Identity, timestamp: i64| {
+            let mut suppl = Supplement::new(content_ref, creator.clone());
+            suppl.timestamp = timestamp;
+            suppl
+        };
+
+        let mut kit = Kit::default();
+        kit.supplements.push(suppl(&malicious, 3)).unwrap();
+        kit.supplements.push(suppl(&unknown, 2)).unwrap();
+        kit.supplements.push(suppl(&trusted, 1)).unwrap();
+        stock
+            .import_kit(kit.validate(&DumbValidator).unwrap())
+            .unwrap();
+
+        let creators = stock
+            .stash
+            .supplements(content_ref)
+            .unwrap()
+            .map(|suppl| suppl.creator)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(creators, bset![unknown.clone(), trusted.clone()]);
+        let used = stock
+            .stash
+            .trusted_supplement(content_ref)
+            .unwrap()
+            .unwrap();
+        assert_eq!(used.creator, trusted);
+
+        stock.set_trust(trusted, TrustLevel::Untrusted).unwrap();
+        assert!(stock
+            .stash
+            .trusted_supplement(content_ref)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_consign_trusted_supplements() {
+        use fixture::*;
+
+        use crate::containers::Supplement;
+
+        let fixture = Fixture::new();
+        let contract_id = fixture.contract_id();
+        let mut resolver = TestResolver::default();
+        let mut stock = fixture.stock(fixture.bundle(), &mut resolver);
+
+        let unknown = Identity::from_str("ssi:unknown").unwrap();
+        let trusted = Identity::from_str("ssi:trusted").unwrap();
+        let content_ref = ContentRef::Genesis(contract_id);
+        let mut kit = Kit::default();
+        kit.supplements
+            .push(Supplement::new(content_ref, unknown.clone()))
+            .unwrap();
+        stock
+            .import_kit(kit.clone().validate(&DumbValidator).unwrap())
+            .unwrap();
+        let contract = stock.export_contract(contract_id).unwrap();
+        assert!(contract.supplements.is_empty());
+
+        stock
+            .set_trust(trusted.clone(), TrustLevel::Trusted)
+            .unwrap();
+        kit.supplements
+            .push(Supplement::new(content_ref, trusted.clone()))
+            .unwrap();
+        stock
+            .import_kit(kit.validate(&DumbValidator).unwrap())
+            .unwrap();
+        let contract = stock.export_contract(contract_id).unwrap();
+        let creators = contract
+            .supplements
+            .iter()
+            .map(|suppl| suppl.creator.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(creators, vec![trusted]);
+    }
+
+    #[test]
+    fn test_conceal_seals() {
+        use rgb::VoidState;
+
+        let seal = || {
+            XChain::with(
+                rgbcore::Layer1::Bitcoin,
+                GraphSeal::new_random_vout(bp::dbc::Method::OpretFirst, Vout::from_u32(0)),
+            )
+        };
+        let ty = AssignmentType::with(1);
+        let opid = OpId::from([0x01; 32]);
+        let assigns = small_vec![
+            Assign::revealed(seal(), VoidState::default()),
+            Assign::revealed(seal(), VoidState::default()),
+            Assign::revealed(seal(), VoidState::default())
+        ];
+        let mut assignments = Assignments::<GraphSeal>::from_inner(tiny_bmap! {
+            ty => TypedAssigns::Declarative(assigns)
+        });
+        let original = assignments.clone();
+
+        conceal_seals(&mut assignments, opid, &bset![Opout::new(opid, ty, 1)]);
+
+        let TypedAssigns::Declarative(assigns) = &assignments[&ty] else {
+            unreachable!()
+        };
+        assert!(matches!(assigns[0], Assign::ConfidentialSeal { .. }));
+        assert!(matches!(assigns[1], Assign::Revealed { .. }));
+        assert!(matches!(assigns[2], Assign::ConfidentialSeal { .. }));
+        // Concealing seals must not change the operation commitment
+        assert_eq!(assignments[&ty].conceal(), original[&ty].conceal());
+    }
+
+    #[test]
+    fn test_contract_assignments_for() {
+        use bp::{Outpoint, Txid};
+        use fixture::*;
+
+        let fixture = Fixture::new();
+        let bundle = fixture.bundle();
+        let mut resolver = TestResolver::default();
+        let stock = fixture.stock(bundle.clone(), &mut resolver);
+        let (tx, _) = fixture.witness(bundle.bundle_id(), 0);
+        let outpoint = |txid: Txid, vout: u32| XChain::Bitcoin(Outpoint::new(txid, vout));
+
+        // Genesis outputs are spent by the bundle
+        let spent = stock
+            .contract_assignments_for(fixture.contract_id(), [
+                outpoint(Txid::from([0x01; 32]), 0),
+                outpoint(Txid::from([0x01; 32]), 1),
+            ])
+            .unwrap();
+        assert!(spent.is_empty());
+
+        let assignments = stock
+            .contract_assignments_for(fixture.contract_id(), [outpoint(tx.txid(), 1)])
+            .unwrap();
+        assert_eq!(assignments.len(), 1);
+        let state = assignments.values().next().unwrap();
+        assert_eq!(state.keys().copied().collect::<BTreeSet<_>>(), bset![
+            Opout::new(fixture.t1.id(), AMOUNT, 0),
+            Opout::new(fixture.t1.id(), RIGHT, 0)
+        ]);
+    }
+
+    #[test]
+    fn test_transfer_concealed() {
+        use bp::seals::txout::ExplicitSeal;
+        use fixture::*;
+
+        use crate::containers::AnchoredBundles;
+
+        let fixture = Fixture::new();
+        let contract_id = fixture.contract_id();
+        let bundle = fixture.bundle();
+        let mut resolver = TestResolver::default();
+        let stock = fixture.stock(bundle.clone(), &mut resolver);
+        let (tx, _) = fixture.witness(bundle.bundle_id(), 0);
+        let output = XChain::Bitcoin(ExplicitSeal::with(Method::OpretFirst, tx.txid(), 1u32));
+
+        let transfer = stock
+            .transfer_concealed(contract_id, [output], None)
+            .unwrap();
+
+        // Amounts from the history of the transferred state can't be concealed
+        let mut forged = transfer.clone();
+        let mut bundles = forged.bundles.release();
+        let mut witness_bundle = bundles.pop_first().unwrap();
+        let AnchoredBundles::Opret(bundle) = &mut witness_bundle.anchored_bundles else {
+            unreachable!()
+        };
+        bundle.update_transitions(|opid, transition| {
+            if opid != fixture.t1.id() {
+                return;
+            }
+            let assigns = transition
+                .assignments
+                .get_mut(&AMOUNT)
+                .and_then(TypedAssigns::as_fungible_mut)
+                .unwrap();
+            let Assign::ConfidentialSeal { seal, state, lock } = assigns[1].clone() else {
+                unreachable!()
+            };
+            assigns[1] = Assign::Confidential {
+                seal,
+                state: state.conceal(),
+                lock,
+            };
+        });
+        bundles.insert(witness_bundle);
+        forged.bundles = Confined::from_checked(bundles);
+        let (status, _) = forged
+            .validate(&resolver, &DumbValidator, false)
+            .unwrap_err();
+        assert!(matches!(
+            status.failures[..],
+            [validation::Failure::BulletproofsInvalid(opid, AMOUNT, _)] if opid == fixture.t1.id()
+        ));
+
+        let transfer = transfer.validate(&resolver, &DumbValidator, false).unwrap();
+        assert!(transfer.validation_status().failures.is_empty());
+        // Bundles are never partially revealed
+        let witness_bundle = transfer.bundled_witnesses().next().unwrap();
+        assert_eq!(witness_bundle.known_transitions().count(), 2);
+
+        // Only the transferred output and the outputs spent by the transfer
+        // history keep their seals revealed
+        fn revealed(op: &impl Operation, opouts: &mut BTreeSet<Opout>) {
+            let assignments = op.assignments();
+            for ty in assignments.types() {
+                let assigns = assignments.get(ty).unwrap();
+                opouts.extend(
+                    (0..assigns.len_u16())
+                        .filter(|no| assigns.revealed_seal_at(*no).unwrap().is_some())
+                        .map(|no| Opout::new(op.id(), ty, no)),
+                );
+            }
+        }
+        let mut opouts = bset![];
+        revealed(&transfer.genesis, &mut opouts);
+        for extension in &transfer.extensions {
+            revealed(extension, &mut opouts);
+        }
+        for transition in witness_bundle.known_transitions() {
+            revealed(transition, &mut opouts);
+        }
+        let genesis_id = fixture.genesis.id();
+        assert_eq!(opouts, bset![
+            Opout::new(genesis_id, AMOUNT, 0),
+            Opout::new(genesis_id, AMOUNT, 1),
+            Opout::new(fixture.extension.id(), RIGHT, 0),
+            Opout::new(fixture.t1.id(), AMOUNT, 0),
+            Opout::new(fixture.t1.id(), RIGHT, 0),
+        ]);
+
+        // Amounts of the transferred state history remain revealed, while the
+        // amount assigned by the unrelated transition is concealed
+        let amounts = witness_bundle
+            .known_transitions()
+            .flat_map(|transition| {
+                transition
+                    .assignments
+                    .values()
+                    .flat_map(|assigns| assigns.as_fungible())
+                    .filter_map(|assign| assign.as_revealed_state())
+                    .map(|state| (transition.id(), state.value.as_u64()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<BTreeSet<_>>();
+        assert_eq!(amounts, bset![(fixture.t1.id(), 60), (fixture.t1.id(), 40)]);
+        assert!(transfer
+            .validation_status()
+            .warnings
+            .contains(&validation::Warning::UncheckableConfidentialState(fixture.t2.id(), AMOUNT)));
+    }
+
+    #[test]
+    fn test_consume_disclosure() {
+        use fixture::*;
+
+        use crate::containers::{ContentSigs, SigBlob};
+
+        struct TestValidator(Identity);
+        impl SigValidator for TestValidator {
+            fn validate_sig(&self, identity: &Identity, _: ContentId, _: &SigBlob) -> bool {
+                *identity != self.0
+            }
+        }
+
+        let fixture = Fixture::new();
+        let contract_id = fixture.contract_id();
+        let mut t1 = fixture.t1.clone();
+        let opid = t1.id();
+        let change = Opout::new(opid, AMOUNT, 1);
+        conceal_seals(&mut t1.assignments, opid, &bset![
+            Opout::new(opid, AMOUNT, 0),
+            Opout::new(opid, RIGHT, 0)
+        ]);
+        let mut resolver = TestResolver::default();
+        let mut stock = fixture.stock(fixture.bundle_with(t1, fixture.t2.clone()), &mut resolver);
+        let has_change = |stock: &Stock| {
+            stock
+                .contract_state(contract_id)
+                .unwrap()
+                .fungible_all()
+                .any(|item| item.opout == change && item.state.value.as_u64() == 40)
+        };
+        assert!(!has_change(&stock));
+
+        let forged = Identity::from_str("ssi:forged").unwrap();
+        let malicious = Identity::from_str("ssi:malicious").unwrap();
+        let unknown = Identity::from_str("ssi:unknown").unwrap();
+        stock
+            .set_trust(malicious.clone(), TrustLevel::Malicious)
+            .unwrap();
+        let sig = SigBlob::from(Confined::try_from(vec![0x01]).unwrap());
+        let content_id = ContentId::Schema(fixture.schema.schema_id());
+        let sigs = ContentSigs::from(
+            Confined::try_from(bmap! {
+                forged.clone() => sig.clone(),
+                malicious => sig.clone(),
+                unknown.clone() => sig,
+            })
+            .unwrap(),
+        );
+
+        let disclosure =
+            |bundle: Option<TransitionBundle>, extension: Option<Extension>| Disclosure {
+                version: ContainerVer::V2,
+                bundles: Confined::from_iter_checked(bundle.map(|b| (b.bundle_id(), b))),
+                extensions: Confined::from_iter_checked(extension),
+                signatures: tiny_bmap! { content_id => sigs.clone() },
+            };
+        let validator = TestValidator(forged);
+
+        let mut other = fixture.t2.clone();
+        other.nonce += 1;
+        let unknown_bundle = fixture.bundle_with(fixture.t1.clone(), other);
+        let unknown_bundle_id = unknown_bundle.bundle_id();
+        assert!(matches!(
+            stock.consume_disclosure(disclosure(Some(unknown_bundle), None), &resolver, &validator),
+            Err(StockError::InvalidInput(DisclosureError::UnknownBundle(id))) if id == unknown_bundle_id
+        ));
+        let mut unknown_extension = fixture.extension.clone();
+        unknown_extension.nonce += 1;
+        let unknown_extension_id = unknown_extension.id();
+        assert!(matches!(
+            stock.consume_disclosure(disclosure(None, Some(unknown_extension)), &resolver, &validator),
+            Err(StockError::InvalidInput(DisclosureError::UnknownExtension(id))) if id == unknown_extension_id
+        ));
+        assert!(!has_change(&stock));
+        assert!(stock.stash.sigs_for(&content_id).unwrap().is_none());
+
+        let status = stock
+            .consume_disclosure(
+                disclosure(Some(fixture.bundle()), Some(fixture.extension.clone())),
+                &resolver,
+                &validator,
+            )
+            .unwrap();
+        assert_eq!(status.warnings.len(), 1);
+        assert!(has_change(&stock));
+        let signers = stock
+            .stash
+            .sigs_for(&content_id)
+            .unwrap()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(signers, bset![unknown]);
+    }
+
+    #[test]
+    fn test_take_input() {
+        use rgb::{AttachState, MediaType};
+
+        let attach = |main: bool| {
+            let state = AttachState {
+                id: AttachId::from([main as u8; 32]),
+                media_type: MediaType::Any,
+            };
+            (PersistedState::Attachment(state, 0), main)
+        };
+        let mut inputs = vec![(PersistedState::Void, false), attach(false), attach(true)];
+
+        let is_attach = |state: &PersistedState| matches!(state, PersistedState::Attachment(..));
+        assert_eq!(take_input(&mut inputs, is_attach), Some(attach(true)));
+        assert_eq!(take_input(&mut inputs, is_attach), Some(attach(false)));
+        assert_eq!(take_input(&mut inputs, is_attach), None);
+        assert_eq!(inputs, vec![(PersistedState::Void, false)]);
+    }
+
+    #[test]
+    fn test_compose_many_invoices() {
+        let stock = Stock::in_memory();
+        let compose = |invoices: &[&RgbInvoice]| {
+            stock.compose_many(
+                invoices.iter().map(|invoice| (*invoice, None)),
+                [] as [XOutputSeal; 0],
+                CloseMethod::OpretFirst,
+                |_, _, _| None,
+            )
+        };
+
+        assert!(matches!(compose(&[]), Err(StockError::InvalidInput(ComposeError::NoInvoices))));
+
+        let rgb20 = RgbInvoice::from_str(
+            "rgb:11Fa!$Dk-rUWXhy8-7H35qXm-pLGGLOo-txBWUgj-tbOaSbI/RGB20/BF+bc:utxob:\
+             zlVS28Rb-amM5lih-ONXGACC-IUWD0Y$-0JXcnWZ-MQn8VEI-B39!F",
+        )
+        .unwrap();
+        let rgb21 = RgbInvoice::from_str(
+            "rgb:11Fa!$Dk-rUWXhy8-7H35qXm-pLGGLOo-txBWUgj-tbOaSbI/RGB21/1@1+bc:utxob:\
+             zlVS28Rb-amM5lih-ONXGACC-IUWD0Y$-0JXcnWZ-MQn8VEI-B39!F",
+        )
+        .unwrap();
+        let contract_id = rgb20.contract.unwrap();
+        assert!(matches!(
+            compose(&[&rgb20, &rgb21]),
+            Err(StockError::InvalidInput(ComposeError::OperationMismatch(id))) if id == contract_id
+        ));
+    }
+
+    #[test]
+    fn test_contract_balance() {
+        use fixture::*;
+        use strict_encoding::StrictDumb;
+
+        use crate::interface::{
+            AssignIface, ContractError, FilterIncludeAll, IfaceImpl, NamedField, OwnedIface, Req,
+        };
+
+        let fixture = Fixture::new();
+        let contract_id = fixture.contract_id();
+        let mut resolver = TestResolver::default();
+        let mut stock = fixture.stock(fixture.bundle(), &mut resolver);
+
+        let mut iface = Iface::strict_dumb();
+        iface.assignments = tiny_bmap! {
+            fname!("amount") => AssignIface::public(OwnedIface::Amount, Req::OneOrMore),
+            fname!("right") => AssignIface::public(OwnedIface::Rights, Req::Optional),
+        };
+        let mut iimpl = IfaceImpl::strict_dumb();
+        iimpl.schema_id = fixture.schema.schema_id();
+        iimpl.iface_id = iface.iface_id();
+        iimpl.assignments = tiny_bset![
+            NamedField::with(AMOUNT, fname!("amount")),
+            NamedField::with(RIGHT, fname!("right"))
+        ];
+        let stash = stock.stash.as_provider_mut();
+        stash.replace_iface(iface.clone()).unwrap();
+        stash.replace_iimpl(iimpl).unwrap();
+
+        let balance = |name: &'static str| {
+            stock.contract_balance(
+                contract_id,
+                iface.name.clone(),
+                FieldName::from(name),
+                FilterIncludeAll,
+                100,
+                1,
+            )
+        };
+        assert_eq!(balance("amount").unwrap().settled, Amount::from(125u64));
+        assert!(matches!(
+            balance("right"),
+            Err(StockError::InvalidInput(ContractIfaceError::Contract(
+                ContractError::NotFungible(name)
+            ))) if name == fname!("right")
+        ));
+    }
+
+    #[test]
+    fn test_compose_auto() {
+        use bp::Outpoint;
+        use fixture::*;
+        use invoice::{Beneficiary, RgbInvoiceBuilder, XChainNet};
+        use rgb::Occurrences;
+        use strict_encoding::StrictDumb;
+
+        use crate::interface::{
+            AssignIface, FilterIncludeAll, IfaceImpl, NamedField, OwnedIface, Req, TransitionIface,
+        };
+
+        let fixture = Fixture::new();
+        let contract_id = fixture.contract_id();
+        let bundle = fixture.bundle();
+        let mut resolver = TestResolver::default();
+        let mut stock = fixture.stock(bundle.clone(), &mut resolver);
+        let (tx, _) = fixture.witness(bundle.bundle_id(), 0);
+
+        let mut iface = Iface::strict_dumb();
+        iface.assignments = tiny_bmap! {
+            fname!("amount") => AssignIface::public(OwnedIface::Amount, Req::OneOrMore),
+        };
+        iface.transitions = tiny_bmap! {
+            fname!("transfer") => TransitionIface {
+                inputs: tiny_bmap! { fname!("amount") => Occurrences::OnceOrMore },
+                assignments: tiny_bmap! { fname!("amount") => Occurrences::OnceOrMore },
+                default_assignment: Some(fname!("amount")),
+                ..TransitionIface::strict_dumb()
+            },
+        };
+        iface.default_operation = Some(fname!("transfer"));
+        let mut iimpl = IfaceImpl::strict_dumb();
+        iimpl.schema_id = fixture.schema.schema_id();
+        iimpl.iface_id = iface.iface_id();
+        iimpl.assignments = tiny_bset![NamedField::with(AMOUNT, fname!("amount"))];
+        iimpl.transitions = tiny_bset![NamedField::with(TRANSFER, fname!("transfer"))];
+        let stash = stock.stash.as_provider_mut();
+        stash.replace_iface(iface.clone()).unwrap();
+        stash.replace_iimpl(iimpl).unwrap();
+
+        let seal = GraphSeal::new_random_vout(Method::OpretFirst, Vout::from_u32(0));
+        let beneficiary = XChainNet::BitcoinMainnet(Beneficiary::BlindedSeal(seal.conceal()));
+        let compose = |amount: u64| {
+            let invoice = RgbInvoiceBuilder::with(contract_id, beneficiary)
+                .set_interface(iface.name.clone())
+                .set_amount_raw(amount)
+                .finish();
+            stock.compose_auto(
+                &invoice,
+                &FilterIncludeAll,
+                CoinSelection::default(),
+                CloseMethod::OpretFirst,
+                None::<Vout>,
+                |_, _, _| Some(Vout::from_u32(1)),
+            )
+        };
+
+        // Genesis allocations are already spent by the fixture bundle and must
+        // not be selected
+        let (prev_outputs, _) = compose(120).unwrap();
+        assert_eq!(
+            prev_outputs
+                .iter()
+                .map(|seal| XOutpoint::from(*seal))
+                .collect::<BTreeSet<_>>(),
+            bset![
+                XOutpoint::from(XChain::Bitcoin(Outpoint::new(tx.txid(), 1))),
+                XOutpoint::from(XChain::Bitcoin(Outpoint::new(tx.txid(), 2)))
+            ]
+        );
+        assert!(matches!(
+            compose(130),
+            Err(StockError::InvalidInput(ComposeError::InsufficientState))
+        ));
+    }
+
+    #[test]
+    fn test_store_transaction_rollback() {
+        use amplify::confinement::U32;
+        use strict_encoding::StrictSerialize;
+
+        let seal = |vout: u32| {
+            XChain::with(
+                rgbcore::Layer1::Bitcoin,
+                GraphSeal::new_random_vout(Method::OpretFirst, Vout::from_u32(vout)),
+            )
+        };
+        let serialize = |stock: &Stock| {
+            (
+                stock
+                    .stash
+                    .as_provider()
+                    .to_strict_serialized::<U32>()
+                    .unwrap(),
+                stock
+                    .state
+                    .as_provider()
+                    .to_strict_serialized::<U32>()
+                    .unwrap(),
+                stock
+                    .index
+                    .as_provider()
+                    .to_strict_serialized::<U32>()
+                    .unwrap(),
+            )
+        };
+
+        let mut stock = Stock::in_memory();
+        stock.store_secret_seal(seal(0)).unwrap();
+        let before = serialize(&stock);
+
+        let contract_id = ContractId::from([0xAA; 32]);
+        let identity = Identity::from_str("ssi:anonymous").unwrap();
+        let res = stock.store_transaction::<Infallible>(|stash, _, index| {
+            let stash = stash.as_provider_mut();
+            stash.add_secret_seal(seal(1)).unwrap();
+            stash.set_trust(identity, TrustLevel::Trusted).unwrap();
+            index
+                .as_provider_mut()
+                .register_contract(contract_id)
+                .unwrap();
+            Err(StockError::AbsentValidWitness)
+        });
+        assert!(matches!(res, Err(StockError::AbsentValidWitness)));
+        assert_eq!(serialize(&stock), before);
+
+        stock
+            .store_transaction::<Infallible>(|_, _, index| {
+                index
+                    .as_provider_mut()
+                    .register_contract(contract_id)
+                    .unwrap();
+                Ok(())
+            })
+            .unwrap();
+        assert_ne!(serialize(&stock), before);
+        // Rollback of a committed transaction doesn't change anything
+        let after = serialize(&stock);
+        stock.index.rollback_transaction();
+        assert_eq!(serialize(&stock), after);
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_sql_stock() {
+        use std::{env, fs};
+
+        use bp::{Outpoint, Txid};
+        use fixture::*;
+
+        use crate::persistence::sql::{SqlIndex, SqlStash, SqlState, SqlStore};
+
+        let open = |store: &SqlStore| {
+            Stock::with(
+                SqlStash::open(store).unwrap(),
+                SqlState::open(store).unwrap(),
+                SqlIndex::open(store).unwrap(),
+            )
+        };
+        let mut path = env::temp_dir();
+        path.push(format!("rgb-std-sql-stock-{}.sqlite", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let fixture = Fixture::new();
+        let bundle = fixture.bundle();
+        let mut resolver = TestResolver::default();
+        let mut stock = open(&SqlStore::open(&path).unwrap());
+        fixture.import(&mut stock, bundle.clone(), &mut resolver);
+
+        // Failed transactions leave no traces in the database
+        let contract_id = ContractId::from([0xAA; 32]);
+        let res = stock.store_transaction::<Infallible>(|_, _, index| {
+            index
+                .as_provider_mut()
+                .register_contract(contract_id)
+                .unwrap();
+            Err(StockError::AbsentValidWitness)
+        });
+        assert!(matches!(res, Err(StockError::AbsentValidWitness)));
+        let index = SqlIndex::open(&SqlStore::open(&path).unwrap()).unwrap();
+        assert!(index.public_opouts(contract_id).is_err());
+        assert!(stock.index.public_opouts(contract_id).is_err());
+        assert!(index.public_opouts(fixture.contract_id()).is_ok());
+        drop(stock);
+
+        // Reopened stock has the same contract data as the in-memory one
+        let expected = fixture.stock(bundle.clone(), &mut TestResolver::default());
+        let stock = open(&SqlStore::open(&path).unwrap());
+        let (tx, _) = fixture.witness(bundle.bundle_id(), 0);
+        let outpoints = [
+            XChain::Bitcoin(Outpoint::new(Txid::from([0x01; 32]), 0)),
+            XChain::Bitcoin(Outpoint::new(tx.txid(), 1)),
+        ];
+        let assignments = stock
+            .contract_assignments_for(fixture.contract_id(), outpoints)
+            .unwrap();
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(
+            assignments,
+            expected
+                .contract_assignments_for(fixture.contract_id(), outpoints)
+                .unwrap()
+        );
+        assert_eq!(
+            stock.stash.bundle(bundle.bundle_id()).unwrap(),
+            expected.stash.bundle(bundle.bundle_id()).unwrap()
+        );
+        drop(stock);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_store_transaction_persistence() {
+        use std::io;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        use amplify::confinement::U32;
+        use nonasync::persistence::PersistenceProvider;
+        use strict_encoding::StrictSerialize;
+
+        /// Keeps the persisted index, failing to store the stash when requested.
+        #[derive(Clone, Debug, Default)]
+        struct TestStore {
+            fail_stash: Arc<AtomicBool>,
+            index: Arc<Mutex<Vec<u8>>>,
+        }
+        fn absent<T>() -> Result<T, PersistenceError> {
+            Err(PersistenceError::with(io::Error::from(io::ErrorKind::NotFound)))
+        }
+        impl PersistenceProvider<MemStash> for TestStore {
+            fn load(&self) -> Result<MemStash, PersistenceError> { absent() }
+            fn store(&self, _: &MemStash) -> Result<(), PersistenceError> {
+                if self.fail_stash.load(Ordering::SeqCst) {
+                    return Err(PersistenceError::with(io::Error::other("disk is full")));
+                }
+                Ok(())
+            }
+        }
+        impl PersistenceProvider<MemState> for TestStore {
+            fn load(&self) -> Result<MemState, PersistenceError> { absent() }
+            fn store(&self, _: &MemState) -> Result<(), PersistenceError> { Ok(()) }
+        }
+        impl PersistenceProvider<MemIndex> for TestStore {
+            fn load(&self) -> Result<MemIndex, PersistenceError> { absent() }
+            fn store(&self, index: &MemIndex) -> Result<(), PersistenceError> {
+                *self.index.lock().unwrap() =
+                    index.to_strict_serialized::<U32>().unwrap().release();
+                Ok(())
+            }
+        }
+
+        let store = TestStore::default();
+        let mut stock = Stock::in_memory();
+        stock.make_persistent(store.clone(), true).unwrap();
+        let index = store.index.lock().unwrap().clone();
+
+        // The index is persisted before the stash, which fails to be persisted
+        store.fail_stash.store(true, Ordering::SeqCst);
+        let res = stock.store_transaction::<Infallible>(|_, _, index| {
+            index
+                .as_provider_mut()
+                .register_contract(ContractId::from([0xAA; 32]))
+                .unwrap();
+            Ok(())
+        });
+        assert!(matches!(res, Err(StockError::StashWrite(_))));
+        let persisted = store.index.lock().unwrap().clone();
+        assert_eq!(persisted, index);
+        assert_eq!(
+            stock
+                .as_index_provider()
+                .to_strict_serialized::<U32>()
+                .unwrap()
+                .release(),
+            index
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn test_store_transaction_fs_rollback() {
+        use std::sync::{Arc, Mutex};
+        use std::{env, fs, io};
+
+        use amplify::confinement::U32;
+        use rgb::{Genesis, Schema};
+        use strict_encoding::{StrictDumb, StrictSerialize};
+
+        use crate::persistence::fs::FsBinStore;
+
+        /// File-system store failing to store the stash once, after skipping the
+        /// given number of writes.
+        #[derive(Clone, Debug)]
+        struct FailingStore {
+            store: FsBinStore,
+            fail_stash: Arc<Mutex<Option<usize>>>,
+        }
+        impl PersistenceProvider<MemStash> for FailingStore {
+            fn load(&self) -> Result<MemStash, PersistenceError> { self.store.load() }
+            fn store(&self, stash: &MemStash) -> Result<(), PersistenceError> {
+                let mut fail_stash = self.fail_stash.lock().unwrap();
+                match *fail_stash {
+                    Some(0) => {
+                        *fail_stash = None;
+                        return Err(PersistenceError::with(io::Error::other("disk is full")));
+                    }
+                    Some(skip) => *fail_stash = Some(skip - 1),
+                    None => {}
+                }
+                self.store.store(stash)
+            }
+        }
+        impl PersistenceProvider<MemState> for FailingStore {
+            fn load(&self) -> Result<MemState, PersistenceError> { self.store.load() }
+            fn store(&self, state: &MemState) -> Result<(), PersistenceError> {
+                self.store.store(state)
+            }
+        }
+        impl PersistenceProvider<MemIndex> for FailingStore {
+            fn load(&self) -> Result<MemIndex, PersistenceError> { self.store.load() }
+            fn store(&self, index: &MemIndex) -> Result<(), PersistenceError> {
+                self.store.store(index)
+            }
+        }
+
+        let serialize = |stock: &Stock| {
+            (
+                stock
+                    .as_stash_provider()
+                    .to_strict_serialized::<U32>()
+                    .unwrap(),
+                stock
+                    .as_state_provider()
+                    .to_strict_serialized::<U32>()
+                    .unwrap(),
+                stock
+                    .as_index_provider()
+                    .to_strict_serialized::<U32>()
+                    .unwrap(),
+            )
+        };
+
+        let mut dir = env::temp_dir();
+        dir.push(format!("rgb-std-stock-rollback-{}", std::process::id()));
+        let store = FailingStore {
+            store: FsBinStore::new(dir.clone()).unwrap(),
+            fail_stash: default!(),
+        };
+        let mut stock = Stock::in_memory();
+        stock.make_persistent(store.clone(), true).unwrap();
+        let before = serialize(&stock);
+
+        // The index and state are persisted before the stash, which fails to be persisted (after
+        // being written at the beginning of the transaction), while the rollback of the stash
+        // succeeds
+        *store.fail_stash.lock().unwrap() = Some(1);
+        let res = stock.store_transaction::<Infallible>(|stash, state, index| {
+            stash
+                .as_provider_mut()
+                .add_secret_seal(XChain::Bitcoin(GraphSeal::new_random_vout(
+                    Method::OpretFirst,
+                    Vout::from_u32(0),
+                )))
+                .unwrap();
+            state
+                .as_provider_mut()
+                .register_contract(&Schema::strict_dumb(), &Genesis::strict_dumb())
+                .unwrap();
+            index
+                .as_provider_mut()
+                .register_contract(ContractId::from([0xAA; 32]))
+                .unwrap();
+            Ok(())
+        });
+        assert!(matches!(res, Err(StockError::StashWrite(_))));
+        assert_eq!(serialize(&stock), before);
+        drop(stock);
+        drop(store);
+
+        let store = FsBinStore::new(dir.clone()).unwrap();
+        let stock = Stock::load(store.clone(), true).unwrap();
+        assert_eq!(serialize(&stock), before);
+        drop(stock);
+        drop(store);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rebuild() {
+        use amplify::confinement::U32;
+        use strict_encoding::StrictSerialize;
+
+        use crate::interface::resolver::DumbResolver;
+
+        let mut stock = Stock::in_memory();
+        stock
+            .store_secret_seal(XChain::with(
+                rgbcore::Layer1::Bitcoin,
+                GraphSeal::new_random_vout(Method::OpretFirst, Vout::from_u32(0)),
+            ))
+            .unwrap();
+        // Index data not backed by the stash must be dropped
+        stock
+            .index
+            .as_provider_mut()
+            .register_contract(ContractId::from([0xAA; 32]))
+            .unwrap();
+        let stash = stock
+            .stash
+            .as_provider()
+            .to_strict_serialized::<U32>()
+            .unwrap();
+
+        let stock = stock
+            .rebuild_state(MemState::in_memory(), DumbResolver)
+            .unwrap()
+            .rebuild_index(MemIndex::in_memory())
+            .unwrap();
+        assert_eq!(
+            stock
+                .stash
+                .as_provider()
+                .to_strict_serialized::<U32>()
+                .unwrap(),
+            stash
+        );
+        assert_eq!(
+            stock
+                .state
+                .as_provider()
+                .to_strict_serialized::<U32>()
+                .unwrap(),
+            MemState::in_memory().to_strict_serialized::<U32>().unwrap()
+        );
+        assert_eq!(
+            stock
+                .index
+                .as_provider()
+                .to_strict_serialized::<U32>()
+                .unwrap(),
+            MemIndex::in_memory().to_strict_serialized::<U32>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rebuild_archived_witness() {
+        use fixture::*;
+
+        use crate::containers::PubWitness;
+
+        let fixture = Fixture::new();
+        let contract_id = fixture.contract_id();
+        let bundle = fixture.bundle();
+        let mut resolver = TestResolver::default();
+        let mut stock = fixture.stock(bundle.clone(), &mut resolver);
+        let (tx, _) = fixture.witness(bundle.bundle_id(), 0);
+        let mined_id = XWitnessId::Bitcoin(tx.txid());
+
+        // The same bundle is anchored to another witness, which gets archived later
+        let (tx, block) = fixture.witness(bundle.bundle_id(), 1);
+        let mut consignment = fixture.consignment(bundle.clone(), &mut resolver);
+        consignment.bundles = Confined::from_iter_checked([WitnessBundle::with(
+            XChain::Bitcoin(PubWitness::with(tx.clone())),
+            fixture.client_bundle(bundle, &block),
+        )]);
+        let archived_id = resolver.add(tx.clone(), mined(101));
+        let consignment = consignment
+            .validate(&resolver, &DumbValidator, false)
+            .unwrap();
+        stock.import_contract(consignment, &resolver).unwrap();
+        resolver.add(tx, WitnessOrd::Archived);
+
+        let stock = stock
+            .rebuild_state(MemState::in_memory(), &resolver)
+            .unwrap();
+        let state = stock
+            .as_state_provider()
+            .contract_state(contract_id)
+            .unwrap();
+        assert_eq!(state.witness_ord(mined_id), Some(mined(100)));
+        assert_eq!(state.witness_ord(archived_id), None);
+        let fungibles = state
+            .fungible_all()
+            .filter(|a| a.witness.is_some())
+            .map(|a| (a.opout, a.witness))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(fungibles, bset![
+            (Opout::new(fixture.t1.id(), AMOUNT, 0), Some(mined_id)),
+            (Opout::new(fixture.t1.id(), AMOUNT, 1), Some(mined_id)),
+            (Opout::new(fixture.t2.id(), AMOUNT, 0), Some(mined_id)),
+        ]);
+    }
+
+    #[test]
+    fn test_check() {
+        use rgb::InputMap;
+        use strict_encoding::StrictDumb;
+
+        let mut stock = Stock::in_memory();
+        assert_eq!(stock.check().unwrap(), vec![]);
+
+        let transition = Transition::strict_dumb();
+        let opid = transition.id();
+        let contract_id = transition.contract_id;
+        let bundle = TransitionBundle {
+            close_method: CloseMethod::OpretFirst,
+            input_map: InputMap::strict_dumb(),
+            known_transitions: Confined::with_key_value(opid, transition),
+        };
+        let bundle_id = bundle.bundle_id();
+        stock
+            .stash
+            .as_provider_mut()
+            .replace_bundle(bundle)
+            .unwrap();
+
+        let issues = stock.check().unwrap();
+        assert_eq!(issues, vec![
+            StashInconsistency::BundleMissedInAnchors(bundle_id, contract_id).into(),
+            IndexInconsistency::BundleWitnessUnknown(bundle_id).into(),
+            IndexInconsistency::BundleAbsent(opid).into(),
+        ]);
+        assert_eq!(
+            issues
+                .iter()
+                .filter_map(StockIssue::repair_action)
+                .collect::<BTreeSet<_>>(),
+            bset![RepairAction::RebuildIndex]
+        );
+    }
+
+    #[test]
+    fn test_portfolio() {
+        use bp::Txid;
+        use rgb::{AssetTag, Genesis, GenesisSeal, RevealedValue, Schema};
+        use strict_encoding::StrictDumb;
+
+        use crate::interface::{AllocatedState, FilterIncludeAll, IfaceImpl, NamedField};
+
+        let seal = |vout: u32| {
+            XChain::with(
+                rgbcore::Layer1::Bitcoin,
+                GenesisSeal::opret_first_rand(Txid::from([0x01; 32]), vout),
+            )
+        };
+        let value = |value: u64| RevealedValue::new_random_blinding(value, AssetTag::strict_dumb());
+        let (amount, other) = (AssignmentType::with(0), AssignmentType::with(1));
+
+        let schema = Schema::strict_dumb();
+        let mut genesis = Genesis::strict_dumb();
+        genesis.schema_id = schema.schema_id();
+        genesis.assignments = Assignments::from_inner(tiny_bmap! {
+            amount => TypedAssigns::Fungible(small_vec![
+                Assign::revealed(seal(0), value(100)),
+                Assign::revealed(seal(1), value(5)),
+            ]),
+            other => TypedAssigns::Fungible(small_vec![Assign::revealed(seal(0), value(7))]),
+        });
+        let contract_id = genesis.contract_id();
+        let iface = Iface::strict_dumb();
+        let mut iimpl = IfaceImpl::strict_dumb();
+        iimpl.schema_id = schema.schema_id();
+        iimpl.iface_id = iface.iface_id();
+        iimpl.assignments = tiny_bset![NamedField::with(amount, fname!("amount"))];
+
+        let mut stock = Stock::in_memory();
+        let stash = stock.stash.as_provider_mut();
+        stash.replace_schema(schema.clone()).unwrap();
+        stash.replace_iface(iface.clone()).unwrap();
+        stash.replace_iimpl(iimpl).unwrap();
+        stash.replace_genesis(genesis.clone()).unwrap();
+        stock
+            .state
+            .as_provider_mut()
+            .register_contract(&schema, &genesis)
+            .unwrap();
+
+        let outpoint = XOutpoint::from(seal(0).to_output_seal().unwrap());
+        let portfolio = stock.portfolio([outpoint]).unwrap();
+        assert_eq!(portfolio.len(), 1);
+        let holdings = &portfolio[&contract_id];
+        assert_eq!(holdings.info.id, contract_id);
+        assert_eq!(holdings.iface, Some(iface.name));
+        assert_eq!(holdings.allocations.len(), 1);
+        let allocations = &holdings.allocations[&fname!("amount")];
+        assert_eq!(allocations.len(), 1);
+        assert_eq!(allocations[0].state, AllocatedState::Amount(Amount::from(100u64)));
+        assert_eq!(holdings.unnamed.len(), 1);
+        assert_eq!(holdings.unnamed[0].opout.ty, other);
+
+        let portfolio = stock.portfolio(&FilterIncludeAll).unwrap();
+        assert_eq!(portfolio[&contract_id].allocations[&fname!("amount")].len(), 2);
+
+        let unknown = XOutpoint::from(seal(2).to_output_seal().unwrap());
+        assert!(stock.portfolio([unknown]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_portfolio_spent() {
+        use bp::{Outpoint, Txid};
+        use fixture::*;
+
+        let fixture = Fixture::new();
+        let bundle = fixture.bundle();
+        let mut resolver = TestResolver::default();
+        let stock = fixture.stock(bundle.clone(), &mut resolver);
+        let (tx, _) = fixture.witness(bundle.bundle_id(), 0);
+        let outpoint =
+            |txid: Txid, vout: u32| XOutpoint::from(XChain::Bitcoin(Outpoint::new(txid, vout)));
+
+        // Genesis outputs are spent by the bundle
+        let genesis_outputs =
+            [outpoint(Txid::from([0x01; 32]), 0), outpoint(Txid::from([0x01; 32]), 1)];
+        assert!(stock.portfolio(genesis_outputs).unwrap().is_empty());
+
+        let portfolio = stock.portfolio([outpoint(tx.txid(), 2)]).unwrap();
+        let holdings = &portfolio[&fixture.contract_id()];
+        assert!(holdings.allocations.is_empty());
+        assert_eq!(
+            holdings
+                .unnamed
+                .iter()
+                .map(|a| a.opout)
+                .collect::<BTreeSet<_>>(),
+            bset![Opout::new(fixture.t1.id(), AMOUNT, 1), Opout::new(fixture.t2.id(), AMOUNT, 0)]
+        );
+    }
+
+    #[test]
+    fn test_contract_iface_allocations_spent() {
+        use bp::{Outpoint, Txid};
+        use fixture::*;
+        use strict_encoding::StrictDumb;
+
+        use crate::interface::{
+            AssignIface, FilterIncludeAll, IfaceImpl, NamedField, OwnedIface, Req,
+        };
+
+        let fixture = Fixture::new();
+        let bundle = fixture.bundle();
+        let mut resolver = TestResolver::default();
+        let mut stock = fixture.stock(bundle.clone(), &mut resolver);
+        let (tx, _) = fixture.witness(bundle.bundle_id(), 0);
+        let outpoint =
+            |txid: Txid, vout: u32| XOutpoint::from(XChain::Bitcoin(Outpoint::new(txid, vout)));
+
+        let mut iface = Iface::strict_dumb();
+        iface.assignments = tiny_bmap! {
+            fname!("amount") => AssignIface::public(OwnedIface::Amount, Req::OneOrMore),
+        };
+        let mut iimpl = IfaceImpl::strict_dumb();
+        iimpl.schema_id = fixture.schema.schema_id();
+        iimpl.iface_id = iface.iface_id();
+        iimpl.assignments = tiny_bset![NamedField::with(AMOUNT, fname!("amount"))];
+        let stash = stock.stash.as_provider_mut();
+        stash.replace_iface(iface.clone()).unwrap();
+        stash.replace_iimpl(iimpl).unwrap();
+
+        let contract = stock
+            .contract_iface(fixture.contract_id(), iface.name.clone())
+            .unwrap();
+
+        // Genesis outputs are spent by the bundle
+        let genesis_output = outpoint(Txid::from([0x01; 32]), 0);
+        assert_eq!(contract.allocations(genesis_output).count(), 0);
+        assert_eq!(contract.outpoint_allocations(genesis_output).count(), 0);
+
+        let allocations = contract
+            .allocations(FilterIncludeAll)
+            .map(|a| a.opout)
+            .collect::<BTreeSet<_>>();
+        assert!(allocations
+            .iter()
+            .all(|opout| opout.op != fixture.genesis.id()));
+        assert_eq!(
+            contract
+                .allocations(outpoint(tx.txid(), 2))
+                .map(|a| a.opout)
+                .collect::<BTreeSet<_>>(),
+            bset![Opout::new(fixture.t1.id(), AMOUNT, 1), Opout::new(fixture.t2.id(), AMOUNT, 0)]
+        );
+    }
 }
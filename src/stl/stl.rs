@@ -33,7 +33,7 @@ use super::{
     AssetSpec, BurnMeta, ContractSpec, ContractTerms, Error, IssueMeta, MediaType,
     LIB_NAME_RGB_CONTRACT, LIB_NAME_RGB_STORAGE,
 };
-use crate::containers::{Contract, Kit, Transfer};
+use crate::containers::{Contract, Disclosure, Kit, Transfer};
 use crate::persistence::{MemIndex, MemStash, MemState};
 use crate::stl::ProofOfReserves;
 use crate::LIB_NAME_RGB_STD;
@@ -41,7 +41,12 @@ use crate::LIB_NAME_RGB_STD;
 /// Strict types id for the library providing standard data types which may be
 /// used in RGB smart contracts.
 pub const LIB_ID_RGB_STORAGE: &str =
-    "stl:mG$H7b6I-$T8qp18-07PSNeA-rbEBNS5-$J5X4y0-1vPxRWg#channel-vortex-bandit";
+    "stl:aHx0Xn5E-NyskImB-jcoRhHv-Er0rWCJ-kj7eGEy-K2CtAW4#bandit-bonjour-bingo";
+
+/// Version of the layout of the persisted stock data, which is defined by the
+/// library with [`LIB_ID_RGB_STORAGE`] id. Must be increased with each change
+/// of the library id.
+pub const STORAGE_VERSION: u16 = 1;
 
 /// Strict types id for the library providing standard data types which may be
 /// used in RGB smart contracts.
@@ -50,7 +55,7 @@ pub const LIB_ID_RGB_CONTRACT: &str =
 
 /// Strict types id for the library representing of RGB StdLib data types.
 pub const LIB_ID_RGB_STD: &str =
-    "stl:JhUC5JgH-Kwps4cO-ZNUklUj-UP6boFp-OY!18Kx-xOSJaVQ#hair-magnum-helena";
+    "stl:6!YPBYnT-z88qt4M-zP53d5!-FVlJT0B-EAW8hKQ-q3LuePs#cliff-nice-fragile";
 
 fn _rgb_std_stl() -> Result<TypeLib, CompileError> {
     LibBuilder::new(libname!(LIB_NAME_RGB_STD), tiny_bset! {
@@ -66,6 +71,7 @@ fn _rgb_std_stl() -> Result<TypeLib, CompileError> {
     .transpile::<Transfer>()
     .transpile::<Contract>()
     .transpile::<Kit>()
+    .transpile::<Disclosure>()
     .compile()
 }
 